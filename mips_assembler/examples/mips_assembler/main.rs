@@ -0,0 +1,162 @@
+use std::{collections::HashMap, env, fs, path::Path, process::ExitCode};
+
+use mips_emulator::{map::{self, Preset}, image::{Format, Image}};
+use mips_assembler::{Bases, Error, Files, FileSystem, Program, object::Object};
+
+const USAGE: &str = "\
+assembles a program, and optionally dumps each section like MARS does.
+
+usage: mips_assembler <source.asm> [output] [options]
+       mips_assembler --link <object.o>... [--out output] [options]
+  output            writes output.text.bin, output.data.bin, and so on
+  --format <name>   what to write them as: Binary (the default), HexText,
+                    BinaryText, IntelHex or SRecord
+  --object          writes an object file to output (or source.o) instead
+  --listing         prints each line with its address and what it turned into,
+                    and every label with where it's defined and used
+  --link            links object files together into a program
+  --out <output>    the same as output, for --link
+  --preset <name>   memory layout, by its MARS name (default CompactTextAtZero)";
+
+fn fail(msg: &str) -> ! {
+	eprintln!("{msg}");
+	std::process::exit(1);
+}
+
+/// Dumps every section that has anything in it, like MARS does.
+fn dump(program: &Program, out: &str, format: Format) -> std::io::Result<()> {
+	for name in [map::TEXT, map::DATA, map::KTEXT, map::KDATA] {
+		if let Some((addr, bytes)) = program.image(name) {
+			let mut image = Image::default();
+			image.push(addr, &bytes);
+			fs::write(format!("{out}{name}.{}", format.extension()), format.write(&image).map_err(std::io::Error::other)?)?;
+		}
+	}
+	Ok(())
+}
+
+fn link(names: &[String], out: Option<&String>, bases: Bases, format: Format) -> std::io::Result<ExitCode> {
+	let mut objects = Vec::new();
+	for name in names {
+		let bytes = fs::read(name).unwrap_or_else(|e| fail(&format!("couldn't read {name}: {e}")));
+		let object = Object::from_elf(&bytes).unwrap_or_else(|e| fail(&format!("{name}: {e}")));
+		objects.push((name.clone(), object));
+	}
+	
+	match mips_assembler::link(&objects, bases) {
+		Ok(program) => {
+			for (name, addr) in &program.labels {
+				println!("{addr:#010X} {name}");
+			}
+			if let Some(out) = out {
+				dump(&program, out, format)?;
+			}
+			Ok(ExitCode::SUCCESS)
+		},
+		Err(errors) => {
+			for e in errors {
+				eprintln!("{e}");
+			}
+			Ok(ExitCode::FAILURE)
+		},
+	}
+}
+
+fn main() -> std::io::Result<ExitCode> {
+	let mut args = env::args().skip(1);
+	let mut positional = Vec::new();
+	let mut preset = Preset::CompactTextAtZero;
+	let mut format = Format::Binary;
+	let (mut object, mut linking, mut listing, mut out) = (false, false, false, None);
+	
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--object" => object = true,
+			"--link" => linking = true,
+			"--listing" => listing = true,
+			"--out" => out = Some(args.next().unwrap_or_else(|| fail("--out needs a value"))),
+			"--preset" => {
+				let name = args.next().unwrap_or_else(|| fail("--preset needs a value"));
+				preset = Preset::ALL.into_iter()
+					.find(|p| p.mars_name().eq_ignore_ascii_case(&name))
+					.unwrap_or_else(|| fail(&format!("there's no preset called {name}")));
+			},
+			"--format" => {
+				let name = args.next().unwrap_or_else(|| fail("--format needs a value"));
+				format = Format::from_name(&name).unwrap_or_else(|| fail(&format!("there's no format called {name}")));
+			},
+			_ if arg.starts_with('-') => fail(&format!("don't know what {arg} is")),
+			_ => positional.push(arg),
+		}
+	}
+	
+	let Some(filename) = positional.first() else {
+		println!("{USAGE}");
+		return Ok(ExitCode::SUCCESS);
+	};
+	let bases = Bases::from(&preset.map());
+	if linking {
+		return link(&positional, out.as_ref(), bases, format);
+	}
+	let out = out.or_else(|| positional.get(1).cloned());
+	
+	// anything it includes is next to it
+	let path = Path::new(filename);
+	let files = FileSystem { root: path.parent().unwrap_or(Path::new("")).to_owned() };
+	let main = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+	let lines = |name: &str| -> Vec<String> {
+		files.read(name).map(|s| s.lines().map(String::from).collect()).unwrap_or_default()
+	};
+	
+	let result = if object {
+		mips_assembler::assemble_object(&files, &main).map(|o| {
+			let out = out.unwrap_or_else(|| path.with_extension("o").to_string_lossy().into_owned());
+			fs::write(out, o.to_elf())
+		})
+	} else {
+		mips_assembler::assemble_files(&files, &main, bases).map(|program| {
+			if listing {
+				print!("{}", mips_assembler::listing(&program, &files));
+			}
+			out.map_or(Ok(()), |out| dump(&program, &out, format))
+		})
+	};
+	
+	match result {
+		Ok(written) => {
+			written?;
+			Ok(ExitCode::SUCCESS)
+		},
+		Err(errors) => {
+			let mut sources = HashMap::new();
+			for e in errors.iter().chain(errors.iter().flat_map(|e| &e.notes)) {
+				sources.entry(&e.file).or_insert_with(|| lines(&e.file));
+			}
+			
+			// where it is, and then the line with arrows under the part that's
+			// wrong. errors that aren't anywhere (like the file not being
+			// there) are just their message.
+			let show = |e: &Error, what: &str| {
+				let Some(line) = sources[&e.file].get(e.span.line.wrapping_sub(1)) else {
+					eprintln!("{what}{}", e.message);
+					return;
+				};
+				let path = files.root.join(&e.file);
+				eprintln!("{}:{}:{}: {what}{}", path.display(), e.span.line, e.span.column, e.message);
+				// keep the tabs so the arrows line up
+				let pad: String = line.chars().take(e.span.column - 1)
+					.map(|c| if c == '\t' { c } else { ' ' })
+					.collect();
+				eprintln!("    {line}");
+				eprintln!("    {pad}{}", "^".repeat(e.span.len.max(1)));
+			};
+			for e in &errors {
+				show(e, "");
+				for note in &e.notes {
+					show(note, "note: ");
+				}
+			}
+			Ok(ExitCode::FAILURE)
+		},
+	}
+}
@@ -0,0 +1,889 @@
+use std::{collections::{BTreeMap, BTreeSet}, sync::Arc};
+
+use mips_emulator::{map::{self, MemoryMap}, mem::Memory, elf::{self, Symbols}};
+
+use crate::{
+	word, Span, Error,
+	files::{Files, tokenize_all},
+	macros::expand,
+	parser::{parse, Statement, Operand, OperandKind},
+	expr::{Expr, BinOp},
+	encode::{encode, value, value_field, fits, Field, Range},
+	pseudo::{plan, usage},
+	object::{Object, ObjectSection, Symbol, Relocation, Reloc, BSS},
+};
+
+/// Where each section starts, unless the source moves it with something like
+/// `.data 0x1001_0100`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bases {
+	pub text: word,
+	pub data: word,
+	pub ktext: word,
+	pub kdata: word,
+}
+
+impl Default for Bases {
+	/// Same as MARS' default memory configuration.
+	fn default() -> Self {
+		Bases { text: 0x0040_0000, data: 0x1001_0000, ktext: 0x8000_0000, kdata: 0x9000_0000 }
+	}
+}
+
+impl From<&MemoryMap> for Bases {
+	/// Wherever the map's segments start. Ones the map doesn't have stay
+	/// where MARS would put them.
+	fn from(map: &MemoryMap) -> Self {
+		let default = Bases::default();
+		Bases {
+			text: map.base(map::TEXT).unwrap_or(default.text),
+			data: map.base(map::DATA).unwrap_or(default.data),
+			ktext: map.base(map::KTEXT).unwrap_or(default.ktext),
+			kdata: map.base(map::KDATA).unwrap_or(default.kdata),
+		}
+	}
+}
+
+impl Bases {
+	pub(crate) fn of(&self, section: &str) -> word {
+		match section {
+			map::TEXT => self.text,
+			map::DATA => self.data,
+			map::KTEXT => self.ktext,
+			_ => self.kdata,
+		}
+	}
+}
+
+/// One run of bytes in one section. A section that gets moved partway
+/// through (or goes back to where it was) turns into more than one of these.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Section {
+	/// `.text`, `.data`, `.ktext`, or `.kdata`, the same as the names in
+	/// `mips_emulator::map`.
+	pub name: &'static str,
+	pub base: word,
+	pub bytes: Vec<u8>,
+	
+	/// Where each statement's bytes start, how many there are (not counting
+	/// any padding after them), and the statement.
+	pub spans: Vec<(word, word, Span)>,
+}
+
+impl Section {
+	pub fn end(&self) -> word {
+		self.base.wrapping_add(self.bytes.len() as word)
+	}
+	
+	/// The bytes as words, like they'd be read as instructions.
+	pub fn words(&self) -> impl Iterator<Item = word> + '_ {
+		self.bytes.chunks(4).map(|c| {
+			let mut w = [0; 4];
+			w[..c.len()].copy_from_slice(c);
+			word::from_le_bytes(w)
+		})
+	}
+}
+
+/// Assembled code and data, with everything where it's supposed to be.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+	pub sections: Vec<Section>,
+	pub labels: BTreeMap<String, word>,
+	
+	/// Labels that were `.globl`'d.
+	pub globals: BTreeSet<String>,
+	
+	/// Where each label was defined, and everywhere it was used, for
+	/// cross-referencing. Linked programs don't have these, since objects
+	/// don't keep their source.
+	pub definitions: BTreeMap<String, Span>,
+	pub uses: BTreeMap<String, Vec<Span>>,
+	
+	/// Every file that went into it, the first being the one that was
+	/// assembled. Spans' `file`s index into this.
+	pub files: Vec<String>,
+}
+
+impl Program {
+	/// Everything in the section called `name`, as one run of bytes from the
+	/// lowest address to the highest, with any gaps zeroed. Handy for dumping
+	/// to a file like MARS does.
+	pub fn image(&self, name: &str) -> Option<(word, Vec<u8>)> {
+		let parts = || self.sections.iter().filter(move |s| s.name == name && !s.bytes.is_empty());
+		let start = parts().map(|s| s.base).min()?;
+		// a section can go right up to the end of memory
+		let end = parts().map(|s| s.base as u64 + s.bytes.len() as u64).max()?;
+		
+		let mut bytes = vec![0; (end - start as u64) as usize];
+		for s in parts() {
+			let at = (s.base - start) as usize;
+			bytes[at..at + s.bytes.len()].copy_from_slice(&s.bytes);
+		}
+		Some((start, bytes))
+	}
+	
+	/// Copies every section into memory, and the labels into `mem.symbols`
+	/// for the debugger.
+	pub fn load(&self, mem: &mut Memory) {
+		for s in &self.sections {
+			mem.set_slice(s.base, &s.bytes);
+		}
+		let symbols = self.labels.iter()
+			.map(|(name, &addr)| elf::Symbol { name: name.clone(), addr, size: 0, function: false })
+			.collect();
+		mem.symbols = Arc::new(Symbols::new(symbols));
+	}
+}
+
+/// The most `.space` can reserve at once, since it all has to be zeroes in
+/// memory somewhere.
+pub(crate) const MAX_SPACE: word = 0x0100_0000;
+
+/// How big a data directive's going to be, and what it aligns to. `None`
+/// if it isn't one.
+fn data_size(name: &str, args: &[Operand]) -> Option<Result<(word, word), Error>> {
+	let each = |size: word| Ok((size, size * args.len() as word));
+	Some(match name {
+		".word" | ".float" => each(4),
+		".half" => each(2),
+		".byte" => each(1),
+		".double" => each(8),
+		".ascii" | ".asciiz" => {
+			let nul = (name == ".asciiz") as word;
+			args.iter().map(|a| match &a.kind {
+				OperandKind::Str(s) => Ok(s.len() as word + nul),
+				_ => Err(Error::new(a.span, "expected a string")),
+			}).sum::<Result<word, _>>().map(|size| (1, size))
+		},
+		".space" => match args {
+			[Operand { kind: OperandKind::Int(n), .. }] if *n > MAX_SPACE =>
+				Err(Error::new(args[0].span, format!("`.space` can only reserve up to {MAX_SPACE:#X} bytes at a time"))),
+			[Operand { kind: OperandKind::Int(n), .. }] => Ok((1, *n)),
+			_ => Err(Error::new(args.first().map_or_else(Span::default, |a| a.span), "expected `.space bytes`")),
+		},
+		_ => return None,
+	})
+}
+
+/// Puts a data directive's values into `out`.
+fn emit_data(name: &str, args: &[Operand], labels: &BTreeMap<String, word>, out: &mut [u8]) -> Result<(), Error> {
+	let mut at = 0;
+	let mut put = |bytes: &[u8]| {
+		out[at..at + bytes.len()].copy_from_slice(bytes);
+		at += bytes.len();
+	};
+	
+	let float = |a: &Operand| match a.kind {
+		OperandKind::Float(f) => Ok(f),
+		OperandKind::Int(n) => Ok(n as i32 as f64),
+		_ => Err(Error::new(a.span, "expected a number")),
+	};
+	
+	for a in args {
+		match name {
+			".word" => put(&value(a, labels)?.to_le_bytes()),
+			".half" => put(&(fits(a, value(a, labels)?, 16, Range::Either, "a half")? as u16).to_le_bytes()),
+			".byte" => put(&[fits(a, value(a, labels)?, 8, Range::Either, "a byte")? as u8]),
+			".float" => put(&(float(a)? as f32).to_le_bytes()),
+			".double" => put(&float(a)?.to_le_bytes()),
+			".ascii" | ".asciiz" => if let OperandKind::Str(s) = &a.kind {
+				put(s.as_bytes());
+				if name == ".asciiz" { put(&[0]); }
+			},
+			_ => (),
+		}
+	}
+	Ok(())
+}
+
+/// What a relocation in an object file is relative to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Target {
+	/// The start of one of its own sections.
+	Section(usize),
+	/// A label from some other file.
+	Extern(String),
+}
+
+/// What an object file needs on top of what's in a [`Program`].
+#[derive(Clone, Debug, Default)]
+struct Relocatable {
+	/// Which section each label's in.
+	homes: BTreeMap<String, usize>,
+	/// The biggest alignment anything in each section wanted.
+	aligns: BTreeMap<usize, word>,
+	/// Labels that are used but not defined, so they have to be somewhere
+	/// else.
+	externs: BTreeSet<String>,
+	relocations: Vec<(usize, word, Reloc, Target)>,
+}
+
+/// The label `e` is relative to, if it's one the linker can fix up: a label,
+/// plus or minus a number. `Some(None)` is a plain number, since the
+/// distance between two labels in the same section is one too.
+fn base<'a>(e: &'a Expr, homes: &BTreeMap<String, usize>) -> Option<Option<&'a str>> {
+	match e {
+		_ if !e.has_labels() => Some(None),
+		Expr::Label(l, _) => Some(Some(l)),
+		Expr::Binary(BinOp::Add, a, b, _) => match (base(a, homes)?, base(b, homes)?) {
+			(Some(_), Some(_)) => None,
+			(a, b) => Some(a.or(b)),
+		},
+		Expr::Binary(BinOp::Sub, a, b, _) => match (base(a, homes)?, base(b, homes)?) {
+			(a, None) => Some(a),
+			(Some(a), Some(b)) if homes.contains_key(a) && homes.get(a) == homes.get(b) => Some(None),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// What the linker has to do about `op`, which ends up in `field` of
+/// something in `section`. Local labels are relative to their section,
+/// since that's all that moves.
+fn relocation(op: &Operand, field: Field, section: usize, homes: &BTreeMap<String, usize>) -> Result<Option<(Reloc, Target)>, Error> {
+	let cant = || Error::new(op.span, "this can't be worked out until it's linked");
+	let (label, half) = match &op.kind {
+		OperandKind::Label(l) => (Some(l.as_str()), None),
+		OperandKind::Expr(Expr::Hi(e)) => (base(e, homes).ok_or_else(cant)?, Some(Reloc::Hi)),
+		OperandKind::Expr(Expr::Lo(e)) => (base(e, homes).ok_or_else(cant)?, Some(Reloc::Lo)),
+		OperandKind::Expr(e) => (base(e, homes).ok_or_else(cant)?, None),
+		_ => (None, None),
+	};
+	let Some(label) = label else { return Ok(None) };
+	
+	let home = homes.get(label).copied();
+	let target = home.map_or_else(|| Target::Extern(label.to_owned()), Target::Section);
+	Ok(Some(match (field, half) {
+		// branches are relative, so they're fine as long as both ends move
+		// together
+		(Field::Branch, None) if home == Some(section) => return Ok(None),
+		(Field::Branch, _) => return Err(Error::new(op.span, format!(
+			"`{label}` is in another section or file, so it can't be branched to until it's linked",
+		))),
+		(Field::Jump, None) => (Reloc::Jump, target),
+		(Field::Word, None) => (Reloc::Word, target),
+		(Field::Half, Some(half)) => (half, target),
+		(Field::Half, None) => return Err(Error::new(op.span, format!(
+			"`{label}` isn't known until it's linked, so use `%hi({label})` or `%lo({label})`",
+		))),
+		_ => return Err(cant()),
+	}))
+}
+
+/// Assembles `source`, with sections starting at `bases`. Every error in the
+/// source gets reported, in the order they show up, not just the first.
+/// 
+/// There's nothing to `.include` from, so use [`assemble_files`] for that.
+pub fn assemble(source: &str, bases: Bases) -> Result<Program, Vec<Error>> {
+	assemble_files(&BTreeMap::from([(String::new(), source.to_owned())]), "", bases)
+}
+
+/// Like [`assemble`], but for the file called `main` in `files`, which is
+/// also where anything it `.include`s comes from.
+pub fn assemble_files(files: &dyn Files, main: &str, bases: Bases) -> Result<Program, Vec<Error>> {
+	assemble_with(files, main, bases, false).map(|(program, _)| program)
+}
+
+/// Assembles the file called `main` in `files` into an object file, to be
+/// [linked](crate::link) with others. Labels it uses that it doesn't define
+/// are left for the linker to find in another file, and only labels that
+/// are `.globl`'d can be found from another file.
+/// 
+/// Sections can't be moved, since they go wherever the linker puts them,
+/// and there's a `.bss` section for `.space` that doesn't need to be in the
+/// file.
+pub fn assemble_object(files: &dyn Files, main: &str) -> Result<Object, Vec<Error>> {
+	let (program, r) = assemble_with(files, main, Bases { text: 0, data: 0, ktext: 0, kdata: 0 }, true)?;
+	let mut object = Object::default();
+	
+	// a symbol for each section first, so they're numbered the same
+	for (c, s) in program.sections.iter().enumerate() {
+		object.sections.push(ObjectSection {
+			name: s.name.to_owned(),
+			align: r.aligns.get(&c).copied().unwrap_or(1),
+			bytes: s.bytes.clone(),
+			relocations: vec![],
+		});
+		object.symbols.push(Symbol { name: s.name.to_owned(), section: Some(c), value: 0, global: false });
+	}
+	for (name, &value) in &program.labels {
+		let global = program.globals.contains(name);
+		object.symbols.push(Symbol { name: name.clone(), section: r.homes.get(name).copied(), value, global });
+	}
+	let mut externs = BTreeMap::new();
+	for name in r.externs {
+		externs.insert(name.clone(), object.symbols.len());
+		object.symbols.push(Symbol { name, section: None, value: 0, global: true });
+	}
+	
+	for (c, offset, kind, target) in r.relocations {
+		let symbol = match target {
+			Target::Section(s) => s,
+			Target::Extern(name) => externs[&name],
+		};
+		object.sections[c].relocations.push(Relocation { offset, kind, symbol });
+	}
+	Ok(object)
+}
+
+/// Does the assembling for both of those. An `object` file's sections all
+/// start at 0, and it keeps track of what the linker's going to need.
+fn assemble_with(files: &dyn Files, main: &str, bases: Bases, object: bool) -> Result<(Program, Relocatable), Vec<Error>> {
+	let (tokens, names, mut errors) = tokenize_all(files, main);
+	let (tokens, macro_errors) = expand(&tokens);
+	errors.extend(macro_errors);
+	let (lines, parse_errors) = parse(&tokens);
+	errors.extend(parse_errors);
+	
+	// first pass: lay everything out, so the labels are all known. every
+	// line that ends up with bytes gets which section they're in, where, and
+	// how many.
+	let mut program = Program::default();
+	let mut r = Relocatable::default();
+	let mut placed: Vec<Option<(usize, word, word)>> = vec![None; lines.len()];
+	
+	let mut current: Option<usize> = None;
+	let mut auto_align = true;
+	let mut pending: Vec<(String, Span)> = vec![];
+	
+	// labels without a statement yet stick to whatever comes after them,
+	// once that's been aligned
+	let bind = |pending: &mut Vec<(String, Span)>, program: &mut Program, r: &mut Relocatable, errors: &mut Vec<Error>, c: usize, addr: word| {
+		for (name, span) in pending.drain(..) {
+			if program.labels.insert(name.clone(), addr).is_some() {
+				errors.push(Error::new(span, format!("label `{name}` is already defined")));
+			} else {
+				program.definitions.insert(name.clone(), span);
+			}
+			r.homes.insert(name, c);
+		}
+	};
+	
+	for (i, line) in lines.iter().enumerate() {
+		pending.extend(line.labels.iter().cloned());
+		
+		// what this line needs: an alignment and a size
+		let Some(statement) = &line.statement else { continue };
+		let (align, size) = match statement {
+			Statement::Instruction { mnemonic, operands, span } => {
+				let section = current.map_or(map::TEXT, |c| program.sections[c].name);
+				if section != map::TEXT && section != map::KTEXT {
+					errors.push(Error::new(*span, "instructions have to go in `.text` or `.ktext`"));
+					continue;
+				}
+				// anything that doesn't plan out is an error, which pass two
+				// will find
+				(4, 4 * plan(mnemonic, operands).map_or(1, |p| p.size(operands)))
+			},
+			Statement::Directive { name, args, span } => match name.as_str() {
+				".text" | ".data" | ".ktext" | ".kdata" | ".bss" => {
+					let section = match name.as_str() {
+						".text" => map::TEXT, ".data" => map::DATA,
+						".ktext" => map::KTEXT, ".kdata" => map::KDATA, _ => BSS,
+					};
+					let addr = match args[..] {
+						[] => None,
+						[Operand { kind: OperandKind::Int(n), .. }] => Some(n),
+						_ => {
+							errors.push(Error::new(*span, format!("expected `{name}` or `{name} address`")));
+							continue;
+						},
+					};
+					if object && addr.is_some() {
+						errors.push(Error::new(*span, "sections can't be moved in an object file, since the linker decides where they go"));
+						continue;
+					}
+					if !object && section == BSS {
+						errors.push(Error::new(*span, "`.bss` only works when assembling an object file"));
+						continue;
+					}
+					
+					if let Some(c) = current {
+						let end = program.sections[c].end();
+						bind(&mut pending, &mut program, &mut r, &mut errors, c, end);
+					}
+					
+					// pick up where it left off, unless it's being moved
+					let resume = program.sections.iter().rposition(|s| s.name == section);
+					current = Some(match (resume, addr) {
+						(Some(r), None) => r,
+						_ => {
+							let base = addr.unwrap_or_else(|| bases.of(section));
+							program.sections.push(Section { name: section, base, ..Default::default() });
+							program.sections.len() - 1
+						},
+					});
+					auto_align = true;
+					continue;
+				},
+				".globl" => {
+					for a in args {
+						match &a.kind {
+							OperandKind::Label(l) => { program.globals.insert(l.clone()); },
+							_ => errors.push(Error::new(a.span, "expected a label")),
+						}
+					}
+					continue;
+				},
+				".eqv" => continue,
+				".align" => match args[..] {
+					[Operand { kind: OperandKind::Int(n @ 0..=3), .. }] => {
+						// like MARS, `.align 0` turns off the automatic alignment
+						// until the next section directive
+						auto_align &= n != 0;
+						(1 << n, 0)
+					},
+					_ => {
+						errors.push(Error::new(*span, "expected `.align` 0, 1, 2, or 3"));
+						continue;
+					},
+				},
+				_ => match data_size(name, args) {
+					Some(Ok(_)) if name != ".space" && current.is_some_and(|c| program.sections[c].name == BSS) => {
+						errors.push(Error::new(*span, "only `.space` can go in `.bss`"));
+						continue;
+					},
+					Some(Ok((align, size))) => (if auto_align { align } else { 1 }, size),
+					Some(Err(e)) => {
+						errors.push(e);
+						continue;
+					},
+					None => {
+						errors.push(Error::new(*span, format!("unknown directive `{name}`")));
+						continue;
+					},
+				},
+			},
+		};
+		
+		// anything before the first section directive goes in .text
+		let c = *current.get_or_insert_with(|| {
+			program.sections.push(Section { name: map::TEXT, base: bases.text, ..Default::default() });
+			program.sections.len() - 1
+		});
+		let section = &mut program.sections[c];
+		
+		// all of it has to fit below 4 GiB, which it can go right up to
+		let addr = (section.base as u64 + section.bytes.len() as u64).next_multiple_of(align as u64);
+		if addr + size as u64 > 1 << word::BITS {
+			errors.push(Error::new(statement.span(), "section runs past the end of the address space"));
+			continue;
+		}
+		let addr = addr as word;
+		let max = r.aligns.entry(c).or_insert(1);
+		*max = align.max(*max);
+		bind(&mut pending, &mut program, &mut r, &mut errors, c, addr);
+		let section = &mut program.sections[c];
+		section.bytes.resize((addr - section.base) as usize + size as usize, 0);
+		placed[i] = Some((c, addr, size));
+	}
+	
+	if let Some(c) = current {
+		let end = program.sections[c].end();
+		bind(&mut pending, &mut program, &mut r, &mut errors, c, end);
+	}
+	
+	// every label that gets used, and where
+	let operands = lines.iter().flat_map(|l| match &l.statement {
+		Some(Statement::Instruction { operands, .. }) => &operands[..],
+		Some(Statement::Directive { args, .. }) => &args[..],
+		None => &[],
+	});
+	for op in operands {
+		let op = match &op.kind {
+			OperandKind::Address(offset, _) => offset,
+			_ => op,
+		};
+		let used = match &op.kind {
+			OperandKind::Label(l) => vec![(l.as_str(), op.span)],
+			OperandKind::Expr(e) => e.labels(),
+			_ => vec![],
+		};
+		for (l, span) in used {
+			program.uses.entry(l.to_owned()).or_default().push(span);
+		}
+	}
+	
+	// in an object file, anything that isn't here is somewhere else, and
+	// counts as 0 until it's linked
+	let mut labels = program.labels.clone();
+	if object {
+		r.externs.extend(program.uses.keys().filter(|l| !labels.contains_key(*l)).cloned());
+		labels.extend(r.externs.iter().map(|l| (l.clone(), 0)));
+	}
+	
+	// second pass: now anything can point anywhere
+	for (line, place) in lines.iter().zip(placed) {
+		let (Some(statement), Some((c, addr, size))) = (&line.statement, place) else { continue };
+		
+		let section = &mut program.sections[c];
+		let at = (addr - section.base) as usize;
+		let relocations = &mut r.relocations;
+		let mut relocate = |op: &Operand, field, addr| -> Result<(), Error> {
+			if let Some((kind, target)) = relocation(op, field, c, &r.homes)? {
+				relocations.push((c, addr, kind, target));
+			}
+			Ok(())
+		};
+		
+		let result = match statement {
+			Statement::Instruction { mnemonic, operands, span } => match plan(mnemonic, operands) {
+				Some(plan) => plan.expand(mnemonic, operands, *span, &labels, object).and_then(|real| {
+					for (k, (m, ops)) in real.iter().enumerate() {
+						let (at, addr) = (at + 4 * k, addr + 4 * k as word);
+						if let (true, Some((op, field))) = (object, value_field(m, ops)) {
+							relocate(op, field, addr)?;
+						}
+						let w = encode(m, ops, *span, addr, &labels)?;
+						section.bytes[at..at + 4].copy_from_slice(&w.to_le_bytes());
+					}
+					Ok(())
+				}),
+				None => match usage(mnemonic) {
+					Some(usage) => Err(Error::new(*span, format!("expected {usage}"))),
+					None => encode(mnemonic, operands, *span, addr, &labels).map(|_| ()),
+				},
+			},
+			Statement::Directive { name, args, .. } => {
+				let size = match name.as_str() {
+					".word" => Some((Field::Word, 4)),
+					".half" => Some((Field::Other, 2)),
+					".byte" => Some((Field::Other, 1)),
+					_ => None,
+				};
+				match (object, size) {
+					(true, Some((field, size))) => args.iter().enumerate()
+						.try_for_each(|(k, a)| relocate(a, field, addr + size * k as word)),
+					_ => Ok(()),
+				}.and_then(|_| emit_data(name, args, &labels, &mut section.bytes[at..]))
+			},
+		};
+		
+		if let Err(e) = result { errors.push(e); }
+		section.spans.push((addr, size, statement.span()));
+	}
+	
+	if errors.is_empty() {
+		program.files = names;
+		Ok((program, r))
+	} else {
+		errors.sort_by_key(|e| e.span);
+		let name = |e: &mut Error| e.file = names[e.span.file].clone();
+		for e in &mut errors {
+			name(e);
+			e.notes.iter_mut().for_each(name);
+		}
+		Err(errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn text(program: &Program) -> Vec<word> {
+		program.sections.iter()
+			.filter(|s| s.name == map::TEXT)
+			.flat_map(Section::words)
+			.collect()
+	}
+	
+	#[test]
+	fn labels() {
+		let source = "
+			start:  addi $t0, $zero, 3
+			loop:   addi $t0, $t0, -1
+			        bne  $t0, $zero, loop   # backwards
+			        beq  $t0, $zero, end    # forwards
+			        jal  end
+			end:    j    start
+		";
+		let program = assemble(source, Bases::default()).unwrap();
+		
+		assert_eq!(text(&program), [
+			0x2008_0003,
+			0x2108_FFFF,
+			0x1500_FFFE,
+			0x1100_0001,
+			0x0C10_0005,
+			0x0810_0000,
+		]);
+		assert_eq!(program.labels["end"], 0x0040_0014);
+		assert_eq!(program.sections[0].spans[2], (0x0040_0008, 4, Span::new(4, 12, 21)));
+		assert_eq!(program.sections[0].bytes[..4], [0x03, 0x00, 0x08, 0x20]);
+	}
+	
+	#[test]
+	fn lenient_parsing() {
+		assert_eq!(
+			text(&assemble("add $t0 $t0 $t0", Bases::default()).unwrap()),
+			text(&assemble("add $t0, $t0, $t0", Bases::default()).unwrap())
+		);
+	}
+	
+	#[test]
+	fn pseudo_ops() {
+		let source = "
+			main:   li   $t0, 5
+			        li   $t1, 0xFFFF
+			        li   $t2, 0x12345
+			        li   $t3, -1
+			        la   $a0, data
+			        blt  $t0, $t1, main
+			        bgt  $t0, 48, main
+			        mul  $t4, $t0, $t1
+			        move $t5, $t4
+			        lw   $t6, data
+			        addi $t7, $t7, 0x10000
+			        addi $t7, $t7, 0x8000
+			        addiu $t7, $t7, -0x8000
+			        slti $t7, $t7, 0x7FFF
+			        sltiu $t7, $t7, -0x8001
+			        andi $t7, $t7, 0xFFFF
+			        ori  $t7, $t7, 0x10000
+			        xori $t7, $t7, -1
+			        ori  $t7, $t7, 0
+			end:
+			.data 0x1001_8000
+			data:   .word 0
+		";
+		let expanded = "
+			main:   addiu $t0, $zero, 5
+			        ori   $t1, $zero, 0xFFFF
+			        lui   $at, 1
+			        ori   $t2, $at, 0x2345
+			        addiu $t3, $zero, -1
+			        lui   $at, 0x1001
+			        ori   $a0, $at, 0x8000
+			        slt   $at, $t0, $t1
+			        bne   $at, $zero, main
+			        addiu $at, $zero, 48
+			        slt   $at, $at, $t0
+			        bne   $at, $zero, main
+			        mult  $t0, $t1
+			        mflo  $t4
+			        addu  $t5, $zero, $t4
+			        lui   $at, 0x1002      # since 0x8000 is negative as an offset
+			        lw    $t6, $at, -0x8000
+			        lui   $at, 1
+			        ori   $at, $at, 0
+			        add   $t7, $t7, $at
+			        ori   $at, $zero, 0x8000
+			        add   $t7, $t7, $at
+			        addiu $t7, $t7, -0x8000
+			        slti  $t7, $t7, 0x7FFF
+			        lui   $at, 0xFFFF
+			        ori   $at, $at, 0x7FFF
+			        sltu  $t7, $t7, $at
+			        andi  $t7, $t7, 0xFFFF
+			        lui   $at, 1
+			        ori   $at, $at, 0
+			        or    $t7, $t7, $at
+			        addiu $at, $zero, -1
+			        xor   $t7, $t7, $at
+			        ori   $t7, $t7, 0
+		";
+		let program = assemble(source, Bases::default()).unwrap();
+		assert_eq!(text(&program), text(&assemble(expanded, Bases::default()).unwrap()));
+		assert_eq!(program.labels["end"], 0x0040_0088);
+		// one span per statement, not per instruction
+		assert_eq!(program.sections[0].spans.len(), 19);
+		assert_eq!(program.sections[0].spans[3].0, 0x0040_0010);
+	}
+	
+	#[test]
+	fn expressions() {
+		let source = "
+			.eqv SIZE 4*3
+			main:   addiu $sp, $sp, -16
+			        li    $t0, 'A'
+			        li    $t1, SIZE + 1
+			        lw    $t2, table+4*3
+			        lw    $t3, 4($sp)
+			        sw    $t3, ($sp)
+			        lw    $t4, table($t1)
+			        lui   $t5, %hi(table)
+			        addiu $t5, $t5, %lo(table)
+			        la    $a0, ($t5)
+			        la    $a0, -4($t5)
+			.data 0x1001_8000
+			table:  .word (1 << 4) | 1, table + 8, 'z'
+			        .byte -1 -1
+		";
+		let expanded = "
+			        addiu $sp, $sp, -16
+			        addiu $t0, $zero, 65
+			        addiu $t1, $zero, 13
+			        lui   $at, 0x1002
+			        lw    $t2, $at, -0x7FF4
+			        lw    $t3, $sp, 4
+			        sw    $t3, $sp, 0
+			        lui   $at, 0x1002
+			        addu  $at, $at, $t1
+			        lw    $t4, $at, -0x8000
+			        lui   $t5, 0x1002
+			        addiu $t5, $t5, -0x8000
+			        addiu $a0, $t5, 0
+			        addiu $a0, $t5, -4
+		";
+		let program = assemble(source, Bases::default()).unwrap();
+		assert_eq!(text(&program), text(&assemble(expanded, Bases::default()).unwrap()));
+		
+		let (_, data) = program.image(map::DATA).unwrap();
+		assert_eq!(data, [
+			0x11, 0, 0, 0,
+			0x08, 0x80, 0x01, 0x10,
+			b'z', 0, 0, 0,
+			0xFF, 0xFF, // two of them, since the space makes it a sign
+		]);
+	}
+	
+	#[test]
+	fn sections() {
+		let source = r#"
+			.eqv LIMIT 3
+			.eqv COUNTER $t0
+			.globl main
+			.data
+			msg:    .ascii  "hi\n"
+			        .asciiz "\"q\"", ""
+			odd:    .byte   0xFF, -128
+			halves:
+			        .half   LIMIT, -2
+			        .byte   1
+			ptr:    .word   main, ptrs_end
+			        .align  0
+			        .half   7
+			        .double 0.5
+			        .float  -2
+			ptrs_end:
+			
+			.text
+			main:   addi COUNTER, $zero, LIMIT
+			
+			.data 0x1001_0100
+			        .space  2
+			late:   .word   main
+			
+			.ktext 0x8000_0180
+			        eret
+			.data
+			        .byte   9
+		"#;
+		let program = assemble(source, Bases::default()).unwrap();
+		
+		let (base, data) = program.image(map::DATA).unwrap();
+		assert_eq!(base, 0x1001_0000);
+		assert_eq!(data.len(), 0x109);
+		assert_eq!(data[..0x28], [
+			b'h', b'i', b'\n', b'"', b'q', b'"', 0, 0, // the empty string's just its 0
+			0xFF, 0x80, // odd
+			3, 0, 0xFE, 0xFF, // halves, already aligned
+			1, 0, // and padding
+			0x00, 0x00, 0x40, 0x00, 0x26, 0x00, 0x01, 0x10, // ptr
+			7, 0, // no padding for anything after `.align 0`
+			0, 0, 0, 0, 0, 0, 0xE0, 0x3F,
+			0, 0, 0, 0xC0,
+			0, 0, // the gap up to where it moved
+		]);
+		assert_eq!(program.labels["halves"], 0x1001_000A);
+		assert_eq!(program.labels["ptrs_end"], 0x1001_0026);
+		assert_eq!(program.labels["late"], 0x1001_0104);
+		// going back to .data carries on from wherever it moved to
+		assert_eq!(data[0x104..], [0x00, 0x00, 0x40, 0x00, 9]);
+		
+		assert_eq!(program.image(map::TEXT), Some((0x0040_0000, vec![0x03, 0x00, 0x08, 0x20])));
+		assert_eq!(program.image(map::KTEXT), Some((0x8000_0180, vec![0x18, 0x00, 0x00, 0x42])));
+		assert!(program.globals.contains("main"));
+		
+		let mut mem = Memory::default();
+		program.load(&mut mem);
+		assert_eq!(mem.get_word(0x1001_0010), Some(0x0040_0000));
+		assert_eq!(mem.symbols.describe(0x1001_0012).as_deref(), Some("ptr+0x2"));
+	}
+	
+	#[test]
+	fn errors() {
+		let source = "
+here:	add $t0, $t1
+	beq $t0, $t1, nowhere
+here:	frobnicate $t0
+	sll $t0, $t1, 32
+	addi $t0, $t1, $t2
+	lui $t0, $t9000
+	j 0x1000_0000
+	lui $t0, -32769
+	.byte 256
+	.data
+	nop
+	.ascii 5
+	.tada
+	.align 4
+	.text
+	li $t0
+	blt $t0, $t1, 2.5
+	.word 1 / 0
+	.bss
+	.text 0xFFFF_FFFC
+	nop
+	nop
+	.data 0xFFFF_FFFE
+	.word 1
+	.space 0x7FFF_FFFF
+";
+		let errors = assemble(source, Bases::default()).unwrap_err();
+		let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+		assert_eq!(errors, [
+			"2:7: expected `add $rd, $rs, $rt`",
+			"3:16: label `nowhere` isn't defined anywhere",
+			"4:1: label `here` is already defined",
+			"4:7: unknown instruction `frobnicate`",
+			"5:16: 0x20 doesn't fit in a shift amount (0 to 31)",
+			"6:17: expected a number or a label",
+			"7:11: unknown register",
+			"8:4: 0x10000000 is too far away to jump to",
+			"9:11: -32769 doesn't fit in 16 bits (-32768 to 65535)",
+			"10:8: 0x100 doesn't fit in a byte (-128 to 255)",
+			"12:2: instructions have to go in `.text` or `.ktext`",
+			"13:9: expected a string",
+			"14:2: unknown directive `.tada`",
+			"15:2: expected `.align` 0, 1, 2, or 3",
+			"17:2: expected `li $reg, imm`",
+			"18:2: expected `blt $reg, $reg, label`",
+			"19:10: can't divide by zero",
+			"20:2: `.bss` only works when assembling an object file",
+			"23:2: section runs past the end of the address space",
+			"25:2: section runs past the end of the address space",
+			"26:9: `.space` can only reserve up to 0x1000000 bytes at a time",
+		]);
+	}
+	
+	#[test]
+	fn object_errors() {
+		let source = "
+	beq $t0, $t1, elsewhere
+	addi $t0, $t0, elsewhere
+	li $t0, elsewhere * 2
+	.text 0x100
+	.data
+	.word here - there
+here:	.half elsewhere
+there:
+	.bss
+	.word 1
+";
+		let files = BTreeMap::from([("x.asm".to_owned(), source.to_owned())]);
+		let errors = assemble_object(&files, "x.asm").unwrap_err();
+		let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+		assert_eq!(errors, [
+			"2:16: `elsewhere` is in another section or file, so it can't be branched to until it's linked",
+			"3:17: `elsewhere` isn't known until it's linked, so use `%hi(elsewhere)` or `%lo(elsewhere)`",
+			"4:10: this can't be worked out until it's linked",
+			"5:2: sections can't be moved in an object file, since the linker decides where they go",
+			"8:13: this can't be worked out until it's linked",
+			"11:2: only `.space` can go in `.bss`",
+		]);
+	}
+}
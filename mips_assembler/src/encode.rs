@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+
+use mips_emulator::chip::Register;
+
+use crate::{word, Span, Error, parser::{Operand, OperandKind}};
+
+/// What an instruction wants written after its mnemonic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Operands {
+	RdRsRt,
+	RdRtShamt,
+	Rs,
+	/// `jalr $rs`, or `jalr $rd, $rs` to link somewhere other than `$ra`
+	Jalr,
+	RsRt,
+	Rd,
+	RtRsImm,
+	/// `andi $rt, $rs, imm`, with an immediate that's zero-extended instead
+	RtRsImmU,
+	/// `lw $rt, offset($rs)`, or `lw $rt, $rs, offset` the way the
+	/// disassembly shows it
+	Mem,
+	RtImm,
+	/// `beq $rs, $rt, label`. A plain number is the offset itself, which is
+	/// also what the disassembly shows.
+	Branch,
+	/// `j label`, or `j` an address.
+	Jump,
+	RtRd,
+	/// `syscall` or `break`, with an optional code
+	Code,
+	None,
+}
+
+impl Operands {
+	fn usage(self) -> &'static str {
+		use Operands::*;
+		match self {
+			RdRsRt    => "$rd, $rs, $rt",
+			RdRtShamt => "$rd, $rt, shamt",
+			Rs        => "$rs",
+			Jalr      => "[$rd,] $rs",
+			RsRt      => "$rs, $rt",
+			Rd        => "$rd",
+			RtRsImm | RtRsImmU => "$rt, $rs, imm",
+			Mem       => "$rt, offset($rs)",
+			RtImm     => "$rt, imm",
+			Branch    => "$rs, $rt, label",
+			Jump      => "label",
+			RtRd      => "$rt, $rd",
+			Code      => "[code]",
+			None      => "nothing",
+		}
+	}
+	
+	/// What each operand has to be, for each way of writing it.
+	fn shapes(self) -> &'static [&'static [Slot]] {
+		use Operands::*;
+		use Slot::{Reg as R, Value as V, Address as A};
+		match self {
+			RdRsRt => &[&[R, R, R]],
+			RtRsImm | RtRsImmU | RdRtShamt | Branch => &[&[R, R, V]],
+			Mem => &[&[R, A], &[R, R, V]],
+			Rs | Rd => &[&[R]],
+			Jalr => &[&[R], &[R, R]],
+			RsRt | RtRd => &[&[R, R]],
+			RtImm => &[&[R, V]],
+			Jump => &[&[V]],
+			Code => &[&[], &[V]],
+			None => &[&[]],
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+	Reg,
+	Value,
+	Address,
+}
+
+impl Slot {
+	fn matches(self, op: &Operand) -> bool {
+		match op.kind {
+			OperandKind::Register(_) => self == Slot::Reg,
+			OperandKind::Address(..) => self == Slot::Address,
+			_ => self == Slot::Value,
+		}
+	}
+}
+
+/// Every instruction the emulator knows how to run, with the bits that
+/// don't come from operands already filled in.
+const INSTRUCTIONS: &[(&str, word, Operands)] = {
+	use Operands::*;
+&[
+	("sll"    , 0x0000_0000, RdRtShamt),
+	("srl"    , 0x0000_0002, RdRtShamt),
+	("jr"     , 0x0000_0008, Rs       ),
+	("jalr"   , 0x0000_0009, Jalr     ),
+	("syscall", 0x0000_000C, Code     ),
+	("break"  , 0x0000_000D, Code     ),
+	("mfhi"   , 0x0000_0010, Rd       ),
+	("mflo"   , 0x0000_0012, Rd       ),
+	("mult"   , 0x0000_0018, RsRt     ),
+	("multu"  , 0x0000_0019, RsRt     ),
+	("div"    , 0x0000_001A, RsRt     ),
+	("divu"   , 0x0000_001B, RsRt     ),
+	("add"    , 0x0000_0020, RdRsRt   ),
+	("addu"   , 0x0000_0021, RdRsRt   ),
+	("sub"    , 0x0000_0022, RdRsRt   ),
+	("subu"   , 0x0000_0023, RdRsRt   ),
+	("and"    , 0x0000_0024, RdRsRt   ),
+	("or"     , 0x0000_0025, RdRsRt   ),
+	("xor"    , 0x0000_0026, RdRsRt   ),
+	("nor"    , 0x0000_0027, RdRsRt   ),
+	("slt"    , 0x0000_002A, RdRsRt   ),
+	("sltu"   , 0x0000_002B, RdRsRt   ),
+	("teq"    , 0x0000_0034, RsRt     ),
+	
+	("j"      , 0x0800_0000, Jump     ),
+	("jal"    , 0x0C00_0000, Jump     ),
+	("beq"    , 0x1000_0000, Branch   ),
+	("bne"    , 0x1400_0000, Branch   ),
+	("addi"   , 0x2000_0000, RtRsImm  ),
+	("addiu"  , 0x2400_0000, RtRsImm  ),
+	("slti"   , 0x2800_0000, RtRsImm  ),
+	("sltiu"  , 0x2C00_0000, RtRsImm  ),
+	("andi"   , 0x3000_0000, RtRsImmU ),
+	("ori"    , 0x3400_0000, RtRsImmU ),
+	("xori"   , 0x3800_0000, RtRsImmU ),
+	("lui"    , 0x3C00_0000, RtImm    ),
+	("lw"     , 0x8C00_0000, Mem      ),
+	("lbu"    , 0x9000_0000, Mem      ),
+	("lhu"    , 0x9400_0000, Mem      ),
+	("sb"     , 0xA000_0000, Mem      ),
+	("sh"     , 0xA400_0000, Mem      ),
+	("sw"     , 0xAC00_0000, Mem      ),
+	
+	("mfc0"   , 0x4000_0000, RtRd     ),
+	("mtc0"   , 0x4080_0000, RtRd     ),
+	("eret"   , 0x4200_0018, None     ),
+	
+	("nop"    , 0x0000_0000, None     ),
+]};
+
+/// Where a number or a label ends up, for working out what a linker has to
+/// fix up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Field {
+	/// An immediate or an offset, in the low 16 bits.
+	Half,
+	Branch,
+	Jump,
+	/// A whole word of data.
+	Word,
+	/// Anything else, like a shift amount, which can't be fixed up.
+	Other,
+}
+
+/// The operand in a real instruction that's a number or a label, if there
+/// is one, and where it ends up.
+pub(crate) fn value_field<'a>(mnemonic: &str, ops: &'a [Operand]) -> Option<(&'a Operand, Field)> {
+	use Operands::{RtRsImm, RtRsImmU, RtImm, Mem, Branch, Jump};
+	let &(_, _, form) = INSTRUCTIONS.iter().find(|&&(m, _, _)| m == mnemonic)?;
+	let field = match form {
+		RtRsImm | RtRsImmU | RtImm | Mem => Field::Half,
+		Branch => Field::Branch,
+		Jump => Field::Jump,
+		_ => Field::Other,
+	};
+	ops.iter().find_map(|op| match &op.kind {
+		OperandKind::Register(_) => None,
+		OperandKind::Address(offset, _) => Some(&**offset),
+		_ => Some(op),
+	}).map(|op| (op, field))
+}
+
+pub(crate) fn is_instruction(mnemonic: &str) -> bool {
+	INSTRUCTIONS.iter().any(|&(m, _, _)| m == mnemonic)
+}
+
+/// True if `mnemonic` is a real instruction, and `ops` has registers,
+/// values, and addresses where it wants them.
+pub(crate) fn accepts(mnemonic: &str, ops: &[Operand]) -> bool {
+	INSTRUCTIONS.iter()
+		.find(|&&(m, _, _)| m == mnemonic)
+		.is_some_and(|&(_, _, form)| form.shapes().iter().any(|shape| {
+			shape.len() == ops.len() && shape.iter().zip(ops)
+				.all(|(slot, op)| slot.matches(op))
+		}))
+}
+
+fn rs(r: Register) -> word { (r as word) << 21 }
+fn rt(r: Register) -> word { (r as word) << 16 }
+fn rd(r: Register) -> word { (r as word) << 11 }
+
+fn register(op: &Operand) -> Result<Register, Error> {
+	match op.kind {
+		OperandKind::Register(r) => Ok(r),
+		_ => Err(Error::new(op.span, "expected a register")),
+	}
+}
+
+/// A number, the address of a label, or some arithmetic on those.
+pub(crate) fn value(op: &Operand, labels: &BTreeMap<String, word>) -> Result<word, Error> {
+	let mut label = |l: &str, span| labels.get(l).copied()
+		.ok_or_else(|| Error::new(span, format!("label `{l}` isn't defined anywhere")));
+	match &op.kind {
+		OperandKind::Int(n) => Ok(*n),
+		OperandKind::Label(l) => label(l, op.span),
+		OperandKind::Expr(e) => e.eval(&mut label),
+		_ => Err(Error::new(op.span, "expected a number or a label")),
+	}
+}
+
+/// Which numbers fit in a field, going by what happens to it afterwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Range {
+	/// It gets sign-extended, like `addi`'s immediate or a load's offset.
+	Signed,
+	/// It gets zero-extended, like `ori`'s immediate.
+	Unsigned,
+	/// It's used as it is, like a `.byte` or `lui`'s immediate, so it can be
+	/// written either way.
+	Either,
+}
+
+/// Checks that `n` fits in `bits` bits, going by `range`, and cuts it down to
+/// that many bits.
+pub(crate) fn fits(op: &Operand, n: word, bits: u32, range: Range, what: &str) -> Result<word, Error> {
+	let mask = word::MAX >> (word::BITS - bits);
+	let negative = n as i32 as i64;
+	let (min, max) = match range {
+		Range::Signed => (-(1 << (bits - 1)), (mask >> 1) as i64),
+		Range::Unsigned => (0, mask as i64),
+		Range::Either => (-(1 << (bits - 1)), mask as i64),
+	};
+	// anything with the top bit set counts as negative
+	let as_written = if negative < 0 { negative } else { n as i64 };
+	if (min..=max).contains(&as_written) {
+		Ok(n & mask)
+	} else if negative < 0 {
+		Err(Error::new(op.span, format!("{negative} doesn't fit in {what} ({min} to {max})")))
+	} else {
+		Err(Error::new(op.span, format!("{n:#X} doesn't fit in {what} ({min} to {max})")))
+	}
+}
+
+/// Turns one instruction into its word. `pc` is where it's going to sit, for
+/// branches and jumps.
+pub(crate) fn encode(
+	mnemonic: &str, ops: &[Operand], span: Span,
+	pc: word, labels: &BTreeMap<String, word>,
+) -> Result<word, Error> {
+	use Operands::*;
+	
+	let &(_, bits, form) = INSTRUCTIONS.iter()
+		.find(|&&(m, _, _)| m == mnemonic)
+		.ok_or_else(|| Error::new(span, format!("unknown instruction `{mnemonic}`")))?;
+	
+	let wrong_count = || Error::new(span, format!("expected `{mnemonic} {}`", form.usage()));
+	
+	let fields = match (form, ops) {
+		(RdRsRt, [d, s, t]) => rd(register(d)?) | rs(register(s)?) | rt(register(t)?),
+		(RdRtShamt, [d, t, sh]) => {
+			let shamt = fits(sh, value(sh, labels)?, 5, Range::Unsigned, "a shift amount")?;
+			rd(register(d)?) | rt(register(t)?) | shamt << 6
+		},
+		(Rs, [s]) => rs(register(s)?),
+		(Jalr, [s]) => rd(Register::ra) | rs(register(s)?),
+		(Jalr, [d, s]) => rd(register(d)?) | rs(register(s)?),
+		(RsRt, [s, t]) => rs(register(s)?) | rt(register(t)?),
+		(Rd, [d]) => rd(register(d)?),
+		(Mem, [t, Operand { kind: OperandKind::Address(offset, s), .. }]) => {
+			let offset = fits(offset, value(offset, labels)?, 16, Range::Signed, "16 bits")?;
+			rt(register(t)?) | rs(*s) | offset
+		},
+		(RtRsImm | RtRsImmU | Mem, [t, s, imm]) => {
+			let range = if form == RtRsImmU { Range::Unsigned } else { Range::Signed };
+			let imm = fits(imm, value(imm, labels)?, 16, range, "16 bits")?;
+			rt(register(t)?) | rs(register(s)?) | imm
+		},
+		(RtImm, [t, imm]) => rt(register(t)?) | fits(imm, value(imm, labels)?, 16, Range::Either, "16 bits")?,
+		(Branch, [s, t, target]) => {
+			let offset = match target.kind {
+				OperandKind::Int(n) => fits(target, n, 16, Range::Signed, "16 bits")?,
+				_ => {
+					let to = value(target, labels)?;
+					if to % 4 != 0 {
+						return Err(Error::new(target.span, format!("can't branch to {to:#010X}, it isn't word-aligned")));
+					}
+					let offset = (to.wrapping_sub(pc.wrapping_add(4)) as i32) >> 2;
+					i16::try_from(offset)
+						.map_err(|_| Error::new(target.span, format!("{to:#010X} is too far away to branch to")))?
+						as u16 as word
+				},
+			};
+			rs(register(s)?) | rt(register(t)?) | offset
+		},
+		(Jump, [target]) => {
+			let to = value(target, labels)?;
+			if to % 4 != 0 {
+				return Err(Error::new(target.span, format!("can't jump to {to:#010X}, it isn't word-aligned")));
+			}
+			// jumps only replace the low 28 bits of the PC
+			if to & 0xF000_0000 != pc.wrapping_add(4) & 0xF000_0000 {
+				return Err(Error::new(target.span, format!("{to:#010X} is too far away to jump to")));
+			}
+			(to >> 2) & 0x03FF_FFFF
+		},
+		(RtRd, [t, d]) => rt(register(t)?) | rd(register(d)?),
+		(Code, []) => 0,
+		(Code, [code]) => fits(code, value(code, labels)?, 20, Range::Unsigned, "20 bits")? << 6,
+		(None, []) => 0,
+		_ => return Err(wrong_count()),
+	};
+	
+	Ok(bits | fields)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn imm(mnemonic: &str, n: i64) -> Result<word, String> {
+		let span = Span::default();
+		let reg = |r| Operand { kind: OperandKind::Register(r), span };
+		let n = Operand { kind: OperandKind::Int(n as word), span };
+		let ops = match mnemonic {
+			"lui" => vec![reg(Register::t0), n],
+			_ => vec![reg(Register::t0), reg(Register::t0), n],
+		};
+		encode(mnemonic, &ops, span, 0, &BTreeMap::new()).map(|w| w & 0xFFFF).map_err(|e| e.message)
+	}
+	
+	#[test]
+	fn ranges() {
+		// sign-extended
+		for m in ["addi", "addiu", "slti", "sltiu", "lw", "sb", "beq"] {
+			assert_eq!(imm(m, -0x8000), Ok(0x8000), "{m}");
+			assert_eq!(imm(m, 0x7FFF), Ok(0x7FFF), "{m}");
+			assert_eq!(imm(m, -0x8001), Err("-32769 doesn't fit in 16 bits (-32768 to 32767)".into()), "{m}");
+			assert_eq!(imm(m, 0x8000), Err("0x8000 doesn't fit in 16 bits (-32768 to 32767)".into()), "{m}");
+		}
+		// zero-extended
+		for m in ["andi", "ori", "xori"] {
+			assert_eq!(imm(m, 0), Ok(0), "{m}");
+			assert_eq!(imm(m, 0xFFFF), Ok(0xFFFF), "{m}");
+			assert_eq!(imm(m, -1), Err("-1 doesn't fit in 16 bits (0 to 65535)".into()), "{m}");
+			assert_eq!(imm(m, 0x10000), Err("0x10000 doesn't fit in 16 bits (0 to 65535)".into()), "{m}");
+		}
+		// it's the top half as it is, so it can be written either way
+		assert_eq!(imm("lui", -1), Ok(0xFFFF));
+		assert_eq!(imm("lui", -0x8000), Ok(0x8000));
+		assert_eq!(imm("lui", 0xFFFF), Ok(0xFFFF));
+		assert_eq!(imm("lui", -0x8001), Err("-32769 doesn't fit in 16 bits (-32768 to 65535)".into()));
+		assert_eq!(imm("lui", 0x10000), Err("0x10000 doesn't fit in 16 bits (-32768 to 65535)".into()));
+	}
+}
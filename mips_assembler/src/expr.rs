@@ -0,0 +1,219 @@
+//! Constant expressions in operands, like `table+4*3` or `%hi(msg)`. The
+//! operators and how tightly they bind are the same as C's, and everything's
+//! done on 32-bit signed numbers that wrap around.
+
+use crate::{word, Span, Error, lexer::{Token, TokenKind}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp {
+	Add, Sub, Mul, Div, Rem,
+	Shl, Shr,
+	And, Or, Xor,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+	Int(word),
+	Label(String, Span),
+	Neg(Box<Expr>),
+	Not(Box<Expr>),
+	/// The span's the operator's, for when it can't be done.
+	Binary(BinOp, Box<Expr>, Box<Expr>, Span),
+	/// The upper half, rounded up if the lower half's going to be negative
+	/// when it's sign-extended, so `%hi(x) << 16` plus `%lo(x)` is `x` again.
+	Hi(Box<Expr>),
+	/// The lower half, sign-extended the way `addiu` and loads and stores
+	/// are going to use it.
+	Lo(Box<Expr>),
+}
+
+/// The upper half of `n`, for `lui`, when the lower half's going to be
+/// sign-extended and added on.
+pub(crate) fn hi(n: word) -> word {
+	n.wrapping_add(0x8000) >> 16
+}
+
+impl Expr {
+	pub fn has_labels(&self) -> bool {
+		match self {
+			Expr::Int(_) => false,
+			Expr::Label(..) => true,
+			Expr::Neg(e) | Expr::Not(e) | Expr::Hi(e) | Expr::Lo(e) => e.has_labels(),
+			Expr::Binary(_, a, b, _) => a.has_labels() || b.has_labels(),
+		}
+	}
+	
+	/// Every label in it, and where.
+	pub fn labels(&self) -> Vec<(&str, Span)> {
+		match self {
+			Expr::Int(_) => vec![],
+			Expr::Label(l, span) => vec![(l, *span)],
+			Expr::Neg(e) | Expr::Not(e) | Expr::Hi(e) | Expr::Lo(e) => e.labels(),
+			Expr::Binary(_, a, b, _) => [a.labels(), b.labels()].concat(),
+		}
+	}
+	
+	/// Works it out, with `label` for the address of each label.
+	pub fn eval(&self, label: &mut dyn FnMut(&str, Span) -> Result<word, Error>) -> Result<word, Error> {
+		Ok(match self {
+			Expr::Int(n) => *n,
+			Expr::Label(l, span) => label(l, *span)?,
+			Expr::Neg(e) => e.eval(label)?.wrapping_neg(),
+			Expr::Not(e) => !e.eval(label)?,
+			Expr::Hi(e) => hi(e.eval(label)?),
+			Expr::Lo(e) => e.eval(label)? as u16 as i16 as word,
+			Expr::Binary(op, a, b, span) => {
+				let (a, b) = (a.eval(label)?, b.eval(label)?);
+				let (sa, sb) = (a as i32, b as i32);
+				match op {
+					BinOp::Add => a.wrapping_add(b),
+					BinOp::Sub => a.wrapping_sub(b),
+					BinOp::Mul => a.wrapping_mul(b),
+					BinOp::Div | BinOp::Rem if b == 0 => return Err(Error::new(*span, "can't divide by zero")),
+					BinOp::Div => sa.wrapping_div(sb) as word,
+					BinOp::Rem => sa.wrapping_rem(sb) as word,
+					BinOp::Shl | BinOp::Shr if b >= 32 => return Err(Error::new(*span, format!("can't shift by {sb}"))),
+					BinOp::Shl => a << b,
+					BinOp::Shr => (sa >> b) as word,
+					BinOp::And => a & b,
+					BinOp::Or => a | b,
+					BinOp::Xor => a ^ b,
+				}
+			},
+		})
+	}
+}
+
+/// The operator a token is, and how tightly it binds.
+fn binary(kind: &TokenKind) -> Option<(BinOp, u8)> {
+	Some(match kind {
+		TokenKind::Pipe    => (BinOp::Or , 1),
+		TokenKind::Caret   => (BinOp::Xor, 2),
+		TokenKind::Amp     => (BinOp::And, 3),
+		TokenKind::Shl     => (BinOp::Shl, 4),
+		TokenKind::Shr     => (BinOp::Shr, 4),
+		TokenKind::Plus    => (BinOp::Add, 5),
+		TokenKind::Minus   => (BinOp::Sub, 5),
+		TokenKind::Star    => (BinOp::Mul, 6),
+		TokenKind::Slash   => (BinOp::Div, 6),
+		TokenKind::Percent => (BinOp::Rem, 6),
+		_ => return None,
+	})
+}
+
+fn end(span: Span) -> usize { span.column + span.len }
+
+/// Reads an expression off the front of `tokens`, returning it, its span, and
+/// whatever's left. `before` is what came before it, for errors when there's
+/// nothing there.
+pub(crate) fn parse(tokens: &[Token], before: Span) -> Result<(Expr, Span, &[Token]), Error> {
+	binary_from(tokens, before, 0)
+}
+
+fn binary_from(tokens: &[Token], before: Span, min: u8) -> Result<(Expr, Span, &[Token]), Error> {
+	let (mut lhs, mut span, mut rest) = unary(tokens, before)?;
+	
+	while let Some((op, after)) = rest.split_first() {
+		let Some((kind, binds)) = binary(&op.kind) else { break };
+		if binds < min { break; }
+		
+		// commas are optional, so `.word 1 -1` has to be two numbers. a sign
+		// with a space before it and not after starts the next operand.
+		let signed = matches!(kind, BinOp::Add | BinOp::Sub)
+			&& end(span) < op.span.column
+			&& after.first().is_some_and(|next| end(op.span) == next.span.column);
+		if signed { break; }
+		
+		let (rhs, rhs_span, more) = binary_from(after, op.span, binds + 1)?;
+		lhs = Expr::Binary(kind, Box::new(lhs), Box::new(rhs), op.span);
+		span = span.to(rhs_span);
+		rest = more;
+	}
+	
+	Ok((lhs, span, rest))
+}
+
+fn unary(tokens: &[Token], before: Span) -> Result<(Expr, Span, &[Token]), Error> {
+	let Some((token, rest)) = tokens.split_first().filter(|(t, _)| t.kind != TokenKind::Newline) else {
+		return Err(Error::new(before, "expected a value after this"));
+	};
+	let span = token.span;
+	
+	// closes a parenthesis opened at `open`
+	fn close(rest: &[Token], open: Span, inner: Span) -> Result<(Span, &[Token]), Error> {
+		match rest.split_first() {
+			Some((Token { kind: TokenKind::RParen, span }, more)) => Ok((open.to(*span), more)),
+			_ => Err(Error::new(open.to(inner), "this `(` is never closed")),
+		}
+	}
+	
+	Ok(match &token.kind {
+		TokenKind::Int(n) => (Expr::Int(*n), span, rest),
+		TokenKind::Ident(l) => (Expr::Label(l.clone(), span), span, rest),
+		TokenKind::Minus | TokenKind::Plus | TokenKind::Tilde => {
+			let (e, inner, more) = unary(rest, span)?;
+			let e = match token.kind {
+				TokenKind::Minus => Expr::Neg(Box::new(e)),
+				TokenKind::Tilde => Expr::Not(Box::new(e)),
+				_ => e,
+			};
+			(e, span.to(inner), more)
+		},
+		TokenKind::LParen => {
+			let (e, inner, more) = binary_from(rest, span, 0)?;
+			let (span, more) = close(more, span, inner)?;
+			(e, span, more)
+		},
+		TokenKind::Param(p) if matches!(p.as_str(), "%hi" | "%lo") => {
+			let Some((open @ Token { kind: TokenKind::LParen, .. }, rest)) = rest.split_first() else {
+				return Err(Error::new(span, format!("expected `{p}(value)`")));
+			};
+			let (e, inner, more) = binary_from(rest, open.span, 0)?;
+			let (end, more) = close(more, open.span, inner)?;
+			let e = if p == "%hi" { Expr::Hi(Box::new(e)) } else { Expr::Lo(Box::new(e)) };
+			(e, span.to(end), more)
+		},
+		TokenKind::Float(_) => return Err(Error::new(span, "floats can only be used by themselves")),
+		TokenKind::Register(_) => return Err(Error::new(span, "registers can't be used in expressions")),
+		_ => return Err(Error::new(span, "expected a value")),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lexer::tokenize;
+	
+	fn eval(source: &str) -> Result<word, String> {
+		let (tokens, errors) = tokenize(source);
+		assert_eq!(errors, []);
+		let (e, _, rest) = parse(&tokens, Span::default()).map_err(|e| e.to_string())?;
+		assert_eq!(rest.len(), 1, "everything but the newline");
+		e.eval(&mut |l, span| match l {
+			"table" => Ok(0x1001_0000),
+			"far" => Ok(0x1234_8000),
+			_ => Err(Error::new(span, format!("label `{l}` isn't defined anywhere"))),
+		}).map_err(|e| e.to_string())
+	}
+	
+	#[test]
+	fn expressions() {
+		assert_eq!(eval("-16"), Ok(-16i32 as word));
+		assert_eq!(eval("table+4*3"), Ok(0x1001_000C));
+		assert_eq!(eval("(1 + 2) * 3 - -1"), Ok(10));
+		assert_eq!(eval("1 | 2 ^ 7 & 3 << 1"), Ok(1 | (2 ^ (7 & (3 << 1)))));
+		assert_eq!(eval("~0 >> 4"), Ok(word::MAX));
+		assert_eq!(eval("-7 / 2 % 2"), Ok(-1i32 as word));
+		assert_eq!(eval("'a' + 1"), Ok(98));
+		assert_eq!(eval("%hi(far)"), Ok(0x1235));
+		assert_eq!(eval("%lo(far)"), Ok(-0x8000i32 as word));
+		assert_eq!(eval("(%hi(far) << 16) + %lo(far)"), Ok(0x1234_8000));
+		
+		assert_eq!(eval("1 / (2 - 2)"), Err("1:3: can't divide by zero".into()));
+		assert_eq!(eval("1 << 32"), Err("1:3: can't shift by 32".into()));
+		assert_eq!(eval("nowhere + 1"), Err("1:1: label `nowhere` isn't defined anywhere".into()));
+		assert_eq!(eval("(1 + 2"), Err("1:1: this `(` is never closed".into()));
+		assert_eq!(eval("1 *"), Err("1:3: expected a value after this".into()));
+		assert_eq!(eval("%hi far"), Err("1:1: expected `%hi(value)`".into()));
+	}
+}
@@ -0,0 +1,188 @@
+//! Where `.include`d files come from. Natively that's usually the real
+//! filesystem, but anything that can hand over a file by name will do, like
+//! the GUI's editor tabs.
+
+use std::{collections::BTreeMap, path::{Component, Path, PathBuf}};
+
+use crate::{Error, lexer::{tokenize, Token, TokenKind}, macros::labels_len};
+
+/// Somewhere to read source files from. Anything `.include`d is next to the
+/// file including it, so `.include "macros.asm"` in `lib/io.asm` asks for
+/// `lib/macros.asm`.
+pub trait Files {
+	/// The file called `name`, or why it can't be had.
+	fn read(&self, name: &str) -> Result<String, String>;
+}
+
+/// Files kept in memory, by name.
+impl Files for BTreeMap<String, String> {
+	fn read(&self, name: &str) -> Result<String, String> {
+		self.get(name).cloned().ok_or_else(|| format!("there's no file called `{name}`"))
+	}
+}
+
+/// The real filesystem, with names relative to `root`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileSystem {
+	pub root: PathBuf,
+}
+
+impl Files for FileSystem {
+	fn read(&self, name: &str) -> Result<String, String> {
+		std::fs::read_to_string(self.root.join(name)).map_err(|e| format!("couldn't read `{name}`: {e}"))
+	}
+}
+
+/// `name` as seen from the file `from`, so an `.include` finds files next to
+/// the one it's in rather than wherever assembling started. Any `.` or `..`
+/// gets taken out, so the same file always ends up with the same name.
+fn relative(from: &str, name: &str) -> String {
+	let dir = Path::new(from).parent().unwrap_or(Path::new(""));
+	let mut path = PathBuf::new();
+	for c in dir.join(name).components() {
+		match c {
+			Component::CurDir => (),
+			Component::ParentDir if matches!(path.components().next_back(), Some(Component::Normal(_))) => { path.pop(); },
+			c => path.push(c),
+		}
+	}
+	path.to_string_lossy().into_owned()
+}
+
+struct Includer<'a> {
+	files: &'a dyn Files,
+	names: Vec<String>,
+	tokens: Vec<Token>,
+	errors: Vec<Error>,
+	/// The files being included right now, innermost last.
+	stack: Vec<String>,
+}
+
+impl Includer<'_> {
+	fn file(&mut self, name: &str, source: &str) {
+		let id = self.names.len();
+		self.names.push(name.to_owned());
+		self.stack.push(name.to_owned());
+		
+		let (mut tokens, errors) = tokenize(source);
+		for t in &mut tokens { t.span.file = id; }
+		self.errors.extend(errors.into_iter().map(|mut e| { e.span.file = id; e }));
+		
+		for line in tokens.split_inclusive(|t| t.kind == TokenKind::Newline) {
+			let labels = labels_len(line);
+			let [Token { kind: TokenKind::Directive(d), span }, rest @ ..] = &line[labels..] else {
+				self.tokens.extend_from_slice(line);
+				continue;
+			};
+			if !d.eq_ignore_ascii_case(".include") {
+				self.tokens.extend_from_slice(line);
+				continue;
+			}
+			
+			// labels before it point at whatever the file starts with
+			if labels > 0 {
+				self.tokens.extend_from_slice(&line[..labels]);
+				self.tokens.push(Token { kind: TokenKind::Newline, span: *span });
+			}
+			
+			let (included, at) = match rest {
+				[Token { kind: TokenKind::Str(s), span }, Token { kind: TokenKind::Newline, .. }] => (relative(name, s), *span),
+				_ => {
+					self.errors.push(Error::new(*span, "expected `.include \"file\"`"));
+					continue;
+				},
+			};
+			if self.stack.contains(&included) {
+				self.errors.push(Error::new(at, format!("`{included}` ends up including itself")));
+				continue;
+			}
+			match self.files.read(&included) {
+				Ok(source) => self.file(&included, &source),
+				Err(e) => self.errors.push(Error::new(at, e)),
+			}
+		}
+		
+		self.stack.pop();
+	}
+}
+
+/// Tokenizes `main`, with each `.include "name"` in it swapped for the tokens
+/// in `name`. Spans' `file`s index into the names that come back.
+pub(crate) fn tokenize_all(files: &dyn Files, main: &str) -> (Vec<Token>, Vec<String>, Vec<Error>) {
+	let mut includer = Includer { files, names: vec![], tokens: vec![], errors: vec![], stack: vec![] };
+	match files.read(main) {
+		Ok(source) => includer.file(main, &source),
+		Err(e) => {
+			includer.names.push(main.to_owned());
+			includer.errors.push(Error::new(Default::default(), e));
+		},
+	}
+	(includer.tokens, includer.names, includer.errors)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{assemble, assemble_files, Bases, Span};
+	
+	fn files(files: &[(&str, &str)]) -> BTreeMap<String, String> {
+		files.iter().map(|&(name, source)| (name.to_owned(), source.to_owned())).collect()
+	}
+	
+	#[test]
+	fn includes() {
+		let files = files(&[
+			("main.asm", "main:\t.include \"lib.asm\"\n\tsquare ($t0)\n\tj main"),
+			("lib.asm", ".macro square (%r)\n\tmul %r, %r, %r\n.end_macro"),
+		]);
+		let program = assemble_files(&files, "main.asm", Bases::default()).unwrap();
+		let inline = assemble("main:\tmul $t0, $t0, $t0\n\tj main", Bases::default()).unwrap();
+		assert_eq!(program.sections[0].bytes, inline.sections[0].bytes);
+		assert_eq!(program.files, ["main.asm", "lib.asm"]);
+	}
+	
+	#[test]
+	fn relative_includes() {
+		let files = files(&[
+			("src/main.asm", ".include \"lib/a.asm\"\n\tnop"),
+			("src/lib/a.asm", ".include \"b.asm\"\n.include \"../../common.asm\"\n.include \"./../lib/a.asm\""),
+			("src/lib/b.asm", "\tsyscall"),
+			("common.asm", "\tbreak"),
+		]);
+		let errors = assemble_files(&files, "src/main.asm", Bases::default()).unwrap_err();
+		let errors: Vec<_> = errors.iter().map(|e| (e.file.as_str(), e.to_string())).collect();
+		assert_eq!(errors, [("src/lib/a.asm", "3:10: `src/lib/a.asm` ends up including itself".into())]);
+		
+		let mut files = files;
+		files.insert("src/lib/a.asm".into(), ".include \"b.asm\"\n.include \"../../common.asm\"".into());
+		let program = assemble_files(&files, "src/main.asm", Bases::default()).unwrap();
+		assert_eq!(program.files, ["src/main.asm", "src/lib/a.asm", "src/lib/b.asm", "common.asm"]);
+		assert_eq!(program.sections[0].bytes, assemble("\tsyscall\n\tbreak\n\tnop", Bases::default()).unwrap().sections[0].bytes);
+	}
+	
+	#[test]
+	fn errors() {
+		let files = files(&[
+			("main.asm", ".include \"a.asm\"\n.include \"gone.asm\"\n.include 5\n\tbad ()"),
+			("a.asm", "\tnop ?\n.include \"b.asm\""),
+			("b.asm", ".include \"a.asm\"\n.macro bad ()\n\tli $t0, %x\n.end_macro"),
+		]);
+		let errors = assemble_files(&files, "main.asm", Bases::default()).unwrap_err();
+		let errors: Vec<_> = errors.iter().map(|e| (e.file.as_str(), e.to_string())).collect();
+		assert_eq!(errors, [
+			("main.asm", "2:10: there's no file called `gone.asm`".into()),
+			("main.asm", "3:1: expected `.include \"file\"`".into()),
+			("main.asm", "4:2: `%x` isn't one of `bad`'s parameters".into()),
+			("main.asm", "4:2: expected `li $reg, imm`".into()),
+			("a.asm", "1:6: unexpected character `?`".into()),
+			("b.asm", "1:10: `a.asm` ends up including itself".into()),
+		]);
+		
+		let errors = assemble_files(&files, "main.asm", Bases::default()).unwrap_err();
+		assert_eq!(errors[2].notes[0].file, "b.asm");
+		assert_eq!(errors[2].notes[0].span, Span { file: 2, ..Span::new(3, 10, 2) });
+		
+		let errors = assemble("\t.include \"x.asm\"", Bases::default()).unwrap_err();
+		assert_eq!(errors[0].message, "there's no file called `x.asm`");
+	}
+}
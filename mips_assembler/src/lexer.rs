@@ -0,0 +1,325 @@
+use crate::{word, Span, Error};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+	/// Mnemonics and labels.
+	Ident(String),
+	
+	/// Anything starting with a `.`, like `.text`. The dot's kept.
+	Directive(String),
+	
+	/// Whatever came after a `$`, so either a name like `t0` or a number.
+	Register(String),
+	
+	/// A macro parameter, like `%reg`. The `%` is kept.
+	Param(String),
+	
+	Int(word),
+	Float(f64),
+	
+	/// A string, with its escapes already turned into what they stand for.
+	Str(String),
+	
+	Comma,
+	Colon,
+	LParen,
+	RParen,
+	
+	Plus,
+	Minus,
+	Star,
+	Slash,
+	/// A `%` with nothing after it, so not a parameter.
+	Percent,
+	Amp,
+	Pipe,
+	Caret,
+	Tilde,
+	Shl,
+	Shr,
+	
+	/// The end of a line. Every line gets one, even the last.
+	Newline,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+	pub kind: TokenKind,
+	pub span: Span,
+}
+
+fn is_ident_start(c: char) -> bool { c.is_ascii_alphabetic() || c == '_' || c == '.' }
+fn is_ident(c: char) -> bool { c.is_ascii_alphanumeric() || c == '_' || c == '.' }
+
+/// Parses a number with an optional `0x`, `0o`, or `0b` prefix.
+fn number(s: &str) -> Result<word, &'static str> {
+	let (digits, radix) = match s.get(..2) {
+		Some("0x" | "0X") => (&s[2..], 16),
+		Some("0o" | "0O") => (&s[2..],  8),
+		Some("0b" | "0B") => (&s[2..],  2),
+		_ => (s, 10),
+	};
+	
+	match u64::from_str_radix(digits, radix) {
+		Ok(n) => word::try_from(n).map_err(|_| "number doesn't fit in 32 bits"),
+		Err(_) if digits.is_empty() => Err("number's missing its digits"),
+		Err(e) if matches!(e.kind(), std::num::IntErrorKind::PosOverflow) => Err("number doesn't fit in 32 bits"),
+		Err(_) => Err("invalid number"),
+	}
+}
+
+/// Scans a number starting at `start`, returning where it ends and what it
+/// was. Anything with a fraction or an exponent is a float.
+fn scan_number(chars: &[char], start: usize) -> (usize, Result<TokenKind, &'static str>) {
+	let is_digit = |i: usize| chars.get(i).is_some_and(|c| c.is_ascii_digit() || *c == '_');
+	let mut end = start;
+	let mut float = false;
+	
+	let prefixed = chars[start] == '0' && chars.get(start + 1).is_some_and(|c| "xXoObB".contains(*c));
+	if !prefixed {
+		while is_digit(end) { end += 1; }
+		if chars.get(end) == Some(&'.') && is_digit(end + 1) {
+			float = true;
+			end += 1;
+			while is_digit(end) { end += 1; }
+		}
+		if chars.get(end).is_some_and(|c| matches!(c, 'e' | 'E')) {
+			let sign = matches!(chars.get(end + 1), Some('+' | '-')) as usize;
+			if is_digit(end + 1 + sign) {
+				float = true;
+				end += 1 + sign;
+				while is_digit(end) { end += 1; }
+			}
+		}
+	}
+	
+	// anything stuck to the end makes it a bad number, rather than a number
+	// and then a label
+	while chars.get(end).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_') { end += 1; }
+	
+	let s: String = chars[start..end].iter().filter(|&&c| c != '_').collect();
+	let kind = if float {
+		s.parse().map(TokenKind::Float).map_err(|_| "invalid number")
+	} else {
+		number(&s).map(TokenKind::Int)
+	};
+	(end, kind)
+}
+
+/// Scans a string (or a character) starting at the quote at `start`,
+/// returning where it ends and its contents. Errors come with the column
+/// they're at.
+fn scan_quoted(chars: &[char], start: usize) -> (usize, Result<String, (usize, &'static str)>) {
+	let quote = chars[start];
+	let mut s = String::new();
+	let mut i = start + 1;
+	
+	loop {
+		let Some(&c) = chars.get(i) else {
+			let e = if quote == '"' { "string doesn't end" } else { "character doesn't end" };
+			return (i, Err((start, e)));
+		};
+		i += 1;
+		
+		match c {
+			c if c == quote => return (i, Ok(s)),
+			'\\' => {
+				let escaped = match chars.get(i) {
+					Some('n') => '\n',
+					Some('t') => '\t',
+					Some('r') => '\r',
+					Some('0') => '\0',
+					Some('\\') => '\\',
+					Some('"') => '"',
+					Some('\'') => '\'',
+					_ => {
+						// skip to the end so the rest of it isn't read as code
+						let end = chars[i..].iter().position(|&c| c == quote).map_or(chars.len(), |p| i + p + 1);
+						return (end, Err((i - 1, "unknown escape")));
+					},
+				};
+				s.push(escaped);
+				i += 1;
+			},
+			c => s.push(c),
+		}
+	}
+}
+
+/// Splits the whole source into tokens. Comments go away, and a line with a
+/// bad token still gets its `Newline` so the parser can carry on after it.
+pub fn tokenize(source: &str) -> (Vec<Token>, Vec<Error>) {
+	let mut tokens = Vec::new();
+	let mut errors = Vec::new();
+	
+	for (i, line) in source.lines().enumerate() {
+		let line_num = i + 1;
+		let chars: Vec<char> = line.chars().collect();
+		let mut col = 0;
+		
+		// grabs characters for as long as `f` likes them
+		let take_while = |start: usize, f: fn(char) -> bool| {
+			let mut end = start;
+			while end < chars.len() && f(chars[end]) { end += 1; }
+			end
+		};
+		
+		while col < chars.len() {
+			let c = chars[col];
+			let start = col;
+			
+			let kind = match c {
+				'#' => break,
+				c if c.is_whitespace() => { col += 1; continue; },
+				',' => { col += 1; TokenKind::Comma },
+				':' => { col += 1; TokenKind::Colon },
+				'+' => { col += 1; TokenKind::Plus },
+				'-' => { col += 1; TokenKind::Minus },
+				'(' => { col += 1; TokenKind::LParen },
+				')' => { col += 1; TokenKind::RParen },
+				'*' => { col += 1; TokenKind::Star },
+				'/' => { col += 1; TokenKind::Slash },
+				'&' => { col += 1; TokenKind::Amp },
+				'|' => { col += 1; TokenKind::Pipe },
+				'^' => { col += 1; TokenKind::Caret },
+				'~' => { col += 1; TokenKind::Tilde },
+				'<' | '>' if chars.get(col + 1) == Some(&c) => {
+					col += 2;
+					if c == '<' { TokenKind::Shl } else { TokenKind::Shr }
+				},
+				'"' | '\'' => {
+					let (end, s) = scan_quoted(&chars, col);
+					col = end;
+					match s {
+						Ok(s) if c == '"' => TokenKind::Str(s),
+						// characters are just numbers
+						Ok(s) => {
+							let mut cs = s.chars();
+							match (cs.next(), cs.next()) {
+								(Some(c), None) => TokenKind::Int(c as word),
+								_ => {
+									errors.push(Error::new(Span::new(line_num, start + 1, col - start), "characters have to be one character long"));
+									continue;
+								},
+							}
+						},
+						Err((at, e)) => {
+							let len = if at == start { end - start } else { 2 };
+							errors.push(Error::new(Span::new(line_num, at + 1, len), e));
+							continue;
+						},
+					}
+				},
+				'$' => {
+					col = take_while(col + 1, |c| c.is_ascii_alphanumeric());
+					let name: String = chars[start + 1..col].iter().collect();
+					if name.is_empty() {
+						errors.push(Error::new(Span::new(line_num, start + 1, 1), "register name missing after $"));
+						continue;
+					}
+					TokenKind::Register(name)
+				},
+				'%' => {
+					col = take_while(col + 1, is_ident);
+					if col == start + 1 {
+						TokenKind::Percent
+					} else {
+						TokenKind::Param(chars[start..col].iter().collect())
+					}
+				},
+				c if c.is_ascii_digit() => {
+					let (end, kind) = scan_number(&chars, col);
+					col = end;
+					match kind {
+						Ok(kind) => kind,
+						Err(e) => {
+							errors.push(Error::new(Span::new(line_num, start + 1, col - start), e));
+							continue;
+						},
+					}
+				},
+				c if is_ident_start(c) => {
+					col = take_while(col, is_ident);
+					let s: String = chars[start..col].iter().collect();
+					if c == '.' { TokenKind::Directive(s) } else { TokenKind::Ident(s) }
+				},
+				c => {
+					col += 1;
+					errors.push(Error::new(Span::new(line_num, start + 1, 1), format!("unexpected character `{c}`")));
+					continue;
+				},
+			};
+			
+			tokens.push(Token { kind, span: Span::new(line_num, start + 1, col - start) });
+		}
+		
+		tokens.push(Token { kind: TokenKind::Newline, span: Span::new(line_num, chars.len() + 1, 0) });
+	}
+	
+	(tokens, errors)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use TokenKind::*;
+	
+	fn kinds(source: &str) -> Vec<TokenKind> {
+		let (tokens, errors) = tokenize(source);
+		assert_eq!(errors, []);
+		tokens.into_iter().map(|t| t.kind).collect()
+	}
+	
+	#[test]
+	fn tokens() {
+		assert_eq!(kinds("loop: addi $t0,$8, 0x10 # hi\n\t.text"), [
+			Ident("loop".into()), Colon,
+			Ident("addi".into()), Register("t0".into()), Comma, Register("8".into()), Comma, Int(16),
+			Newline,
+			Directive(".text".into()),
+			Newline,
+		]);
+		
+		assert_eq!(kinds(".macro m (%a, %b_2)"), [
+			Directive(".macro".into()), Ident("m".into()),
+			LParen, Param("%a".into()), Comma, Param("%b_2".into()), RParen,
+			Newline,
+		]);
+		
+		assert_eq!(kinds(r"%hi(x) ~1<<2 % 'a' '\n' '\''"), [
+			Param("%hi".into()), LParen, Ident("x".into()), RParen,
+			Tilde, Int(1), Shl, Int(2), Percent, Int(97), Int(10), Int(39),
+			Newline,
+		]);
+		
+		assert_eq!(kinds("0b1010 0o17 1_000 4294967295"), [
+			Int(10), Int(15), Int(1000), Int(u32::MAX), Newline,
+		]);
+		
+		assert_eq!(kinds(r#".asciiz "a\tb # not a comment", -1.5e3, +2"#), [
+			Directive(".asciiz".into()), Str("a\tb # not a comment".into()), Comma,
+			Minus, Float(1500.0), Comma, Plus, Int(2),
+			Newline,
+		]);
+		
+		let (tokens, errors) = tokenize("  add ? 0xZZ 4294967296");
+		assert_eq!(tokens.len(), 2, "add and the newline");
+		assert_eq!(errors, [
+			Error::new(Span::new(1, 7, 1), "unexpected character `?`"),
+			Error::new(Span::new(1, 9, 4), "invalid number"),
+			Error::new(Span::new(1, 14, 10), "number doesn't fit in 32 bits"),
+		]);
+		
+		let (_, errors) = tokenize(r#"  "what\q" "oops"#);
+		assert_eq!(errors, [
+			Error::new(Span::new(1, 8, 2), "unknown escape"),
+			Error::new(Span::new(1, 12, 5), "string doesn't end"),
+		]);
+		
+		let (_, errors) = tokenize("'ab' 'c");
+		assert_eq!(errors, [
+			Error::new(Span::new(1, 1, 4), "characters have to be one character long"),
+			Error::new(Span::new(1, 6, 2), "character doesn't end"),
+		]);
+	}
+}
@@ -0,0 +1,103 @@
+//! An assembler for the emulator's flavor of MIPS, meant to be shared by the
+//! GUI, the command line, and tests.
+//!
+//! Source goes through [`lexer`] into tokens (with [`files`] pulling in any
+//! `.include`d ones), [`macros`] to expand any macros
+//! it uses, [`parser`] into lines of labels
+//! and statements, and then [`assemble`] does two passes over those: one to
+//! find out where every label lands, and one to encode instructions now that
+//! branches and jumps can point anywhere, forwards or backwards.
+//!
+//! Pseudo-instructions like `li` and `blt` are expanded into real ones along
+//! the way, with however many instructions they need worked out in the
+//! first pass.
+//!
+//! Files can also be assembled on their own into an [`object`] file, with
+//! the labels they use from elsewhere left for [`link`] to fill in.
+//!
+//! A [`listing`] shows where every line went and what it turned into.
+
+use std::fmt;
+
+#[allow(non_camel_case_types)]
+type word = u32;
+
+pub mod expr;
+pub mod files;
+pub mod lexer;
+pub mod macros;
+pub mod object;
+pub mod parser;
+mod encode;
+mod pseudo;
+mod assembler;
+mod linker;
+mod listing;
+
+pub use assembler::{assemble, assemble_files, assemble_object, Program, Section, Bases};
+pub use linker::link;
+pub use listing::listing;
+pub use files::{Files, FileSystem};
+
+/// Where something is in the source. Lines and columns both start at 1, like
+/// every text editor ever, and columns count characters rather than bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+	/// Which file, out of the ones that got `.include`d. The one being
+	/// assembled is 0.
+	pub file: usize,
+	pub line: usize,
+	pub column: usize,
+	pub len: usize,
+}
+
+impl Span {
+	pub const fn new(line: usize, column: usize, len: usize) -> Self {
+		Span { file: 0, line, column, len }
+	}
+	
+	/// Everything from this span to `other`, which should be on the same
+	/// line. Either can come first, since tokens from a macro can end up
+	/// pointing anywhere on the line that used it.
+	pub fn to(self, other: Span) -> Span {
+		let column = self.column.min(other.column);
+		let end = (self.column + self.len).max(other.column + other.len);
+		Span { column, len: end - column, ..self }
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+	pub span: Span,
+	/// The name of the file `span` is in, which gets filled in once
+	/// everything's been assembled.
+	pub file: String,
+	pub message: String,
+	
+	/// Other places that have something to do with it, like where the macro
+	/// that went wrong was defined.
+	pub notes: Vec<Error>,
+}
+
+impl Error {
+	pub fn new(span: Span, message: impl Into<String>) -> Self {
+		Error { span, file: String::new(), message: message.into(), notes: vec![] }
+	}
+	
+	pub fn note(mut self, span: Span, message: impl Into<String>) -> Self {
+		self.notes.push(Error::new(span, message));
+		self
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		// ones about a whole file, like from linking, aren't on any line
+		if self.span.line == 0 {
+			return write!(f, "{}", self.message);
+		}
+		write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+	}
+}
+
+impl std::error::Error for Error {}
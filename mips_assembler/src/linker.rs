@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use mips_emulator::map;
+
+use crate::{
+	word, Span, Error,
+	assembler::{Program, Section, Bases},
+	object::{Object, Symbol, Reloc, BSS},
+	expr::hi,
+};
+
+/// Where each section goes, in order. `.bss` goes after `.data`, so it has
+/// to come after it here too.
+const SECTIONS: [&str; 5] = [map::TEXT, map::DATA, BSS, map::KTEXT, map::KDATA];
+
+/// An error in a whole file, rather than anywhere in particular.
+fn error(file: &str, message: String) -> Error {
+	Error { file: file.to_owned(), ..Error::new(Span::default(), message) }
+}
+
+/// Puts `objects` together into one program, each of them being a name (for
+/// errors) and an object file. Sections start at `bases`, with each object's
+/// part of a section after the last one's, and `.bss` straight after
+/// `.data`. Globals get shared between all of them, and every relocation
+/// gets filled in.
+/// 
+/// Like [`assemble`](crate::assemble), every problem gets reported, not
+/// just the first.
+pub fn link(objects: &[(String, Object)], bases: Bases) -> Result<Program, Vec<Error>> {
+	let mut errors = vec![];
+	let mut program = Program::default();
+	
+	for (file, object) in objects {
+		for s in object.sections.iter().filter(|s| !SECTIONS.contains(&s.name.as_str())) {
+			errors.push(error(file, format!("`{file}` has a `{}` section, and there's nowhere to put it", s.name)));
+		}
+	}
+	
+	// where each object's sections ended up, as which of the program's
+	// sections and the address
+	let mut placed: Vec<Vec<Option<(usize, word)>>> = objects.iter().map(|(_, o)| vec![None; o.sections.len()]).collect();
+	for name in SECTIONS {
+		let base = match name {
+			BSS => program.sections.iter().find(|s| s.name == map::DATA).map_or(bases.data, Section::end),
+			_ => bases.of(name),
+		};
+		let mut section = Section { name, base, ..Default::default() };
+		
+		for ((_, object), placed) in objects.iter().zip(&mut placed) {
+			for (i, s) in object.sections.iter().enumerate().filter(|(_, s)| s.name == name) {
+				let addr = section.end().next_multiple_of(s.align.max(1));
+				section.bytes.resize((addr - base) as usize, 0);
+				section.bytes.extend_from_slice(&s.bytes);
+				placed[i] = Some((program.sections.len(), addr));
+			}
+		}
+		if !section.bytes.is_empty() {
+			program.sections.push(section);
+		}
+	}
+	
+	let address = |f: usize, symbol: &Symbol| symbol.section
+		.and_then(|i| placed[f][i])
+		.map(|(_, addr)| addr.wrapping_add(symbol.value));
+	
+	// every global that's defined somewhere, and which file it's from
+	let mut globals: BTreeMap<&str, (usize, word)> = BTreeMap::new();
+	for (f, (file, object)) in objects.iter().enumerate() {
+		for symbol in object.symbols.iter().filter(|s| s.global) {
+			let Some(addr) = address(f, symbol) else { continue };
+			match globals.get(symbol.name.as_str()) {
+				Some(&(other, _)) => errors.push(error(file, format!(
+					"`{}` is defined in both `{}` and `{file}`", symbol.name, objects[other].0,
+				))),
+				None => { globals.insert(&symbol.name, (f, addr)); },
+			}
+		}
+	}
+	
+	for (f, (file, object)) in objects.iter().enumerate() {
+		// only say once that something's missing, however much it's used
+		let mut missing = BTreeSet::new();
+		
+		for (i, s) in object.sections.iter().enumerate() {
+			let Some((c, base)) = placed[f][i] else { continue };
+			
+			for (r, rel) in s.relocations.iter().enumerate() {
+				let symbol = &object.symbols[rel.symbol];
+				let name = &symbol.name;
+				let Some(target) = address(f, symbol).or_else(|| globals.get(name.as_str()).map(|&(_, addr)| addr)) else {
+					if missing.insert(name) {
+						errors.push(error(file, format!("`{file}` uses `{name}`, which isn't defined anywhere")));
+					}
+					continue;
+				};
+				
+				let at = rel.offset as usize;
+				let read = |at: usize| word::from_le_bytes(s.bytes[at..at + 4].try_into().unwrap());
+				let old = read(at);
+				let pc = base.wrapping_add(rel.offset);
+				
+				let new = match rel.kind {
+					Reloc::Word => old.wrapping_add(target),
+					Reloc::Hi => {
+						// what's added to the symbol is this half and the one in
+						// the `%lo` that goes with it
+						let lo = s.relocations[r + 1..].iter().find(|l| l.kind == Reloc::Lo && l.symbol == rel.symbol);
+						let Some(lo) = lo else {
+							errors.push(error(file, format!("`{file}` has a `%hi` of `{name}` without a `%lo` after it")));
+							continue;
+						};
+						let addend = (old << 16).wrapping_add(read(lo.offset as usize) as u16 as i16 as word);
+						old & 0xFFFF_0000 | hi(target.wrapping_add(addend))
+					},
+					Reloc::Lo => old & 0xFFFF_0000 | target.wrapping_add(old as u16 as i16 as word) & 0xFFFF,
+					Reloc::Jump => {
+						let to = target.wrapping_add(((old << 6) as i32 >> 4) as word);
+						// jumps only replace the low 28 bits of the PC
+						if to & 0xF000_0000 != pc.wrapping_add(4) & 0xF000_0000 {
+							errors.push(error(file, format!("`{file}` has a jump at {pc:#010X} to {to:#010X}, which is too far away")));
+							continue;
+						}
+						old & 0xFC00_0000 | (to >> 2) & 0x03FF_FFFF
+					},
+				};
+				
+				let section = &mut program.sections[c];
+				let at = (pc - section.base) as usize;
+				section.bytes[at..at + 4].copy_from_slice(&new.to_le_bytes());
+			}
+		}
+	}
+	
+	if !errors.is_empty() {
+		return Err(errors);
+	}
+	
+	for (name, (_, addr)) in globals {
+		program.labels.insert(name.to_owned(), addr);
+		program.globals.insert(name.to_owned());
+	}
+	program.files = objects.iter().map(|(name, _)| name.clone()).collect();
+	Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{assemble, assemble_object};
+	
+	fn object(name: &str, source: &str) -> (String, Object) {
+		let files = BTreeMap::from([(name.to_owned(), source.to_owned())]);
+		(name.to_owned(), assemble_object(&files, name).unwrap())
+	}
+	
+	fn messages(errors: Vec<Error>) -> Vec<String> {
+		errors.iter().map(ToString::to_string).collect()
+	}
+	
+	#[test]
+	fn linking() {
+		let a = object("a.asm", "
+			.globl main
+			main:   la   $a0, message
+			        lw   $t0, count
+			        jal  print
+			        j    main
+			.data
+			count:  .word 3, message + 1
+			.bss
+			buffer: .space 16
+		");
+		let b = object("b.asm", "
+			.globl print, message
+			print:  beq  $t0, $zero, done
+			        addi $t0, $t0, -1
+			        j    print
+			done:   jr   $ra
+			.data
+			        .byte 1
+			message: .asciiz \"hi\"
+		");
+		
+		let text = &a.1.section(map::TEXT).unwrap().relocations;
+		let kinds: Vec<_> = text.iter().map(|r| (r.offset, r.kind, a.1.symbols[r.symbol].name.as_str())).collect();
+		assert_eq!(kinds, [
+			(0x00, Reloc::Hi, "message"),
+			(0x04, Reloc::Lo, "message"),
+			(0x08, Reloc::Hi, map::DATA),
+			(0x0C, Reloc::Lo, map::DATA),
+			(0x10, Reloc::Jump, "print"),
+			(0x14, Reloc::Jump, map::TEXT),
+		]);
+		
+		let program = link(&[a.clone(), b.clone()], Bases::default()).unwrap();
+		let expected = assemble("
+			lui   $at, 0x1001
+			addiu $a0, $at, 9
+			lui   $at, 0x1001
+			lw    $t0, 0($at)
+			jal   0x0040_0018
+			j     0x0040_0000
+			beq   $t0, $zero, 2
+			addi  $t0, $t0, -1
+			j     0x0040_0018
+			jr    $ra
+		", Bases::default()).unwrap();
+		assert_eq!(program.image(map::TEXT), expected.image(map::TEXT));
+		assert_eq!(program.image(map::DATA), Some((0x1001_0000, vec![3, 0, 0, 0, 0x0A, 0x00, 0x01, 0x10, 1, b'h', b'i', 0])));
+		assert_eq!(program.image(BSS), Some((0x1001_000C, vec![0; 16])));
+		assert_eq!(program.labels, BTreeMap::from([
+			("main".into(), 0x0040_0000),
+			("message".into(), 0x1001_0009),
+			("print".into(), 0x0040_0018),
+		]));
+		
+		// and it all survives being written out and read back in
+		let reread = |(name, o): (String, Object)| (name, Object::from_elf(&o.to_elf()).unwrap());
+		assert_eq!(link(&[reread(a), reread(b)], Bases::default()), Ok(program));
+	}
+	
+	#[test]
+	fn lo_offsets() {
+		let source = "
+			.globl main
+			main:   lui  $t0, %hi(table)
+			        lw   $t1, %lo(table)($t0)
+			.data
+			        .space 0x8000
+			table:  .word 7
+		";
+		let expected = assemble("
+			lui   $t0, 0x1002
+			lw    $t1, -0x8000($t0)
+		", Bases::default()).unwrap();
+		assert_eq!(assemble(source, Bases::default()).unwrap().image(map::TEXT), expected.image(map::TEXT));
+		
+		let a = object("a.asm", source);
+		let text = &a.1.section(map::TEXT).unwrap().relocations;
+		let kinds: Vec<_> = text.iter().map(|r| (r.offset, r.kind, a.1.symbols[r.symbol].name.as_str())).collect();
+		assert_eq!(kinds, [(0x00, Reloc::Hi, map::DATA), (0x04, Reloc::Lo, map::DATA)]);
+		assert_eq!(link(&[a], Bases::default()).unwrap().image(map::TEXT), expected.image(map::TEXT));
+	}
+	
+	#[test]
+	fn errors() {
+		let a = object("a.asm", ".globl main, value\nmain: lw $t0, value\nvalue: .word missing, missing");
+		let b = object("b.asm", ".globl main\nmain: jal nowhere");
+		let mut c = object("c.asm", ".globl far\nfar: j far");
+		c.1.sections[0].name = ".rodata".into();
+		
+		assert_eq!(messages(link(&[a, b, c], Bases::default()).unwrap_err()), [
+			"`c.asm` has a `.rodata` section, and there's nowhere to put it",
+			"`main` is defined in both `a.asm` and `b.asm`",
+			"`a.asm` uses `missing`, which isn't defined anywhere",
+			"`b.asm` uses `nowhere`, which isn't defined anywhere",
+		]);
+		
+		let d = object("d.asm", "lui $t0, %hi(somewhere)");
+		let e = object("e.asm", ".globl somewhere\n.ktext\nsomewhere: j somewhere\n.text\nj somewhere");
+		assert_eq!(messages(link(&[d, e], Bases::default()).unwrap_err()), [
+			"`d.asm` has a `%hi` of `somewhere` without a `%lo` after it",
+			"`e.asm` has a jump at 0x00400004 to 0x80000000, which is too far away",
+		]);
+	}
+}
@@ -0,0 +1,276 @@
+//! Listings: every line of source with where it went and what it turned
+//! into, like assemblers have printed since forever, and a cross-reference
+//! of every label at the end.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use mips_emulator::{chip::{Cpu, Register}, map};
+
+use crate::{word, Span, Files, assembler::{Program, Section}};
+
+/// How many bytes of data go on each row.
+const ROW_BYTES: usize = 8;
+
+/// One row: the address, the words or bytes, the line number, and the text,
+/// with whatever's missing left blank.
+fn row(out: &mut String, addr: Option<word>, code: &str, line: Option<usize>, text: &str) {
+	let addr = addr.map_or_else(|| " ".repeat(10), |a| format!("{a:#010X}"));
+	let line = line.map_or_else(String::new, |l| l.to_string());
+	let row = format!("{addr}  {code:23}  {line:>5}  {text}");
+	out.push_str(row.trim_end());
+	out.push('\n');
+}
+
+/// Data, `ROW_BYTES` to a row, with the first row's text being `text`.
+fn data(out: &mut String, addr: word, bytes: &[u8], line: Option<usize>, text: &str) {
+	let mut rows = bytes.chunks(ROW_BYTES);
+	let first = rows.next().unwrap_or_default();
+	let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ");
+	row(out, Some(addr), &hex(first), line, text);
+	for (i, bytes) in rows.enumerate() {
+		row(out, Some(addr + ((i + 1) * ROW_BYTES) as word), &hex(bytes), None, "");
+	}
+}
+
+/// Which section a statement's in, where, and how big it is.
+type Placed<'a> = (&'a Section, word, word);
+
+/// Where a statement is, and its bytes.
+fn piece<'a>(&(section, addr, size): &Placed<'a>) -> (word, &'a [u8]) {
+	let at = (addr - section.base) as usize;
+	(addr, &section.bytes[at..at + size as usize])
+}
+
+/// What `w` disassembles to, written the way it'd be written in source.
+/// Loads and stores get their offset in front of the base, and branches
+/// get their registers the right way around and their offset in
+/// instructions.
+fn disassemble(w: word) -> String {
+	let reg = |at: u32| Register::from((w >> at & 0x1F) as u8);
+	let (rs, rt) = (reg(21), reg(16));
+	let offset = w as u16 as i16;
+	match Cpu::get_instruction_info(w).map(|(name, _)| name) {
+		Some(name @ ("lw" | "lbu" | "lhu" | "sb" | "sh" | "sw" | "ll" | "sc")) => format!("{name} ${rt:?}, {offset}(${rs:?})"),
+		Some(name @ ("beq" | "bne")) => format!("{name} ${rs:?}, ${rt:?}, {offset}"),
+		_ => Cpu::get_disassembly(w).unwrap_or_default(),
+	}
+}
+
+/// Instructions that came out of something else, each with what it
+/// disassembles to, indented under it.
+fn expansion(out: &mut String, addr: word, bytes: &[u8]) {
+	for (i, w) in bytes.chunks_exact(4).enumerate() {
+		let w = word::from_le_bytes(w.try_into().unwrap());
+		let text = format!("    {}", disassemble(w));
+		row(out, Some(addr + 4 * i as word), &format!("{w:#010X}"), None, &text);
+	}
+}
+
+/// Every line of every file that went into `program`, with the address it
+/// ended up at and the words (or bytes, for data) it turned into. Anything
+/// that turned into more than it says, like pseudo-instructions and macros,
+/// gets the real instructions listed underneath it. A table of every label,
+/// where it was defined, and where it was used comes after all that.
+/// 
+/// `files` should be the same as what `program` was assembled from, for the
+/// source.
+pub fn listing(program: &Program, files: &dyn Files) -> String {
+	let mut out = String::new();
+	
+	// what each line turned into, by file and line
+	let mut statements: BTreeMap<(usize, usize), Vec<Placed>> = BTreeMap::new();
+	for section in &program.sections {
+		for &(addr, size, span) in &section.spans {
+			statements.entry((span.file, span.line)).or_default().push((section, addr, size));
+		}
+	}
+	// and the ones that only have labels on them
+	let mut labels = BTreeMap::new();
+	for (name, span) in &program.definitions {
+		labels.entry((span.file, span.line)).or_insert(program.labels[name]);
+	}
+	
+	for (f, name) in program.files.iter().enumerate() {
+		if program.files.len() > 1 {
+			let _ = writeln!(out, "{}{name}", if f > 0 { "\n" } else { "" });
+		}
+		let source = files.read(name).unwrap_or_default();
+		
+		for (line, text) in source.lines().enumerate().map(|(l, t)| (l + 1, t)) {
+			let Some(here) = statements.get(&(f, line)) else {
+				row(&mut out, labels.get(&(f, line)).copied(), "", Some(line), text);
+				continue;
+			};
+			
+			let code = |section: &Section| section.name == map::TEXT || section.name == map::KTEXT;
+			// what it says it is, to tell real instructions from pseudo ones
+			let mnemonic = text.split_whitespace()
+				.find(|w| !w.ends_with(':'))
+				.unwrap_or_default()
+				.to_ascii_lowercase();
+			
+			match &here[..] {
+				[(section, ..)] if !code(section) || mnemonic.starts_with('.') => {
+					let (addr, bytes) = piece(&here[0]);
+					// there's no point listing all the zeroes
+					let bytes = if mnemonic == ".space" { &[] } else { bytes };
+					data(&mut out, addr, bytes, Some(line), text);
+				},
+				[one] => {
+					let (addr, bytes) = piece(one);
+					let w = bytes.try_into().map(word::from_le_bytes).ok();
+					let real = w.and_then(Cpu::get_instruction_info).is_some_and(|(name, _)| name == mnemonic);
+					if let (Some(w), true) = (w, real) {
+						row(&mut out, Some(addr), &format!("{w:#010X}"), Some(line), text);
+					} else {
+						row(&mut out, Some(addr), "", Some(line), text);
+						expansion(&mut out, addr, bytes);
+					}
+				},
+				// a macro, which could be anything
+				_ => {
+					row(&mut out, Some(here[0].1), "", Some(line), text);
+					for p in here {
+						let (addr, bytes) = piece(p);
+						match code(p.0) {
+							true => expansion(&mut out, addr, bytes),
+							false => data(&mut out, addr, bytes, None, ""),
+						}
+					}
+				},
+			}
+		}
+	}
+	
+	cross_reference(&mut out, program);
+	out
+}
+
+/// Every label, where it is, where it was defined, and every line that uses
+/// it.
+fn cross_reference(out: &mut String, program: &Program) {
+	if program.labels.is_empty() {
+		return;
+	}
+	
+	// which file only matters if there's more than one
+	let place = |s: &Span| match program.files.len() {
+		0 | 1 => s.line.to_string(),
+		_ => format!("{}:{}", program.files[s.file], s.line),
+	};
+	let rows: Vec<_> = program.labels.iter().map(|(name, addr)| {
+		let defined = program.definitions.get(name).map(place).unwrap_or_default();
+		let mut uses: Vec<_> = program.uses.get(name).into_iter().flatten().map(|s| (s.file, s.line, place(s))).collect();
+		uses.sort();
+		uses.dedup();
+		let uses: Vec<_> = uses.into_iter().map(|(_, _, p)| p).collect();
+		(name, addr, defined, uses.join(", "))
+	}).collect();
+	
+	let name_width = rows.iter().map(|r| r.0.chars().count()).chain([5]).max().unwrap();
+	let defined_width = rows.iter().map(|r| r.2.len()).chain([7]).max().unwrap();
+	let _ = writeln!(out, "\n{:name_width$}  address     {:defined_width$}  used", "label", "defined");
+	for (name, addr, defined, uses) in rows {
+		let row = format!("{name:name_width$}  {addr:#010X}  {defined:defined_width$}  {uses}");
+		let _ = writeln!(out, "{}", row.trim_end());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{assemble_files, Bases};
+	
+	#[test]
+	fn expansions() {
+		let main = "\
+loop:	lw   $t1, value
+	sw   $t1, -4($sp)
+	blt  $t0, $t1, loop
+.data
+value:	.word 7
+";
+		let files = BTreeMap::from([("main.asm".to_owned(), main.to_owned())]);
+		let program = assemble_files(&files, "main.asm", Bases::default()).unwrap();
+		
+		assert_eq!(super::listing(&program, &files), "\
+0x00400000                               1  loop:	lw   $t1, value
+0x00400000  0x3C011001                          lui $at, 0x1001
+0x00400004  0x8C290000                          lw $t1, 0($at)
+0x00400008  0xAFA9FFFC                   2  	sw   $t1, -4($sp)
+0x0040000C                               3  	blt  $t0, $t1, loop
+0x0040000C  0x0109082A                          slt $at, $t0, $t1
+0x00400010  0x1420FFFB                          bne $at, $zero, -5
+                                         4  .data
+0x10010000  07 00 00 00                  5  value:	.word 7
+
+label  address     defined  used
+loop   0x00400000  1        3
+value  0x10010000  5        1
+");
+	}
+	
+	#[test]
+	fn listing() {
+		let main = "\
+.macro twice (%r)
+	addu %r, %r, %r
+	addu %r, %r, %r
+.end_macro
+.globl main
+main:	la   $a0, message   # the message
+	addi $t0, $zero, 3
+loop:	twice ($t0)
+	j    loop
+.data
+flag:	.byte 1
+count:	.word 3, loop
+message:
+	.asciiz \"hello there\"
+	.space 64
+.include \"more.asm\"
+";
+		let files = BTreeMap::from([
+			("main.asm".to_owned(), main.to_owned()),
+			("more.asm".to_owned(), "more:\t.half 7\n\t.word count\n".to_owned()),
+		]);
+		let program = assemble_files(&files, "main.asm", Bases::default()).unwrap();
+		
+		assert_eq!(super::listing(&program, &files), "\
+main.asm
+                                         1  .macro twice (%r)
+                                         2  	addu %r, %r, %r
+                                         3  	addu %r, %r, %r
+                                         4  .end_macro
+                                         5  .globl main
+0x00400000                               6  main:	la   $a0, message   # the message
+0x00400000  0x3C011001                          lui $at, 0x1001
+0x00400004  0x3424000C                          ori $a0, $at, 0xC
+0x00400008  0x20080003                   7  	addi $t0, $zero, 3
+0x0040000C                               8  loop:	twice ($t0)
+0x0040000C  0x01084021                          addu $t0, $t0, $t0
+0x00400010  0x01084021                          addu $t0, $t0, $t0
+0x00400014  0x08100003                   9  	j    loop
+                                        10  .data
+0x10010000  01                          11  flag:	.byte 1
+0x10010004  03 00 00 00 0C 00 40 00     12  count:	.word 3, loop
+0x1001000C                              13  message:
+0x1001000C  68 65 6C 6C 6F 20 74 68     14  	.asciiz \"hello there\"
+0x10010014  65 72 65 00
+0x10010018                              15  	.space 64
+                                        16  .include \"more.asm\"
+
+more.asm
+0x10010058  07 00                        1  more:	.half 7
+0x1001005C  04 00 01 10                  2  	.word count
+
+label    address     defined      used
+count    0x10010004  main.asm:12  more.asm:2
+flag     0x10010000  main.asm:11
+loop     0x0040000C  main.asm:8   main.asm:9, main.asm:12
+main     0x00400000  main.asm:6   main.asm:5
+message  0x1001000C  main.asm:13  main.asm:6
+more     0x10010058  more.asm:1
+");
+	}
+}
@@ -0,0 +1,343 @@
+//! MARS-style macros. They get expanded on tokens, before anything's parsed,
+//! so a macro's body can be anything at all until it's used:
+//! 
+//! ```text
+//! .macro print_int (%val)
+//!     li $v0, 1
+//!     la $a0, %val
+//!     syscall
+//! .end_macro
+//! 
+//! print_int (42)
+//! ```
+//! 
+//! Macros with the same name but a different number of parameters are
+//! different macros. Labels defined inside a macro get a suffix that's
+//! different every time it's used, so loops in macros don't clash.
+
+use std::collections::HashMap;
+
+use crate::{Span, Error, lexer::{Token, TokenKind}};
+
+#[derive(Clone, Debug)]
+struct Macro {
+	/// The name in the `.macro`, for pointing at in errors.
+	span: Span,
+	params: Vec<String>,
+	/// Each line of it, `Newline`s and all.
+	body: Vec<Vec<Token>>,
+	/// Labels defined in the body, which get renamed every time.
+	locals: Vec<String>,
+}
+
+#[derive(Default)]
+struct Expander {
+	macros: HashMap<(String, usize), Macro>,
+	tokens: Vec<Token>,
+	errors: Vec<Error>,
+	/// How many times any macro's been used, for making labels unique.
+	expansions: usize,
+}
+
+fn is_directive(line: &[Token], name: &str) -> bool {
+	matches!(line.first(), Some(Token { kind: TokenKind::Directive(d), .. }) if d.eq_ignore_ascii_case(name))
+}
+
+/// How many tokens the labels at the start of a line take up.
+pub(crate) fn labels_len(line: &[Token]) -> usize {
+	line.chunks(2)
+		.take_while(|pair| matches!(pair, [Token { kind: TokenKind::Ident(_), .. }, Token { kind: TokenKind::Colon, .. }]))
+		.count() * 2
+}
+
+/// Drops the `Newline` off the end of a line.
+fn content(line: &[Token]) -> &[Token] {
+	match line {
+		[rest @ .., Token { kind: TokenKind::Newline, .. }] => rest,
+		_ => line,
+	}
+}
+
+/// Splits up a macro call's arguments. They can be in parentheses or not,
+/// and if there aren't any commas, whitespace separates them instead.
+fn split_args(tokens: &[Token]) -> Vec<Vec<Token>> {
+	let tokens = match tokens {
+		[Token { kind: TokenKind::LParen, .. }, inner @ .., Token { kind: TokenKind::RParen, .. }] => inner,
+		_ => tokens,
+	};
+	let commas = tokens.iter().any(|t| t.kind == TokenKind::Comma);
+	
+	let mut args: Vec<Vec<Token>> = vec![];
+	let mut depth = 0;
+	let mut fresh = true;
+	let mut end = 0;
+	for t in tokens {
+		let spaced = t.span.column > end;
+		end = t.span.column + t.span.len;
+		match t.kind {
+			TokenKind::Comma if depth == 0 => { fresh = true; continue; },
+			TokenKind::LParen => depth += 1,
+			TokenKind::RParen => depth -= 1,
+			_ => (),
+		}
+		
+		// a sign sticks to whatever comes after it, space or not
+		let signed = args.last().and_then(|a| a.last())
+			.is_some_and(|last| matches!(last.kind, TokenKind::Plus | TokenKind::Minus));
+		if fresh || !commas && spaced && depth == 0 && !signed && t.kind != TokenKind::RParen {
+			args.push(vec![]);
+			fresh = false;
+		}
+		args.last_mut().unwrap().push(t.clone());
+	}
+	args
+}
+
+impl Expander {
+	/// Reads a `.macro` line, and the lines up to its `.end_macro`.
+	fn define(&mut self, line: &[Token], body: Vec<Vec<Token>>) {
+		let (name, span, rest) = match content(line) {
+			[_, Token { kind: TokenKind::Ident(name), span }, rest @ ..] => (name, *span, rest),
+			_ => {
+				self.errors.push(Error::new(line[0].span, "expected `.macro name (%params)`"));
+				return;
+			},
+		};
+		let rest = match rest {
+			[Token { kind: TokenKind::LParen, .. }, inner @ .., Token { kind: TokenKind::RParen, .. }] => inner,
+			_ => rest,
+		};
+		
+		let mut params: Vec<String> = vec![];
+		for t in rest {
+			match &t.kind {
+				TokenKind::Param(p) if params.contains(p) => {
+					self.errors.push(Error::new(t.span, format!("`{p}` is already a parameter")));
+				},
+				TokenKind::Param(p) => params.push(p.clone()),
+				TokenKind::Comma => (),
+				_ => {
+					self.errors.push(Error::new(t.span, "expected a parameter, like `%name`"));
+					return;
+				},
+			}
+		}
+		
+		let locals = body.iter()
+			.flat_map(|line| line[..labels_len(line)].iter().step_by(2))
+			.filter_map(|t| match &t.kind {
+				TokenKind::Ident(l) => Some(l.clone()),
+				_ => None,
+			})
+			.collect();
+		
+		let key = (name.clone(), params.len());
+		if let Some(old) = self.macros.get(&key) {
+			self.errors.push(
+				Error::new(span, format!("`{name}` with {} parameters is already defined", params.len()))
+					.note(old.span, "it was defined here first")
+			);
+			return;
+		}
+		self.macros.insert(key, Macro { span, params, body, locals });
+	}
+	
+	/// Passes a line through, expanding it if it's a macro call. `stack` is
+	/// the macros it's inside of, to catch any that use themselves.
+	fn line(&mut self, line: Vec<Token>, stack: &mut Vec<(String, usize)>) {
+		let labels = labels_len(&line);
+		let (name, rest) = match content(&line)[labels..] {
+			[Token { kind: TokenKind::Ident(ref name), .. }, ref rest @ ..]
+				if self.macros.keys().any(|(n, _)| n == name) => (name.clone(), rest),
+			_ => {
+				self.tokens.extend(line);
+				return;
+			},
+		};
+		
+		// everything the macro turns into points back at the call
+		let call = content(&line)[labels..].iter().map(|t| t.span).reduce(Span::to).unwrap();
+		let args = split_args(rest);
+		let key = (name, args.len());
+		
+		let Some(m) = self.macros.get(&key).cloned() else {
+			let mut counts: Vec<_> = self.macros.keys().filter(|(n, _)| *n == key.0).map(|(_, c)| c.to_string()).collect();
+			counts.sort();
+			let s = if counts == ["1"] { "" } else { "s" };
+			self.errors.push(Error::new(call, format!(
+				"`{}` takes {} argument{s}, not {}", key.0, counts.join(" or "), args.len()
+			)));
+			return;
+		};
+		
+		if stack.contains(&key) {
+			self.errors.push(
+				Error::new(call, format!("`{}` ends up using itself, so it'd never stop expanding", key.0))
+					.note(m.span, format!("`{}` is defined here", key.0))
+			);
+			return;
+		}
+		
+		// labels on the same line stick to whatever the macro turns into
+		if labels > 0 {
+			self.tokens.extend_from_slice(&line[..labels]);
+			self.tokens.push(Token { kind: TokenKind::Newline, span: call });
+		}
+		
+		self.expansions += 1;
+		let suffix = format!("_M{}", self.expansions);
+		stack.push(key);
+		for body_line in &m.body {
+			let mut out = Vec::with_capacity(body_line.len());
+			for t in body_line {
+				match &t.kind {
+					TokenKind::Param(p) => match m.params.iter().position(|q| q == p) {
+						// arguments keep pointing at themselves
+						Some(i) => out.extend(args[i].iter().cloned()),
+						None if p == "%hi" || p == "%lo" => out.push(Token { kind: t.kind.clone(), span: call }),
+						None => self.errors.push(
+							Error::new(call, format!("`{p}` isn't one of `{}`'s parameters", stack.last().unwrap().0))
+								.note(t.span, format!("`{p}` is used here"))
+						),
+					},
+					TokenKind::Ident(l) if m.locals.contains(l) =>
+						out.push(Token { kind: TokenKind::Ident(format!("{l}{suffix}")), span: call }),
+					kind => out.push(Token { kind: kind.clone(), span: call }),
+				}
+			}
+			self.line(out, stack);
+		}
+		stack.pop();
+	}
+}
+
+/// Takes out every macro definition, and replaces every use of one with
+/// what it stands for.
+pub fn expand(tokens: &[Token]) -> (Vec<Token>, Vec<Error>) {
+	let mut x = Expander::default();
+	let mut lines = tokens.split_inclusive(|t| t.kind == TokenKind::Newline);
+	
+	while let Some(line) = lines.next() {
+		if is_directive(line, ".macro") {
+			let mut body = vec![];
+			let mut ended = false;
+			for inner in lines.by_ref() {
+				if is_directive(inner, ".end_macro") {
+					ended = true;
+					break;
+				} else if is_directive(inner, ".macro") {
+					x.errors.push(Error::new(inner[0].span, "macros can't be defined inside other macros"));
+				} else {
+					body.push(inner.to_vec());
+				}
+			}
+			
+			if !ended {
+				x.errors.push(Error::new(line[0].span, "`.macro` is missing its `.end_macro`"));
+			}
+			x.define(line, body);
+		} else if is_directive(line, ".end_macro") {
+			x.errors.push(Error::new(line[0].span, "`.end_macro` without a `.macro`"));
+		} else {
+			x.line(line.to_vec(), &mut vec![]);
+		}
+	}
+	
+	(x.tokens, x.errors)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{assemble, Bases, Span};
+	
+	fn text(source: &str) -> Vec<u8> {
+		assemble(source, Bases::default()).unwrap().sections[0].bytes.clone()
+	}
+	
+	#[test]
+	fn expansion() {
+		let source = "
+			.macro print_int (%val)
+				li $v0, 1
+				li $a0, %val
+				syscall
+			.end_macro
+			.macro twice (%body)
+				%body ()
+				%body ()
+			.end_macro
+			.macro hello ()
+				print_int (-7)
+			.end_macro
+			.macro count (%reg, %to)
+				li %reg, 0
+			loop:	addi %reg, %reg, 1
+				blt %reg, %to, loop
+			.end_macro
+			
+			main:	print_int (5)
+				twice (hello)
+				count $t0 10
+				count ($t1, 3)
+				j main
+		";
+		let expanded = "
+			main:	li $v0, 1
+				li $a0, 5
+				syscall
+				li $v0, 1
+				li $a0, -7
+				syscall
+				li $v0, 1
+				li $a0, -7
+				syscall
+				li $t0, 0
+			a:	addi $t0, $t0, 1
+				blt $t0, 10, a
+				li $t1, 0
+			b:	addi $t1, $t1, 1
+				blt $t1, 3, b
+				j main
+		";
+		assert_eq!(text(source), text(expanded));
+		
+		let program = assemble(source, Bases::default()).unwrap();
+		assert_eq!(program.labels["main"], 0x0040_0000);
+		assert_eq!(program.labels["loop_M7"], 0x0040_0028);
+		assert_eq!(program.labels["loop_M8"], 0x0040_003C);
+	}
+	
+	#[test]
+	fn errors() {
+		let source = "
+.macro bad (%a)
+	li $t0, %b
+.end_macro
+.macro forever ()
+	again ()
+.end_macro
+.macro again ()
+	forever ()
+.end_macro
+	bad (1)
+	forever ()
+	bad 1 2
+.end_macro
+.macro oops
+";
+		let errors = assemble(source, Bases::default()).unwrap_err();
+		let errors: Vec<_> = errors.iter()
+			.map(|e| (e.to_string(), e.notes.iter().map(|n| (n.span, n.message.clone())).collect::<Vec<_>>()))
+			.collect();
+		assert_eq!(errors, [
+			("11:2: `%b` isn't one of `bad`'s parameters".into(), vec![(Span::new(3, 10, 2), "`%b` is used here".into())]),
+			("11:2: expected `li $reg, imm`".into(), vec![]),
+			("12:2: `forever` ends up using itself, so it'd never stop expanding".into(), vec![
+				(Span::new(5, 8, 7), "`forever` is defined here".into()),
+			]),
+			("13:2: `bad` takes 1 argument, not 2".into(), vec![]),
+			("14:1: `.end_macro` without a `.macro`".into(), vec![]),
+			("15:1: `.macro` is missing its `.end_macro`".into(), vec![]),
+		]);
+	}
+}
@@ -0,0 +1,448 @@
+//! Relocatable object files, for programs split over files that get
+//! assembled on their own and [linked](crate::link) together afterwards.
+//!
+//! They're read and written as 32-bit little-endian MIPS ELF objects, so
+//! `readelf` and `objdump` can look inside them too. Relocations are the
+//! `REL` kind, with what gets added to the symbol already sitting in the
+//! bytes they fix up, like every other MIPS assembler does it.
+
+use crate::{word, assembler::MAX_SPACE};
+
+/// Where uninitialized data goes. It takes up no room in the file, and is
+/// all zeroes once it's loaded.
+pub const BSS: &str = ".bss";
+
+/// How a relocation fixes up the place it points at, once its symbol's
+/// address is known. The numbers are ELF's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reloc {
+	/// `R_MIPS_32`, a whole word, like `.word label`.
+	Word = 2,
+	/// `R_MIPS_26`, where a `j` or `jal` goes.
+	Jump = 4,
+	/// `R_MIPS_HI16`, a `%hi`. What's added to the symbol is split between
+	/// this and the `%lo` after it.
+	Hi = 5,
+	/// `R_MIPS_LO16`, a `%lo`.
+	Lo = 6,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Relocation {
+	/// From the start of the section.
+	pub offset: word,
+	pub kind: Reloc,
+	/// An index into the object's `symbols`.
+	pub symbol: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ObjectSection {
+	pub name: String,
+	pub align: word,
+	/// Zeroes, for `.bss`.
+	pub bytes: Vec<u8>,
+	pub relocations: Vec<Relocation>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbol {
+	/// A local symbol with its section's name stands for the section itself,
+	/// which is what relocations to local labels are relative to.
+	pub name: String,
+	/// An index into the object's `sections`, or `None` if it's in some
+	/// other file.
+	pub section: Option<usize>,
+	/// From the start of the section.
+	pub value: word,
+	pub global: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Object {
+	pub sections: Vec<ObjectSection>,
+	pub symbols: Vec<Symbol>,
+}
+
+const ET_REL: u16 = 1;
+const EM_MIPS: u16 = 8;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+const HEADER_SIZE: usize = 52;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 16;
+
+fn put16(out: &mut Vec<u8>, n: u16) { out.extend_from_slice(&n.to_le_bytes()); }
+fn put32(out: &mut Vec<u8>, n: u32) { out.extend_from_slice(&n.to_le_bytes()); }
+
+/// A string table, which ELF wants to start with an empty string.
+struct Strings(Vec<u8>);
+
+impl Strings {
+	fn new() -> Self { Strings(vec![0]) }
+	
+	fn add(&mut self, s: &str) -> u32 {
+		if s.is_empty() {
+			return 0;
+		}
+		let at = self.0.len() as u32;
+		self.0.extend_from_slice(s.as_bytes());
+		self.0.push(0);
+		at
+	}
+}
+
+/// One section header, and what goes in the section.
+struct Header {
+	name: u32,
+	kind: u32,
+	flags: u32,
+	data: Vec<u8>,
+	/// Where it is in the file, once that's known.
+	offset: usize,
+	/// Only different from `data`'s length for `.bss`.
+	size: usize,
+	link: u32,
+	info: u32,
+	align: u32,
+	entsize: u32,
+}
+
+impl Object {
+	pub fn section(&self, name: &str) -> Option<&ObjectSection> {
+		self.sections.iter().find(|s| s.name == name)
+	}
+	
+	fn is_section_symbol(&self, s: &Symbol) -> bool {
+		!s.global && s.section.is_some_and(|i| self.sections[i].name == s.name)
+	}
+	
+	pub fn to_elf(&self) -> Vec<u8> {
+		// locals have to come before globals, and 0 is no symbol at all
+		let order: Vec<usize> = (0..self.symbols.len()).filter(|&i| !self.symbols[i].global)
+			.chain((0..self.symbols.len()).filter(|&i| self.symbols[i].global))
+			.collect();
+		let mut index = vec![0; self.symbols.len()];
+		for (i, &s) in order.iter().enumerate() {
+			index[s] = i as u32 + 1;
+		}
+		let locals = 1 + self.symbols.iter().filter(|s| !s.global).count() as u32;
+		
+		// null, the sections, their relocations, then the tables
+		let with_relocations = self.sections.iter().filter(|s| !s.relocations.is_empty()).count();
+		let symtab = (1 + self.sections.len() + with_relocations) as u32;
+		
+		let mut names = Strings::new();
+		let mut strings = Strings::new();
+		let mut headers = vec![];
+		
+		for s in &self.sections {
+			let code = s.name == ".text" || s.name == ".ktext";
+			let bss = s.name == BSS;
+			headers.push(Header {
+				name: names.add(&s.name),
+				kind: if bss { SHT_NOBITS } else { SHT_PROGBITS },
+				flags: SHF_ALLOC | if code { SHF_EXECINSTR } else { SHF_WRITE },
+				data: if bss { vec![] } else { s.bytes.clone() },
+				offset: 0,
+				size: s.bytes.len(),
+				link: 0,
+				info: 0,
+				align: s.align,
+				entsize: 0,
+			});
+		}
+		
+		for (i, s) in self.sections.iter().enumerate().filter(|(_, s)| !s.relocations.is_empty()) {
+			let mut data = vec![];
+			for r in &s.relocations {
+				put32(&mut data, r.offset);
+				put32(&mut data, index[r.symbol] << 8 | r.kind as u32);
+			}
+			headers.push(Header {
+				name: names.add(&format!(".rel{}", s.name)),
+				kind: SHT_REL,
+				flags: 0,
+				offset: 0,
+				size: data.len(),
+				data,
+				link: symtab,
+				info: i as u32 + 1,
+				align: 4,
+				entsize: 8,
+			});
+		}
+		
+		let mut data = vec![0; SYMBOL_SIZE];
+		for &i in &order {
+			let s = &self.symbols[i];
+			let (name, kind) = if self.is_section_symbol(s) { (0, STT_SECTION) } else { (strings.add(&s.name), 0) };
+			let bind = if s.global { STB_GLOBAL } else { STB_LOCAL };
+			put32(&mut data, name);
+			put32(&mut data, s.value);
+			put32(&mut data, 0);
+			data.push(bind << 4 | kind);
+			data.push(0);
+			put16(&mut data, s.section.map_or(0, |i| i as u16 + 1));
+		}
+		headers.push(Header {
+			name: names.add(".symtab"), kind: SHT_SYMTAB, flags: 0, offset: 0,
+			size: data.len(), data,
+			link: symtab + 1, info: locals, align: 4, entsize: SYMBOL_SIZE as u32,
+		});
+		headers.push(Header {
+			name: names.add(".strtab"), kind: SHT_STRTAB, flags: 0, offset: 0,
+			size: strings.0.len(), data: strings.0,
+			link: 0, info: 0, align: 1, entsize: 0,
+		});
+		let name = names.add(".shstrtab");
+		headers.push(Header {
+			name, kind: SHT_STRTAB, flags: 0, offset: 0,
+			size: names.0.len(), data: names.0,
+			link: 0, info: 0, align: 1, entsize: 0,
+		});
+		
+		// everything's data goes after the ELF header, and then the section
+		// headers after that
+		let mut out = vec![0; HEADER_SIZE];
+		for h in &mut headers {
+			out.resize(out.len().next_multiple_of(h.align.max(1) as usize), 0);
+			h.offset = out.len();
+			out.extend_from_slice(&h.data);
+		}
+		out.resize(out.len().next_multiple_of(4), 0);
+		let section_headers = out.len();
+		
+		out.extend_from_slice(&[0; SECTION_HEADER_SIZE]);
+		for h in &headers {
+			for n in [h.name, h.kind, h.flags, 0, h.offset as u32, h.size as u32, h.link, h.info, h.align, h.entsize] {
+				put32(&mut out, n);
+			}
+		}
+		
+		let mut header = vec![];
+		header.extend_from_slice(b"\x7FELF");
+		// 32-bit, little-endian, version 1
+		header.extend_from_slice(&[1, 1, 1]);
+		header.resize(16, 0);
+		put16(&mut header, ET_REL);
+		put16(&mut header, EM_MIPS);
+		put32(&mut header, 1);
+		put32(&mut header, 0); // entry
+		put32(&mut header, 0); // program headers
+		put32(&mut header, section_headers as u32);
+		put32(&mut header, 0); // flags
+		put16(&mut header, HEADER_SIZE as u16);
+		put16(&mut header, 0);
+		put16(&mut header, 0);
+		put16(&mut header, SECTION_HEADER_SIZE as u16);
+		put16(&mut header, headers.len() as u16 + 1);
+		put16(&mut header, headers.len() as u16);
+		out[..HEADER_SIZE].copy_from_slice(&header);
+		
+		out
+	}
+	
+	/// Reads an object file back. Sections that wouldn't get loaded (like
+	/// debug info) are left out, along with their symbols.
+	pub fn from_elf(elf: &[u8]) -> Result<Object, &'static str> {
+		const CUT_OFF: &str = "the file's cut off";
+		// everything's checked, since none of these numbers can be trusted
+		let bytes = |at: usize, len: usize| at.checked_add(len).and_then(|end| elf.get(at..end)).ok_or(CUT_OFF);
+		let u16_at = |at: usize| bytes(at, 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+		let u32_at = |at: usize| bytes(at, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+		
+		if elf.get(..4) != Some(b"\x7FELF") {
+			return Err("that isn't an ELF file");
+		}
+		if elf.get(4..6) != Some(&[1, 1]) {
+			return Err("only 32-bit little-endian ELF files are supported");
+		}
+		if u16_at(16)? != ET_REL {
+			return Err("that isn't an object file");
+		}
+		if u16_at(18)? != EM_MIPS {
+			return Err("that isn't a MIPS object file");
+		}
+		
+		let (offset, size, count, names) = (u32_at(32)? as usize, u16_at(46)? as usize, u16_at(48)? as usize, u16_at(50)? as usize);
+		let headers = (0..count).map(|i| {
+			let at = |field: usize| u32_at(offset.saturating_add(i * size + field * 4));
+			Ok(Header {
+				name: at(0)?, kind: at(1)?, flags: at(2)?,
+				data: vec![],
+				offset: at(4)? as usize,
+				size: at(5)? as usize,
+				link: at(6)?, info: at(7)?, align: at(8)?, entsize: at(9)?,
+			})
+		}).collect::<Result<Vec<_>, &'static str>>()?;
+		let data = |h: &Header| bytes(h.offset, h.size);
+		let string = |table: &[u8], at: u32| -> String {
+			let s = table.get(at as usize..).unwrap_or_default();
+			String::from_utf8_lossy(&s[..s.iter().position(|&b| b == 0).unwrap_or(s.len())]).into_owned()
+		};
+		let names = data(headers.get(names).ok_or("the section names are missing")?)?;
+		
+		let mut object = Object::default();
+		let mut kept = vec![None; headers.len()];
+		for (i, h) in headers.iter().enumerate() {
+			if h.flags & SHF_ALLOC == 0 || !matches!(h.kind, SHT_PROGBITS | SHT_NOBITS) {
+				continue;
+			}
+			// `.bss` isn't in the file, so nothing else says how big it can be
+			if h.kind == SHT_NOBITS && h.size > MAX_SPACE as usize {
+				return Err("there's a `.bss` section in it that's too big");
+			}
+			kept[i] = Some(object.sections.len());
+			object.sections.push(ObjectSection {
+				name: string(names, h.name),
+				align: h.align.max(1),
+				bytes: if h.kind == SHT_NOBITS { vec![0; h.size] } else { data(h)?.to_vec() },
+				relocations: vec![],
+			});
+		}
+		
+		// which of ours each of the file's symbols is, if it's one we kept
+		let mut symbols: Vec<Option<usize>> = vec![None];
+		if let Some(symtab) = headers.iter().find(|h| h.kind == SHT_SYMTAB) {
+			let strings = data(headers.get(symtab.link as usize).ok_or("the symbol names are missing")?)?;
+			for entry in data(symtab)?.chunks_exact(SYMBOL_SIZE).skip(1) {
+				let field = |i: usize| u32::from_le_bytes(entry[i * 4..i * 4 + 4].try_into().unwrap());
+				let (info, shndx) = (entry[12], u16::from_le_bytes([entry[14], entry[15]]));
+				let section = match shndx {
+					0 => Some(None),
+					n => kept.get(n as usize).copied().flatten().map(Some),
+				};
+				let (Some(section), false) = (section, info & 0xF == STT_FILE) else {
+					symbols.push(None);
+					continue;
+				};
+				
+				symbols.push(Some(object.symbols.len()));
+				object.symbols.push(Symbol {
+					name: match section {
+						Some(i) if info & 0xF == STT_SECTION => object.sections[i].name.clone(),
+						_ => string(strings, field(0)),
+					},
+					section,
+					value: field(1),
+					global: info >> 4 != STB_LOCAL,
+				});
+			}
+		}
+		
+		for h in &headers {
+			let Some(target) = kept.get(h.info as usize).copied().flatten() else { continue };
+			match h.kind {
+				SHT_REL => (),
+				SHT_RELA => return Err("only REL relocations are supported, not RELA"),
+				_ => continue,
+			}
+			for entry in data(h)?.chunks_exact(8) {
+				let offset = u32::from_le_bytes(entry[..4].try_into().unwrap());
+				let info = u32::from_le_bytes(entry[4..].try_into().unwrap());
+				let kind = match info & 0xFF {
+					0 => continue,
+					2 => Reloc::Word,
+					4 => Reloc::Jump,
+					5 => Reloc::Hi,
+					6 => Reloc::Lo,
+					_ => return Err("there's a kind of relocation in it that isn't supported"),
+				};
+				let symbol = symbols.get(info as usize >> 8).copied().flatten()
+					.ok_or("there's a relocation in it to a symbol that isn't supported")?;
+				let section = &mut object.sections[target];
+				if section.bytes.len().checked_sub(4).is_none_or(|last| offset as usize > last) {
+					return Err("there's a relocation in it that's outside its section");
+				}
+				section.relocations.push(Relocation { offset, kind, symbol });
+			}
+		}
+		
+		Ok(object)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn elf() {
+		let object = Object {
+			sections: vec![
+				ObjectSection {
+					name: ".text".into(),
+					align: 4,
+					bytes: vec![0x00, 0x00, 0x01, 0x3C, 0x00, 0x00, 0x24, 0x24, 0x00, 0x00, 0x00, 0x0C],
+					relocations: vec![
+						Relocation { offset: 0, kind: Reloc::Hi, symbol: 1 },
+						Relocation { offset: 4, kind: Reloc::Lo, symbol: 1 },
+						Relocation { offset: 8, kind: Reloc::Jump, symbol: 3 },
+					],
+				},
+				ObjectSection { name: ".data".into(), align: 8, bytes: vec![1, 2, 3, 4], relocations: vec![] },
+				ObjectSection { name: BSS.into(), align: 4, bytes: vec![0; 16], relocations: vec![] },
+			],
+			symbols: vec![
+				Symbol { name: "main".into(), section: Some(0), value: 0, global: true },
+				Symbol { name: ".data".into(), section: Some(1), value: 0, global: false },
+				Symbol { name: "buffer".into(), section: Some(2), value: 4, global: false },
+				Symbol { name: "print".into(), section: None, value: 0, global: true },
+			],
+		};
+		let elf = object.to_elf();
+		
+		assert_eq!(elf[..4], *b"\x7FELF");
+		assert_eq!(u16::from_le_bytes([elf[16], elf[17]]), ET_REL);
+		assert_eq!(u16::from_le_bytes([elf[18], elf[19]]), EM_MIPS);
+		// .bss doesn't take up any room
+		assert!(elf.len() < 600);
+		
+		// locals come first when it's written, so they come first when it's
+		// read back
+		let mut expected = object.clone();
+		expected.symbols.swap(0, 2);
+		expected.symbols.swap(0, 1);
+		for r in &mut expected.sections[0].relocations {
+			r.symbol = [2, 0, 1, 3][r.symbol];
+		}
+		assert_eq!(Object::from_elf(&elf), Ok(expected));
+		
+		assert_eq!(Object::from_elf(b"#!/bin/sh"), Err("that isn't an ELF file"));
+		assert_eq!(Object::from_elf(&elf[..40]), Err("the file's cut off"));
+		let mut big_endian = elf.clone();
+		big_endian[5] = 2;
+		assert_eq!(Object::from_elf(&big_endian), Err("only 32-bit little-endian ELF files are supported"));
+		
+		// sections that say they're somewhere they can't be
+		let u32_at = |at: usize| u32::from_le_bytes(elf[at..at + 4].try_into().unwrap());
+		let header = |kind: u32| (0..u16::from_le_bytes([elf[48], elf[49]]) as usize)
+			.map(|i| u32_at(32) as usize + i * 40)
+			.find(|&at| u32_at(at + 4) == kind)
+			.unwrap();
+		let mut huge_bss = elf.clone();
+		let at = header(SHT_NOBITS);
+		huge_bss[at + 20..at + 24].copy_from_slice(&0x8000_0000u32.to_le_bytes());
+		assert_eq!(Object::from_elf(&huge_bss), Err("there's a `.bss` section in it that's too big"));
+		let mut past_the_end = elf.clone();
+		let at = header(SHT_PROGBITS);
+		past_the_end[at + 16..at + 24].fill(0xFF);
+		assert_eq!(Object::from_elf(&past_the_end), Err("the file's cut off"));
+	}
+}
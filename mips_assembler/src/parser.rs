@@ -0,0 +1,245 @@
+use mips_emulator::chip::Register;
+
+use std::collections::HashMap;
+
+use crate::{word, Span, Error, expr::{self, Expr}, lexer::{Token, TokenKind}};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OperandKind {
+	Register(Register),
+	
+	/// Negative numbers are already two's complement.
+	Int(word),
+	Float(f64),
+	Str(String),
+	Label(String),
+	
+	/// Arithmetic with labels in it, which has to wait until they're known.
+	/// Anything without labels is worked out straight away.
+	Expr(Expr),
+	
+	/// `offset($base)`, for loads and stores. Just `($base)` has an offset
+	/// of 0.
+	Address(Box<Operand>, Register),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operand {
+	pub kind: OperandKind,
+	pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+	Instruction { mnemonic: String, operands: Vec<Operand>, span: Span },
+	Directive { name: String, args: Vec<Operand>, span: Span },
+}
+
+impl Statement {
+	/// The whole statement, from the mnemonic to the last operand.
+	pub fn span(&self) -> Span {
+		match self {
+			Statement::Instruction { span, .. } | Statement::Directive { span, .. } => *span,
+		}
+	}
+}
+
+/// A line with anything on it. Labels can share a line with a statement, or
+/// sit on their own and point at whatever comes next.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Line {
+	pub labels: Vec<(String, Span)>,
+	pub statement: Option<Statement>,
+}
+
+/// Works out an expression now if it can be.
+pub(crate) fn fold(e: Expr) -> Result<OperandKind, Error> {
+	Ok(match e {
+		Expr::Label(l, _) => OperandKind::Label(l),
+		e if !e.has_labels() => OperandKind::Int(e.eval(&mut |_, _| unreachable!())?),
+		e => OperandKind::Expr(e),
+	})
+}
+
+/// Reads one operand off the front of `tokens`, returning it and whatever's
+/// left after it.
+fn operand(tokens: &[Token]) -> Result<(Operand, &[Token]), Error> {
+	let (token, rest) = tokens.split_first().unwrap();
+	let register = |name: &str, span| Register::try_from(name).map_err(|e| Error::new(span, e));
+	
+	let kind = match (&token.kind, rest) {
+		(TokenKind::Register(name), _) => OperandKind::Register(register(name, token.span)?),
+		(TokenKind::Str(s), _) => OperandKind::Str(s.clone()),
+		(TokenKind::Float(f), _) => OperandKind::Float(*f),
+		(TokenKind::Plus | TokenKind::Minus, [Token { kind: TokenKind::Float(f), span }, rest @ ..]) => {
+			let f = if token.kind == TokenKind::Minus { -f } else { *f };
+			return Ok((Operand { kind: OperandKind::Float(f), span: token.span.to(*span) }, rest));
+		},
+		(TokenKind::LParen, [
+			Token { kind: TokenKind::Register(name), span },
+			Token { kind: TokenKind::RParen, span: close },
+			rest @ ..
+		]) => {
+			let zero = Operand { kind: OperandKind::Int(0), span: token.span };
+			let kind = OperandKind::Address(Box::new(zero), register(name, *span)?);
+			return Ok((Operand { kind, span: token.span.to(*close) }, rest));
+		},
+		(TokenKind::Colon, _) => return Err(Error::new(token.span, "labels have to come before the instruction")),
+		(TokenKind::Directive(d), _) => return Err(Error::new(token.span, format!("`{d}` can't be an operand"))),
+		(TokenKind::Param(p), _) if p != "%hi" && p != "%lo" =>
+			return Err(Error::new(token.span, format!("`{p}` can only be used in a macro"))),
+		_ => {
+			let (e, span, rest) = expr::parse(tokens, token.span)?;
+			let value = Operand { kind: fold(e)?, span };
+			
+			// and an offset, if there's a register after it
+			return Ok(match rest {
+				[
+					Token { kind: TokenKind::LParen, .. },
+					Token { kind: TokenKind::Register(name), span: r },
+					Token { kind: TokenKind::RParen, span: close },
+					rest @ ..
+				] => {
+					let kind = OperandKind::Address(Box::new(value), register(name, *r)?);
+					(Operand { kind, span: span.to(*close) }, rest)
+				},
+				_ => (value, rest),
+			});
+		},
+	};
+	Ok((Operand { kind, span: token.span }, rest))
+}
+
+fn line(tokens: &[Token]) -> Result<Line, Error> {
+	let mut line = Line::default();
+	let mut rest = tokens;
+	
+	while let [Token { kind: TokenKind::Ident(name), span }, Token { kind: TokenKind::Colon, .. }, more @ ..] = rest {
+		line.labels.push((name.clone(), *span));
+		rest = more;
+	}
+	
+	let Some((first, rest)) = rest.split_first() else {
+		return Ok(line);
+	};
+	
+	// commas are optional, like they were in the old one-line assembler
+	let mut operands = Vec::new();
+	let mut rest = rest;
+	while let Some(t) = rest.first() {
+		if t.kind == TokenKind::Comma {
+			rest = &rest[1..];
+		} else {
+			let (op, more) = operand(rest)?;
+			operands.push(op);
+			rest = more;
+		}
+	}
+	
+	let span = first.span.to(tokens.last().unwrap().span);
+	
+	line.statement = Some(match &first.kind {
+		TokenKind::Ident(mnemonic) => Statement::Instruction { mnemonic: mnemonic.to_ascii_lowercase(), operands, span },
+		TokenKind::Directive(name) => Statement::Directive { name: name.to_ascii_lowercase(), args: operands, span },
+		_ => return Err(Error::new(first.span, "expected an instruction or a directive")),
+	});
+	
+	Ok(line)
+}
+
+fn is_eqv(t: &Token) -> bool {
+	matches!(&t.kind, TokenKind::Directive(d) if d.eq_ignore_ascii_case(".eqv"))
+}
+
+/// Groups tokens into lines. Blank lines (and comment-only ones) are left
+/// out, and a line that doesn't parse leaves an error behind instead.
+/// 
+/// `.eqv` gets handled here, since like in MARS it's plain substitution:
+/// after `.eqv NAME stuff`, every `NAME` turns into `stuff`, whatever that
+/// is. That leaves the `.eqv` itself as a directive with no arguments.
+pub fn parse(tokens: &[Token]) -> (Vec<Line>, Vec<Error>) {
+	let mut lines = Vec::new();
+	let mut errors = Vec::new();
+	let mut eqvs: HashMap<String, Vec<TokenKind>> = HashMap::new();
+	
+	for tokens in tokens.split(|t| t.kind == TokenKind::Newline).filter(|t| !t.is_empty()) {
+		let mut substituted = Vec::with_capacity(tokens.len());
+		for (i, t) in tokens.iter().enumerate() {
+			let defining = i == 1 && is_eqv(&tokens[0]);
+			match &t.kind {
+				TokenKind::Ident(name) if !defining && eqvs.contains_key(name) => {
+					// everything it turns into points back at the name, for errors
+					substituted.extend(eqvs[name].iter().map(|kind| Token { kind: kind.clone(), span: t.span }));
+				},
+				_ => substituted.push(t.clone()),
+			}
+		}
+		
+		if let [first, rest @ ..] = &substituted[..] {
+			if is_eqv(first) {
+				let span = first.span.to(substituted.last().unwrap().span);
+				match rest {
+					[Token { kind: TokenKind::Ident(name), span: name_span }, value @ ..] => {
+						if value.is_empty() {
+							errors.push(Error::new(*name_span, format!("`{name}` needs something to stand for")));
+						} else if eqvs.contains_key(name) {
+							errors.push(Error::new(*name_span, format!("`{name}` is already defined")));
+						} else {
+							eqvs.insert(name.clone(), value.iter().map(|t| t.kind.clone()).collect());
+						}
+					},
+					_ => errors.push(Error::new(first.span, "expected `.eqv NAME value`")),
+				}
+				lines.push(Line {
+					labels: vec![],
+					statement: Some(Statement::Directive { name: ".eqv".into(), args: vec![], span }),
+				});
+				continue;
+			}
+		}
+		
+		match line(&substituted) {
+			Ok(l) => lines.push(l),
+			Err(e) => errors.push(e),
+		}
+	}
+	
+	(lines, errors)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lexer::tokenize;
+	
+	#[test]
+	fn lines() {
+		let (tokens, _) = tokenize("start: loop:\n\tbeq $t0 $zero, loop # back\n\n.text\nadd $t0, : $t1");
+		let (lines, errors) = parse(&tokens);
+		
+		assert_eq!(lines, [
+			Line {
+				labels: vec![("start".into(), Span::new(1, 1, 5)), ("loop".into(), Span::new(1, 8, 4))],
+				statement: None,
+			},
+			Line {
+				labels: vec![],
+				statement: Some(Statement::Instruction {
+					mnemonic: "beq".into(),
+					operands: vec![
+						Operand { kind: OperandKind::Register(Register::t0), span: Span::new(2, 6, 3) },
+						Operand { kind: OperandKind::Register(Register::zero), span: Span::new(2, 10, 5) },
+						Operand { kind: OperandKind::Label("loop".into()), span: Span::new(2, 17, 4) },
+					],
+					span: Span::new(2, 2, 19),
+				}),
+			},
+			Line {
+				labels: vec![],
+				statement: Some(Statement::Directive { name: ".text".into(), args: vec![], span: Span::new(4, 1, 5) }),
+			},
+		]);
+		
+		assert_eq!(errors, [Error::new(Span::new(5, 10, 1), "labels have to come before the instruction")]);
+	}
+}
@@ -0,0 +1,370 @@
+//! Pseudo-instructions, the way MARS does them: each one turns into a few
+//! real instructions, using `$at` for anything it needs to keep in between.
+//! 
+//! Anything written with a number or a label where a register goes (like
+//! `beq $t0, 5, done` or `mul $t0, $t1, 10`) gets that loaded into `$at`
+//! first, and then carries on as if it was `$at` all along.
+
+use std::collections::BTreeMap;
+
+use mips_emulator::chip::Register;
+
+use crate::{
+	word, Span, Error,
+	parser::{fold, Operand, OperandKind},
+	encode::{accepts, is_instruction, value},
+	expr::Expr,
+};
+
+/// What an operand has to be for a form to match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Arg {
+	Reg,
+	/// A number that fits in a signed 16 bits.
+	Imm16,
+	/// A number that fits in an unsigned 16 bits.
+	ImmU16,
+	/// A number that doesn't fit in a signed 16 bits.
+	Big16,
+	/// A number that doesn't fit in an unsigned 16 bits.
+	BigU16,
+	/// A number or a label, or arithmetic on them.
+	Imm,
+	/// Where a branch goes.
+	Target,
+	/// `offset($rs)` with an offset that fits in a signed 16 bits.
+	Addr16,
+	/// `offset($rs)` with any other offset.
+	AddrBig,
+}
+
+/// One operand of an expanded instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Out {
+	/// Operand n, as it was written.
+	Op(usize),
+	At,
+	Zero,
+	/// The upper half of operand n, plus one if the lower half's going to be
+	/// sign-extended into a negative.
+	Hi(usize),
+	/// The lower half of operand n.
+	Lo(usize),
+	Int(word),
+	/// The register in operand n, which is an address.
+	Base(usize),
+	/// The offset in operand n, which is an address.
+	Off(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+	I(&'static str, &'static [Out]),
+	/// Puts operand n in a register, in one instruction if it's small enough
+	/// and two if it isn't. Labels always get two, since they aren't known
+	/// yet when the sizes are worked out.
+	Li(Out, usize),
+}
+
+/// Every pseudo-instruction, in every form. The first form that matches
+/// wins, so the smaller ones go first.
+const PSEUDO_OPS: &[(&str, &[Arg], &[Step])] = {
+	use Arg::*;
+	use Out::*;
+	use Step::*;
+&[
+	("move" , &[Reg, Reg], &[I("addu", &[Op(0), Zero, Op(1)])]),
+	("li"   , &[Reg, Imm], &[Li(Op(0), 1)]),
+	("la"   , &[Reg, Imm], &[Li(Op(0), 1)]),
+	("la"   , &[Reg, Addr16 ], &[I("addiu", &[Op(0), Base(1), Off(1)])]),
+	("la"   , &[Reg, AddrBig], &[I("lui", &[At, Hi(1)]), I("addiu", &[At, At, Lo(1)]), I("addu", &[Op(0), At, Base(1)])]),
+	
+	("not"  , &[Reg, Reg], &[I("nor" , &[Op(0), Op(1), Zero])]),
+	("neg"  , &[Reg, Reg], &[I("sub" , &[Op(0), Zero, Op(1)])]),
+	("negu" , &[Reg, Reg], &[I("subu", &[Op(0), Zero, Op(1)])]),
+	// no sra to get the sign with, so slt it is
+	("abs"  , &[Reg, Reg], &[
+		I("slt" , &[At, Op(1), Zero]),
+		I("subu", &[At, Zero, At]),
+		I("xor" , &[Op(0), Op(1), At]),
+		I("subu", &[Op(0), Op(0), At]),
+	]),
+	
+	("mul"  , &[Reg, Reg, Reg], &[I("mult" , &[Op(1), Op(2)]), I("mflo", &[Op(0)])]),
+	("mulu" , &[Reg, Reg, Reg], &[I("multu", &[Op(1), Op(2)]), I("mflo", &[Op(0)])]),
+	("div"  , &[Reg, Reg, Reg], &[I("div"  , &[Op(1), Op(2)]), I("mflo", &[Op(0)])]),
+	("divu" , &[Reg, Reg, Reg], &[I("divu" , &[Op(1), Op(2)]), I("mflo", &[Op(0)])]),
+	("rem"  , &[Reg, Reg, Reg], &[I("div"  , &[Op(1), Op(2)]), I("mfhi", &[Op(0)])]),
+	("remu" , &[Reg, Reg, Reg], &[I("divu" , &[Op(1), Op(2)]), I("mfhi", &[Op(0)])]),
+	
+	// immediates given to the register versions, when they fit
+	("add"  , &[Reg, Reg, Imm16 ], &[I("addi" , &[Op(0), Op(1), Op(2)])]),
+	("addu" , &[Reg, Reg, Imm16 ], &[I("addiu", &[Op(0), Op(1), Op(2)])]),
+	("and"  , &[Reg, Reg, ImmU16], &[I("andi" , &[Op(0), Op(1), Op(2)])]),
+	("or"   , &[Reg, Reg, ImmU16], &[I("ori"  , &[Op(0), Op(1), Op(2)])]),
+	("xor"  , &[Reg, Reg, ImmU16], &[I("xori" , &[Op(0), Op(1), Op(2)])]),
+	("slt"  , &[Reg, Reg, Imm16 ], &[I("slti" , &[Op(0), Op(1), Op(2)])]),
+	("sltu" , &[Reg, Reg, Imm16 ], &[I("sltiu", &[Op(0), Op(1), Op(2)])]),
+	// and the other way around, when they don't, going by whether the real
+	// one sign-extends or zero-extends
+	("addi" , &[Reg, Reg, Big16 ], &[Li(At, 2), I("add" , &[Op(0), Op(1), At])]),
+	("addiu", &[Reg, Reg, Big16 ], &[Li(At, 2), I("addu", &[Op(0), Op(1), At])]),
+	("andi" , &[Reg, Reg, BigU16], &[Li(At, 2), I("and" , &[Op(0), Op(1), At])]),
+	("ori"  , &[Reg, Reg, BigU16], &[Li(At, 2), I("or"  , &[Op(0), Op(1), At])]),
+	("xori" , &[Reg, Reg, BigU16], &[Li(At, 2), I("xor" , &[Op(0), Op(1), At])]),
+	("slti" , &[Reg, Reg, Big16 ], &[Li(At, 2), I("slt" , &[Op(0), Op(1), At])]),
+	("sltiu", &[Reg, Reg, Big16 ], &[Li(At, 2), I("sltu", &[Op(0), Op(1), At])]),
+	("subi" , &[Reg, Reg, Imm], &[Li(At, 2), I("sub" , &[Op(0), Op(1), At])]),
+	("subiu", &[Reg, Reg, Imm], &[Li(At, 2), I("subu", &[Op(0), Op(1), At])]),
+	
+	("sgt"  , &[Reg, Reg, Reg], &[I("slt" , &[Op(0), Op(2), Op(1)])]),
+	("sgtu" , &[Reg, Reg, Reg], &[I("sltu", &[Op(0), Op(2), Op(1)])]),
+	("sle"  , &[Reg, Reg, Reg], &[I("slt" , &[Op(0), Op(2), Op(1)]), I("xori", &[Op(0), Op(0), Int(1)])]),
+	("sleu" , &[Reg, Reg, Reg], &[I("sltu", &[Op(0), Op(2), Op(1)]), I("xori", &[Op(0), Op(0), Int(1)])]),
+	("sge"  , &[Reg, Reg, Reg], &[I("slt" , &[Op(0), Op(1), Op(2)]), I("xori", &[Op(0), Op(0), Int(1)])]),
+	("sgeu" , &[Reg, Reg, Reg], &[I("sltu", &[Op(0), Op(1), Op(2)]), I("xori", &[Op(0), Op(0), Int(1)])]),
+	("seq"  , &[Reg, Reg, Reg], &[I("subu", &[Op(0), Op(1), Op(2)]), I("sltiu", &[Op(0), Op(0), Int(1)])]),
+	("sne"  , &[Reg, Reg, Reg], &[I("subu", &[Op(0), Op(1), Op(2)]), I("sltu", &[Op(0), Zero, Op(0)])]),
+	
+	("b"    , &[Target], &[I("beq", &[Zero, Zero, Op(0)])]),
+	("beqz" , &[Reg, Target], &[I("beq", &[Op(0), Zero, Op(1)])]),
+	("bnez" , &[Reg, Target], &[I("bne", &[Op(0), Zero, Op(1)])]),
+	("blt"  , &[Reg, Reg, Target], &[I("slt" , &[At, Op(0), Op(1)]), I("bne", &[At, Zero, Op(2)])]),
+	("bltu" , &[Reg, Reg, Target], &[I("sltu", &[At, Op(0), Op(1)]), I("bne", &[At, Zero, Op(2)])]),
+	("bge"  , &[Reg, Reg, Target], &[I("slt" , &[At, Op(0), Op(1)]), I("beq", &[At, Zero, Op(2)])]),
+	("bgeu" , &[Reg, Reg, Target], &[I("sltu", &[At, Op(0), Op(1)]), I("beq", &[At, Zero, Op(2)])]),
+	("bgt"  , &[Reg, Reg, Target], &[I("slt" , &[At, Op(1), Op(0)]), I("bne", &[At, Zero, Op(2)])]),
+	("bgtu" , &[Reg, Reg, Target], &[I("sltu", &[At, Op(1), Op(0)]), I("bne", &[At, Zero, Op(2)])]),
+	("ble"  , &[Reg, Reg, Target], &[I("slt" , &[At, Op(1), Op(0)]), I("beq", &[At, Zero, Op(2)])]),
+	("bleu" , &[Reg, Reg, Target], &[I("sltu", &[At, Op(1), Op(0)]), I("beq", &[At, Zero, Op(2)])]),
+	// TODO: these are real instructions, once the emulator can run them
+	("bltz" , &[Reg, Target], &[I("slt", &[At, Op(0), Zero]), I("bne", &[At, Zero, Op(1)])]),
+	("bgez" , &[Reg, Target], &[I("slt", &[At, Op(0), Zero]), I("beq", &[At, Zero, Op(1)])]),
+	("bgtz" , &[Reg, Target], &[I("slt", &[At, Zero, Op(0)]), I("bne", &[At, Zero, Op(1)])]),
+	("blez" , &[Reg, Target], &[I("slt", &[At, Zero, Op(0)]), I("beq", &[At, Zero, Op(1)])]),
+	
+	// loads and stores straight from a label
+	("lw"   , &[Reg, Imm16], &[I("lw" , &[Op(0), Zero, Op(1)])]),
+	("lhu"  , &[Reg, Imm16], &[I("lhu", &[Op(0), Zero, Op(1)])]),
+	("lbu"  , &[Reg, Imm16], &[I("lbu", &[Op(0), Zero, Op(1)])]),
+	("sw"   , &[Reg, Imm16], &[I("sw" , &[Op(0), Zero, Op(1)])]),
+	("sh"   , &[Reg, Imm16], &[I("sh" , &[Op(0), Zero, Op(1)])]),
+	("sb"   , &[Reg, Imm16], &[I("sb" , &[Op(0), Zero, Op(1)])]),
+	("lw"   , &[Reg, Imm], &[I("lui", &[At, Hi(1)]), I("lw" , &[Op(0), At, Lo(1)])]),
+	("lhu"  , &[Reg, Imm], &[I("lui", &[At, Hi(1)]), I("lhu", &[Op(0), At, Lo(1)])]),
+	("lbu"  , &[Reg, Imm], &[I("lui", &[At, Hi(1)]), I("lbu", &[Op(0), At, Lo(1)])]),
+	("sw"   , &[Reg, Imm], &[I("lui", &[At, Hi(1)]), I("sw" , &[Op(0), At, Lo(1)])]),
+	("sh"   , &[Reg, Imm], &[I("lui", &[At, Hi(1)]), I("sh" , &[Op(0), At, Lo(1)])]),
+	("sb"   , &[Reg, Imm], &[I("lui", &[At, Hi(1)]), I("sb" , &[Op(0), At, Lo(1)])]),
+	// and from a label plus a register
+	("lw"   , &[Reg, AddrBig], &[I("lui", &[At, Hi(1)]), I("addu", &[At, At, Base(1)]), I("lw" , &[Op(0), At, Lo(1)])]),
+	("lhu"  , &[Reg, AddrBig], &[I("lui", &[At, Hi(1)]), I("addu", &[At, At, Base(1)]), I("lhu", &[Op(0), At, Lo(1)])]),
+	("lbu"  , &[Reg, AddrBig], &[I("lui", &[At, Hi(1)]), I("addu", &[At, At, Base(1)]), I("lbu", &[Op(0), At, Lo(1)])]),
+	("sw"   , &[Reg, AddrBig], &[I("lui", &[At, Hi(1)]), I("addu", &[At, At, Base(1)]), I("sw" , &[Op(0), At, Lo(1)])]),
+	("sh"   , &[Reg, AddrBig], &[I("lui", &[At, Hi(1)]), I("addu", &[At, At, Base(1)]), I("sh" , &[Op(0), At, Lo(1)])]),
+	("sb"   , &[Reg, AddrBig], &[I("lui", &[At, Hi(1)]), I("addu", &[At, At, Base(1)]), I("sb" , &[Op(0), At, Lo(1)])]),
+]};
+
+impl Arg {
+	fn matches(self, op: &Operand) -> bool {
+		use OperandKind::*;
+		let small = |n: word| (n as i32 as i64) >= -0x8000 && (n as i32 as i64) < 0x8000;
+		match (self, &op.kind) {
+			(Arg::Reg, Register(_)) => true,
+			(Arg::Imm16, Int(n)) => small(*n),
+			// a `%lo` is always a signed 16 bits, even before it's known
+			(Arg::Imm16, Expr(crate::expr::Expr::Lo(_))) => true,
+			(Arg::ImmU16, Int(n)) => *n <= 0xFFFF,
+			(Arg::Big16, Int(n)) => !small(*n),
+			(Arg::BigU16, Int(n)) => *n > 0xFFFF,
+			(Arg::Imm | Arg::Target, Int(_) | Label(_) | Expr(_)) => true,
+			(Arg::Addr16 | Arg::AddrBig, Address(offset, _)) =>
+				(Arg::Imm16.matches(offset)) == (self == Arg::Addr16),
+			_ => false,
+		}
+	}
+	
+	fn usage(self) -> &'static str {
+		match self {
+			Arg::Reg => "$reg",
+			Arg::Target => "label",
+			Arg::Addr16 | Arg::AddrBig => "offset($reg)",
+			_ => "imm",
+		}
+	}
+}
+
+/// Either a pseudo-instruction's steps, or a real instruction (that might
+/// still need something put in `$at` for it).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Form {
+	Pseudo(&'static [Step]),
+	Real,
+}
+
+/// How a statement gets turned into real instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Plan {
+	/// Which operand gets put in `$at` first, if any.
+	via_at: Option<usize>,
+	form: Form,
+}
+
+fn direct(mnemonic: &str, ops: &[Operand]) -> Option<Form> {
+	PSEUDO_OPS.iter()
+		.find(|(m, args, _)| *m == mnemonic && args.len() == ops.len() && args.iter().zip(ops).all(|(a, op)| a.matches(op)))
+		.map(|&(_, _, steps)| Form::Pseudo(steps))
+		.or_else(|| accepts(mnemonic, ops).then_some(Form::Real))
+}
+
+/// Works out how to write `mnemonic` with these operands, or `None` if it
+/// isn't a pseudo-instruction and isn't a real one written right either.
+pub(crate) fn plan(mnemonic: &str, ops: &[Operand]) -> Option<Plan> {
+	if let Some(form) = direct(mnemonic, ops) {
+		return Some(Plan { via_at: None, form });
+	}
+	
+	// the first operand's always where the result goes, so only the rest can
+	// be swapped for $at
+	(1..ops.len())
+		.filter(|&i| matches!(ops[i].kind, OperandKind::Int(_) | OperandKind::Label(_) | OperandKind::Expr(_)))
+		.find_map(|i| {
+			let mut with_at = ops.to_vec();
+			with_at[i].kind = OperandKind::Register(Register::at);
+			direct(mnemonic, &with_at).map(|form| Plan { via_at: Some(i), form })
+		})
+}
+
+/// How a pseudo-instruction can be written, for when it isn't, or `None` if
+/// there's no pseudo-instruction called that (or there's a real one too,
+/// which knows better what it wanted).
+pub(crate) fn usage(mnemonic: &str) -> Option<String> {
+	if is_instruction(mnemonic) {
+		return None;
+	}
+	
+	let forms: Vec<String> = PSEUDO_OPS.iter()
+		.filter(|(m, _, _)| *m == mnemonic)
+		.map(|(m, args, _)| {
+			let args: Vec<_> = args.iter().map(|a| a.usage()).collect();
+			format!("`{m} {}`", args.join(", "))
+		})
+		.fold(Vec::new(), |mut forms, f| {
+			if !forms.contains(&f) { forms.push(f); }
+			forms
+		});
+	(!forms.is_empty()).then(|| forms.join(" or "))
+}
+
+/// A number or a label as an expression, to do arithmetic on.
+fn expr(op: &Operand, labels: &BTreeMap<String, word>) -> Result<Expr, Error> {
+	Ok(match &op.kind {
+		OperandKind::Label(l) => Expr::Label(l.clone(), op.span),
+		OperandKind::Expr(e) => e.clone(),
+		_ => Expr::Int(value(op, labels)?),
+	})
+}
+
+/// The upper or lower half of `op`. Anything with a label in it stays that
+/// way, so an object file knows what to tell the linker about.
+fn half(f: fn(Box<Expr>) -> Expr, op: &Operand, labels: &BTreeMap<String, word>) -> Result<Operand, Error> {
+	Ok(Operand { kind: fold(f(Box::new(expr(op, labels)?)))?, span: op.span })
+}
+
+/// How many instructions `li` needs for this.
+fn li_size(op: &Operand) -> word {
+	match op.kind {
+		OperandKind::Int(n) if Arg::Imm16.matches(op) || n <= 0xFFFF => 1,
+		_ => 2,
+	}
+}
+
+impl Plan {
+	/// How many real instructions this turns into. This has to work before
+	/// labels are known, since it's how they get found.
+	pub(crate) fn size(&self, ops: &[Operand]) -> word {
+		let steps = match self.form {
+			Form::Pseudo(steps) => steps.iter().map(|step| match *step {
+				Step::I(..) => 1,
+				Step::Li(_, n) => li_size(&ops[n]),
+			}).sum(),
+			Form::Real => 1,
+		};
+		self.via_at.map_or(0, |i| li_size(&ops[i])) + steps
+	}
+	
+	/// The real instructions, with their operands. `span` is the whole
+	/// statement, for operands that don't come from anywhere in particular.
+	/// 
+	/// In an `object` file, labels get loaded with `%hi` and `%lo`, since
+	/// those are what a linker knows how to fill in.
+	pub(crate) fn expand<'a>(
+		&self, mnemonic: &'a str, ops: &[Operand], span: Span,
+		labels: &BTreeMap<String, word>, object: bool,
+	) -> Result<Vec<(&'a str, Vec<Operand>)>, Error> {
+		let mut ops = ops.to_vec();
+		let mut out = Vec::new();
+		let reg = |r| Operand { kind: OperandKind::Register(r), span };
+		
+		let li = |out: &mut Vec<(&'a str, Vec<Operand>)>, dest: Operand, op: &Operand| -> Result<(), Error> {
+			let n = value(op, labels)?;
+			let int = |n| Operand { kind: OperandKind::Int(n), span: op.span };
+			// sized off the operand and not the value, same as `size`
+			if li_size(op) == 1 && Arg::Imm16.matches(op) {
+				out.push(("addiu", vec![dest, reg(Register::zero), int(n)]));
+			} else if li_size(op) == 1 {
+				out.push(("ori", vec![dest, reg(Register::zero), int(n)]));
+			} else if object && !matches!(op.kind, OperandKind::Int(_)) {
+				out.push(("lui", vec![reg(Register::at), half(Expr::Hi, op, labels)?]));
+				out.push(("addiu", vec![dest, reg(Register::at), half(Expr::Lo, op, labels)?]));
+			} else {
+				out.push(("lui", vec![reg(Register::at), int(n >> 16)]));
+				out.push(("ori", vec![dest, reg(Register::at), int(n & 0xFFFF)]));
+			}
+			Ok(())
+		};
+		
+		if let Some(i) = self.via_at {
+			li(&mut out, reg(Register::at), &ops[i])?;
+			ops[i] = reg(Register::at);
+		}
+		
+		let steps = match self.form {
+			Form::Pseudo(steps) => steps,
+			Form::Real => {
+				out.push((mnemonic, ops));
+				return Ok(out);
+			},
+		};
+		
+		// an address' offset, or anything else as it is
+		let offset = |op: &Operand| match &op.kind {
+			OperandKind::Address(offset, _) => (**offset).clone(),
+			_ => op.clone(),
+		};
+		let operand = |o: Out| -> Result<Operand, Error> {
+			Ok(match o {
+				Out::Op(n) => ops[n].clone(),
+				Out::At => reg(Register::at),
+				Out::Zero => reg(Register::zero),
+				// the lower half gets sign-extended when it's used as an offset
+				Out::Hi(n) => half(Expr::Hi, &offset(&ops[n]), labels)?,
+				Out::Lo(n) => half(Expr::Lo, &offset(&ops[n]), labels)?,
+				Out::Int(n) => Operand { kind: OperandKind::Int(n), span },
+				Out::Base(n) => match ops[n].kind {
+					OperandKind::Address(_, base) => Operand { kind: OperandKind::Register(base), span: ops[n].span },
+					_ => unreachable!("only addresses have a base"),
+				},
+				Out::Off(n) => offset(&ops[n]),
+			})
+		};
+		
+		for step in steps {
+			match *step {
+				Step::I(m, outs) => out.push((m, outs.iter().map(|&o| operand(o)).collect::<Result<_, _>>()?)),
+				Step::Li(dest, n) => li(&mut out, operand(dest)?, &ops[n])?,
+			}
+		}
+		Ok(out)
+	}
+}
@@ -0,0 +1,145 @@
+use std::{env, fs, io::{Read, Write}, sync::mpsc, thread};
+
+use mips_emulator::mem::Memory;
+use mips_emulator::map::{self, Preset};
+use mips_emulator::mmio::{KeyboardDisplay, Timer, Disk, Audio, Uart};
+use mips_emulator::chip::{Cpu, Register};
+use mips_emulator::elf::Elf;
+use mips_emulator::image::Image;
+
+const USAGE: &str = "\
+runs a program without the GUI. the UART is hooked up to stdin and stdout.
+images can be raw binary, MARS' HexText or BinaryText, Intel HEX or S-records.
+
+usage: mips_run <text image> [data image] [options]
+       mips_run <program.elf> [options]
+  --preset <name>   memory layout, by its MARS name (default CompactTextAtZero)
+  --steps <n>       stop after this many instructions (default 10000000)
+  --disk <file>     put a disk image in the disk drive
+  --wav <file>      save whatever the audio device played";
+
+/// What the `syscall` instruction encodes to.
+const SYSCALL: u32 = 0x0000_000C;
+
+fn main() -> std::io::Result<()> {
+	let mut args = env::args().skip(1);
+	
+	let mut files = vec![];
+	let mut preset = Preset::CompactTextAtZero;
+	let mut steps: u64 = 10_000_000;
+	let mut disk = None;
+	let mut wav = None;
+	
+	while let Some(arg) = args.next() {
+		let mut value = || args.next().unwrap_or_else(|| fail(&format!("{arg} needs a value")));
+		match arg.as_str() {
+			"--preset" => {
+				let name = value();
+				preset = Preset::ALL.into_iter()
+					.find(|p| p.mars_name().eq_ignore_ascii_case(&name))
+					.unwrap_or_else(|| fail(&format!("there's no preset called {name}")));
+			},
+			"--steps" => steps = value().parse().unwrap_or_else(|_| fail("--steps needs a number")),
+			"--disk" => disk = Some(value()),
+			"--wav" => wav = Some(value()),
+			_ if arg.starts_with("--") => fail(&format!("don't know what {arg} is")),
+			_ => files.push(arg),
+		}
+	}
+	
+	if files.is_empty() || files.len() > 2 {
+		println!("{USAGE}");
+		return Ok(());
+	}
+	
+	let mut mem = Memory::default();
+	mem.map = preset.map();
+	
+	// same spots as in the GUI
+	if let Some(mmio) = mem.map.base(map::MMIO) {
+		mem.attach(Box::new(KeyboardDisplay::new(mmio)));
+		mem.attach(Box::new(Timer::new(mmio + 0x60)));
+		
+		let mut drive = Disk::new(mmio + 0x80);
+		if let Some(disk) = &disk {
+			drive.insert(disk).unwrap_or_else(|e| fail(e));
+		}
+		mem.attach(Box::new(drive));
+		
+		mem.attach(Box::new(Audio::new(mmio + 0xA0)));
+		mem.attach(Box::new(Uart::new(mmio + 0xC0)));
+	}
+	
+	let mut cpu = Cpu::default();
+	let first = fs::read(&files[0])?;
+	if first.starts_with(b"\x7FELF") {
+		let elf = Elf::parse(&first).unwrap_or_else(|e| fail(&format!("{}: {e}", files[0])));
+		elf.load(&mut cpu, &mut mem).unwrap_or_else(|e| fail(&format!("{}: {e}", files[0])));
+	} else {
+		// the ones without addresses go at the start of their section
+		for (file, segment) in files.iter().zip([map::TEXT, map::DATA]) {
+			let base = mem.map.base(segment).unwrap_or(0);
+			let image = Image::read(&fs::read(file)?, base).unwrap_or_else(|e| fail(&format!("{file}: {e}")));
+			image.load(&mut mem).unwrap_or_else(|| fail(&format!("{file} doesn't fit in memory")));
+			if let Some(entry) = image.entry {
+				mem.map.entry = entry;
+			}
+		}
+		cpu.reset(&mem.map);
+	}
+	
+	// reading stdin blocks, so it gets its own thread
+	let (tx, stdin) = mpsc::channel();
+	thread::spawn(move || {
+		let mut buf = [0u8; 256];
+		while let Ok(n @ 1..) = std::io::stdin().read(&mut buf) {
+			if tx.send(buf[..n].to_vec()).is_err() { break; }
+		}
+	});
+	
+	let mut exit_code = 0;
+	let mut stdout = std::io::stdout();
+	for _ in 0..steps {
+		// MARS' exit syscalls, since there's nothing to handle them
+		if mem.get_word(cpu.pc) == Some(SYSCALL) {
+			match cpu[Register::v0] {
+				10 => break,
+				17 => { exit_code = cpu[Register::a0] as i32; break; },
+				_ => (),
+			}
+		}
+		
+		cpu.tick(&mut mem);
+		
+		if let Some(kd) = mem.device_mut::<KeyboardDisplay>() {
+			if !kd.output.is_empty() {
+				write!(stdout, "{}", kd.output)?;
+				stdout.flush()?;
+				kd.output.clear();
+			}
+		}
+		
+		if let Some(uart) = mem.device_mut::<Uart>() {
+			if let Ok(input) = stdin.try_recv() {
+				uart.send(&input);
+			}
+			if !uart.output.is_empty() {
+				stdout.write_all(&uart.output)?;
+				stdout.flush()?;
+				uart.output.clear();
+			}
+		}
+	}
+	
+	if let (Some(path), Some(audio)) = (wav, mem.device::<Audio>()) {
+		audio.save_wav(&path)?;
+		eprintln!("saved {:.2}s of audio to {path}", audio.duration().as_secs_f64());
+	}
+	
+	std::process::exit(exit_code);
+}
+
+fn fail(why: &str) -> ! {
+	eprintln!("{why}");
+	std::process::exit(1);
+}
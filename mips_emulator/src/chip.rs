@@ -1,4 +1,4 @@
-use super::{word, WORD_BYTES, mem::Memory, bits_span, smear_bit};
+use super::{word, WORD_BYTES, mem::Memory, map::{Access, MemoryMap}, bits_span, smear_bit};
 
 #[allow(non_camel_case_types)]
 #[allow(dead_code)]
@@ -45,6 +45,7 @@ pub enum Register {
 	/// Return Address
 	ra = 31,
 }
+#[allow(dead_code)]
 impl Register {
 	const NUM_BITS: usize = 5;
 }
@@ -52,7 +53,7 @@ impl From<u8> for Register {
 	fn from(r: u8) -> Self {
 		match r {
 			// SAFE: it's within the correct boudns lol
-			0..=31 => unsafe { std::mem::transmute(r) },
+			0..=31 => unsafe { std::mem::transmute::<u8, Register>(r) },
 			
 			_ => panic!("Invalid Register"),
 		}
@@ -94,7 +95,7 @@ impl TryFrom<&str> for Register {
 				match num.parse::<u8>() {
 					Ok(n) if (0..=31).contains(&n) =>
 						// SAFE: immm so cool
-						unsafe { std::mem::transmute(n) },
+						unsafe { std::mem::transmute::<u8, Register>(n) },
 					_ => return Err("unknown register"),
 				}
 			},
@@ -231,7 +232,7 @@ impl ExceptionCause {
 	}
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Cpu {
 	pub reg: [word; 32],
 	pub pc: word,
@@ -247,6 +248,17 @@ pub struct Cpu {
 	/// what the heck a branch delay slot is, see this article:
 	/// https://devblogs.microsoft.com/oldnewthing/20180411-00/?p=98485
 	pub after_delay: Option<word>,
+	
+	/// Set when the current instruction raised an exception (or returned from
+	/// one), so the tick doesn't step the PC past where it just jumped to.
+	trapped: bool,
+	
+	/// Set while running a branch delay slot, so an exception in it goes back
+	/// to the branch instead.
+	in_delay: bool,
+	
+	/// Interrupt lines that devices raised, but the CPU hasn't gotten to yet.
+	pending: u8,
 }
 impl core::ops::Index<Register> for Cpu {
 	type Output = word;
@@ -260,7 +272,7 @@ impl core::ops::IndexMut<Register> for Cpu {
 	}
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Cp0 {
 	pub halt: bool,
 	pub reg: [word; 16],
@@ -268,6 +280,21 @@ pub struct Cp0 {
 	/// What address to find the exception handler at.
 	pub exception_handler: word,
 }
+impl Cp0 {
+	/// `Status` bit that turns interrupts on.
+	pub const INTERRUPT_ENABLE: word = 1 << 0;
+	
+	/// `Status` bit that's set while handling an exception. No interrupts can
+	/// happen until `eret` clears it.
+	pub const EXCEPTION_LEVEL: word = 1 << 1;
+	
+	/// `Cause` bit that's set when the exception happened in a branch delay
+	/// slot, in which case `ExPC` is the branch before it.
+	pub const BRANCH_DELAY: word = 1 << 31;
+	
+	/// What MARS starts `Status` at: every interrupt unmasked and enabled.
+	pub const STATUS_DEFAULT: word = 0x0000_FF11;
+}
 impl core::ops::Index<Cp0Register> for Cp0 {
 	type Output = word;
 	fn index(&self, index: Cp0Register) -> &Self::Output {
@@ -362,8 +389,10 @@ enum Opcode {
 // 	params: InsFormatParams
 // }
 
+#[allow(dead_code)]
 #[derive(Clone, Copy)]
 struct Instruction(word);
+#[allow(dead_code)]
 impl Instruction {
 	fn opcode(&self) -> u8 {
 		bits_span(self.0, 26, 6) as u8
@@ -431,11 +460,13 @@ impl Cpu {
 		// 0x31 TGEU // TODO: implement
 		// 0x32 TLT // TODO: implement
 		// 0x33 TLTU // TODO: implement
-		// 0x34 TEQ // TODO: implement
+		(Function(0x34), "teq"      , R(false)), // doesn't use rd
 		// 0x36 TNE // TODO: implement
 		
 		// where does this belong
-		(Coprocessor(0, 0x00), "mfc0", R(true )),
+		(Coprocessor(0, 0x00), "mfc0", R(false)), // rd is a cp0 register
+		(Coprocessor(0, 0x04), "mtc0", R(false)), // rd is a cp0 register
+		(Coprocessor(0, 0x10), "eret", Sys     ),
 		
 		(General(0x02), "j"    , J),
 		(General(0x03), "jal"  , J),
@@ -471,19 +502,124 @@ impl Cpu {
 		(Function(0x00), "nop"      , Sys     ),
 	]};
 	
+	/// Puts the CPU back the way it'd be at power-on, with the PC, `$gp`,
+	/// `$sp`, and exception handler wherever the memory map wants them.
+	pub fn reset(&mut self, map: &MemoryMap) {
+		*self = Cpu::default();
+		
+		self.pc = map.entry;
+		self[Register::gp] = map.gp;
+		self[Register::sp] = map.sp;
+		self.cp0.exception_handler = map.exception_handler;
+		self.cp0[Cp0Register::Status] = Cp0::STATUS_DEFAULT;
+	}
+	
 	pub fn tick(&mut self, mem: &mut Memory) {
-		let ins = mem.get_word(self.pc).unwrap();
-		self.do_instruction(ins, mem);
-		self.pc = self.after_delay.take()
-			.unwrap_or_else(|| self.pc.wrapping_add(WORD_BYTES as word));
+		if self.interrupt(mem) { return; }
+		
+		if let Some(ins) = self.fetch(mem) {
+			self.do_instruction(ins, mem);
+		}
+		if !std::mem::take(&mut self.trapped) {
+			self.pc = self.after_delay.take()
+				.unwrap_or_else(|| self.pc.wrapping_add(WORD_BYTES as word));
+		}
 	}
 	
 	pub fn tick_branch_delay(&mut self, mem: &mut Memory) {
-		let ins = mem.get_word(self.pc).unwrap();
-		let next_pc = self.after_delay.take()
-			.unwrap_or_else(|| self.pc.wrapping_add(WORD_BYTES as word));
-		self.do_instruction(ins, mem);
-		self.pc = next_pc;
+		if self.interrupt(mem) { return; }
+		
+		let delayed = self.after_delay.take();
+		let next_pc = delayed.unwrap_or_else(|| self.pc.wrapping_add(WORD_BYTES as word));
+		self.in_delay = delayed.is_some();
+		if let Some(ins) = self.fetch(mem) {
+			self.do_instruction(ins, mem);
+		}
+		self.in_delay = false;
+		if std::mem::take(&mut self.trapped) {
+			// a branch in here never gets to happen. if it was the delay slot
+			// that trapped, `ExPC` points at the branch so it happens again.
+			self.after_delay = None;
+		} else {
+			self.pc = next_pc;
+		}
+	}
+	
+	/// Ticks every device, then jumps to the exception handler if any of them
+	/// raised an interrupt the CPU's listening for. Returns true if it did,
+	/// in which case there's no instruction this tick.
+	/// 
+	/// Interrupts wait until after branch delay slots, since there's no good
+	/// way to come back to one.
+	fn interrupt(&mut self, mem: &mut Memory) -> bool {
+		use Cp0Register::*;
+		
+		self.pending |= mem.tick_devices();
+		
+		let status = self.cp0[Status];
+		let lines = self.pending & (status >> 8) as u8;
+		let enabled = status & Cp0::INTERRUPT_ENABLE != 0
+			&& status & Cp0::EXCEPTION_LEVEL == 0;
+		if !enabled || lines == 0 || self.after_delay.is_some() { return false; }
+		
+		self.pending &= !lines;
+		self.cp0[Cause] = (self.cp0[Cause] & !0xFF00) | (lines as word) << 8;
+		self.exception(ExceptionCause::Int);
+		
+		// the PC's already at the handler, with nothing left to step past
+		self.trapped = false;
+		true
+	}
+	
+	/// Checks that the CPU is allowed to `access` the `size` bytes at `addr`,
+	/// and raises an address error if it isn't.
+	fn check_access(&mut self, mem: &Memory, addr: word, size: word, access: Access) -> bool {
+		if addr.is_multiple_of(size) && mem.map.permits(addr, access) { return true; }
+		
+		self.cp0[Cp0Register::BadVAddr] = addr;
+		self.exception(match access {
+			Access::Load | Access::Fetch => ExceptionCause::AdEL,
+			Access::Store => ExceptionCause::AdES,
+		});
+		false
+	}
+	
+	fn fetch(&mut self, mem: &Memory) -> Option<word> {
+		if !self.check_access(mem, self.pc, WORD_BYTES, Access::Fetch) { return None; }
+		mem.get_word(self.pc)
+	}
+	
+	/// Loads a byte, half, or word, depending on `size`.
+	fn load(&mut self, mem: &mut Memory, addr: word, size: word) -> Option<word> {
+		if !self.check_access(mem, addr, size, Access::Load) { return None; }
+		
+		mem.device_before_load(addr, size);
+		let val = match size {
+			1 => Some(mem.get_byte(addr) as word),
+			2 => mem.get_half(addr).map(word::from),
+			_ => mem.get_word(addr),
+		};
+		mem.device_load(addr, size);
+		val
+	}
+	
+	/// Stores a byte, half, or word, depending on `size`.
+	fn store(&mut self, mem: &mut Memory, addr: word, size: word, val: word) {
+		if !self.check_access(mem, addr, size, Access::Store) { return; }
+		
+		let stored = match size {
+			1 => mem.set_byte(addr, val as u8),
+			2 => mem.set_half(addr, val as u16),
+			_ => mem.set_word(addr, val),
+		};
+		
+		// only happens if memory's run out of pages
+		if stored.is_none() {
+			self.cp0[Cp0Register::BadVAddr] = addr;
+			self.exception(ExceptionCause::Dbe);
+		} else {
+			mem.device_store(addr, size);
+		}
 	}
 	
 	pub fn do_instruction(&mut self, ins: word, mem: &mut Memory) {
@@ -498,6 +634,7 @@ impl Cpu {
 		let imm = bits_span(ins, 0, 16);      // immediate value
 		let se_imm = smear_bit(imm, 15);      // sign-extended immediate val
 		let b_addr = smear_bit(imm, 15) << 2; // sign-extended address
+		let m_addr = self[rs].wrapping_add(se_imm); // load/store address
 		
 		// R format only
 		let function = bits_span(ins, 0, 6);
@@ -574,6 +711,17 @@ impl Cpu {
 				
 				_ => panic!("no impl for {opcode:02x} fn {function:02x}"),
 			},
+			0x10 => match bits_span(ins, 21, 5) {
+				/*mfc0 */ 0x00 => self[rt] = self.cp0.reg[rd as usize & 0xF],
+				/*mtc0 */ 0x04 => self.cp0.reg[rd as usize & 0xF] = self[rt],
+				/*eret */ 0x10 if function == 0x18 => {
+					self.cp0[Cp0Register::Status] &= !Cp0::EXCEPTION_LEVEL;
+					self.pc = self.cp0[Cp0Register::ExPC];
+					self.after_delay = None;
+					self.trapped = true;
+				},
+				f => panic!("no impl for {opcode:02x} {f:02x}"),
+			},
 			/*j    */ 0x02 => self.after_delay = Some(j_addr),
 			/*jal  */ 0x03 => { self[ra] = self.pc.wrapping_add(WORD_BYTES as word * 2); self.after_delay = Some(j_addr); },
 			/*beq  */ 0x04 => if self[rs] == self[rt] { self.after_delay = Some(self.pc.wrapping_add(b_addr).wrapping_add(WORD_BYTES as word)); },
@@ -586,12 +734,12 @@ impl Cpu {
 			/*ori  */ 0x0d => self[rt] = self[rs] | imm,
 			/*xori */ 0x0e => self[rt] = self[rs] ^ imm,
 			/*lui  */ 0x0f => self[rt] = imm << 16,
-			/*lw   */ 0x23 => self[rt] = mem.get_word(self[rs] + se_imm).unwrap(),
-			/*lbu  */ 0x24 => self[rt] = mem.get_byte(self[rs] + se_imm).unwrap() as word,
-			/*lhu  */ 0x25 => self[rt] = mem.get_word(self[rs] + se_imm).unwrap() & 0xFFFF,
-			/*sb   */ 0x28 => { mem.set_byte(self[rs] + se_imm, (self[rt] & 0xFF) as u8); },
-			/*sh   */ 0x29 => { mem.set_word(self[rs] + se_imm, self[rt] & 0xFFFF); },
-			/*sw   */ 0x2b => { mem.set_word(self[rs] + se_imm, self[rt]); },
+			/*lw   */ 0x23 => if let Some(w) = self.load(mem, m_addr, 4) { self[rt] = w; },
+			/*lbu  */ 0x24 => if let Some(w) = self.load(mem, m_addr, 1) { self[rt] = w; },
+			/*lhu  */ 0x25 => if let Some(w) = self.load(mem, m_addr, 2) { self[rt] = w; },
+			/*sb   */ 0x28 => self.store(mem, m_addr, 1, self[rt] & 0xFF),
+			/*sh   */ 0x29 => self.store(mem, m_addr, 2, self[rt] & 0xFFFF),
+			/*sw   */ 0x2b => self.store(mem, m_addr, 4, self[rt]),
 			
 			_ => panic!("no impl for {opcode:02x}"),
 		}
@@ -601,7 +749,7 @@ impl Cpu {
 		let opcode = bits_span(ins, 26, 6);
 		let opcode_ty = match opcode {
 			0x00 => Opcode::Function(bits_span(ins, 0, 6) as u8),
-			// 0x10 => Opcode::Coprocessor(bits_span(ins, 21, 5) as u8),
+			0x10..=0x13 => Opcode::Coprocessor(opcode as u8 & 0x3, bits_span(ins, 21, 5) as u8),
 			_    => Opcode::General(opcode as u8),
 		};
 		
@@ -624,9 +772,19 @@ impl Cpu {
 					let shamt = bits_span(ins, 6, 5);
 					Some(format!("{ins_name} ${rd:?}, ${rs:?}, ${rt:?}, {shamt}"))
 				},
+				// mfc0 and mtc0 move between rt and a cp0 register, which
+				// doesn't have a name
+				R(_) if matches!(ins_name, "mfc0" | "mtc0") => {
+					Some(format!("{ins_name} ${rt:?}, ${}", rd as u8))
+				},
 				R(_) => {
 					Some(format!("{ins_name} ${rd:?}, ${rs:?}, ${rt:?}"))
 				},
+				// lui doesn't use rs, so it'd just be $zero
+				I if ins_name == "lui" => {
+					let imm = bits_span(ins, 0, 16);
+					Some(format!("{ins_name} ${rt:?}, {imm:#X}"))
+				},
 				I => {
 					let imm = bits_span(ins, 0, 16);
 					Some(format!("{ins_name} ${rt:?}, ${rs:?}, {imm:#X}"))
@@ -635,6 +793,7 @@ impl Cpu {
 					let j_addr = bits_span(ins, 0, 26) << 2;
 					Some(format!("{ins_name} {j_addr:#010X}"))
 				},
+				Sys if ins_name == "eret" => Some(ins_name.to_owned()),
 				Sys => {
 					let code = bits_span(ins, 6, 20);
 					Some(format!("{ins_name} {code:#X}"))
@@ -645,120 +804,14 @@ impl Cpu {
 		}
 	}
 	
-	pub fn from_assembly(s: &str) -> Result<word, &'static str> {
-		let mut parts = s.split_ascii_whitespace()
-			.map(|s| s.trim())
-			.filter(|&s| !s.is_empty());
-		
-		let mnemonic = parts.next().ok_or("missing mnemonic")?;
-		let def = Self::INSTRUCTIONS.iter()
-			.find(|(_, m, _)| mnemonic == *m)
-			.cloned().ok_or("unknown mnemonic")?;
-		
-		fn maybe_comma(s: &str) -> &str {
-			s.strip_suffix(',').unwrap_or(s)
-		}
-		
-		fn register(s: &str) -> Result<Register, &'static str> {
-			maybe_comma(s)
-				.strip_prefix('$')
-				.ok_or("register name missing $ prefix") // TODO: good idea to have number version of it too?
-				.and_then(Register::try_from)
-		}
-		
-		fn literal(s: &str) -> Result<word, &'static str> {
-			let s = maybe_comma(s);
-			
-			let (s, radix) = match s.get(..2) {
-				Some("0x") => (&s[2..], 16),
-				Some("0o") => (&s[2..],  8),
-				Some("0b") => (&s[2..],  2),
-				_ => (s, 10)
-			};
-			
-			// TODO: actual error please
-			word::from_str_radix(s, radix).map_err(|_| "invalid literal")
-		}
-		
-		use Opcode::*;
-		use InsFormat::*;
-		let r = match def {
-			(Coprocessor(_, _), _, _) => {
-				unimplemented!()
-			},
-			(c, _, R(shift)) => {
-				let rd = parts.next()
-					.ok_or("missing rd register")
-					.and_then(register)?;
-				
-				let rs = parts.next()
-					.ok_or("missing rs register")
-					.and_then(register)?;
-				
-				let rt = parts.next()
-					.ok_or("missing rt register")
-					.and_then(register)?;
-				
-				let shamt = if shift {
-					parts.next()
-					.ok_or("missing shift value")
-					.and_then(literal)?
-					.try_into() // u8
-					.map_err(|_| "shift value too large for u8")?
-				} else { 0 };
-				
-				Ok(match c {
-					General(o)  => op(o, op_r(0, rd, rs, rt, shamt)),
-					Function(f) =>       op_r(f, rd, rs, rt, shamt) ,
-					_ => unimplemented!(),
-				})
-			},
-			(c, _, I) => {
-				let rt = parts.next()
-					.ok_or("missing rt register")
-					.and_then(register)?;
-				
-				let rs = parts.next()
-					.ok_or("missing rs register")
-					.and_then(register)?;
-				
-				let imm = parts.next()
-					.ok_or("missing immediate value")
-					.and_then(literal)?;
-				
-				Ok(match c {
-					General(o) => op(o, op_i(rs, rt, imm as i16)),
-					_ => unimplemented!(),
-				})
-			},
-			(c, _, J) => {
-				let j_addr = parts.next()
-					.ok_or("missing address")
-					.and_then(literal)?;
-				
-				match c {
-					General(o) => Ok(op(o, op_j(j_addr))),
-					_ => unimplemented!(),
-				}
-			},
-			(c, _, Sys) => {
-				let code = if let Some(s) = parts.next() { literal(s)? } else { 0 };
-				
-				Ok(match c {
-					Function(f) => op_syscall(code) | f as u32,
-					_ => unimplemented!(),
-				})
-			},
-		};
-		
-		// this looks gross lol
-		if parts.count() == 0 { r } else { Err("too many arguments") }
-	}
-	
 	fn exception(&mut self, cause: ExceptionCause, ) {
 		/*use Register::*;*/ use Cp0Register::*;
 		self.cp0[ExPC] = self.pc;
-		self.cp0[Cause] |= (cause as u32) << 2;
+		self.cp0[Cause] = (self.cp0[Cause] & !(0x1F << 2 | Cp0::BRANCH_DELAY)) | ((cause as u32) << 2);
+		if self.in_delay {
+			self.cp0[ExPC] = self.pc.wrapping_sub(WORD_BYTES);
+			self.cp0[Cause] |= Cp0::BRANCH_DELAY;
+		}
 		// TODO: easy way to determine if cause is from this instruction or if
 		//       it's an interrupt that just so happened to stop this instr.
 		// (so that UI can easily display '!' or ';' on the EPC)
@@ -766,7 +819,10 @@ impl Cpu {
 		// i mean the solution to that is.. just what cause it is. there's ones
 		// for external sources and internal sourcesss..
 		
+		self.cp0[Status] |= Cp0::EXCEPTION_LEVEL;
+		
 		self.pc = self.cp0.exception_handler;
+		self.trapped = true;
 		// https://devblogs.microsoft.com/oldnewthing/20180416-00/?p=98515
 		
 		// self.pc = 0x8000_0080; // TODO: ahaha.. error handling. ...
@@ -775,23 +831,22 @@ impl Cpu {
 	}
 }
 
-fn op(o: u8, x: word) -> word { ((o as word) << 26) | x }
-
-fn op_r(f: u8, rd: Register, rs: Register, rt: Register, shamt: u8) -> word {
-	((rs as word) << 21) | ((rt as word) << 16) | ((rd as word) << 11) | ((shamt as word) << 6) | f as word
-}
-
-fn op_i(rs: Register, rt: Register, imm: i16) -> word {
-	((rs as word) << 21) | ((rt as word) << 16) | ((imm as word) & 0xFFFF)
-}
-
-fn op_j(addr: word) -> word { addr >> 2 }
-
-fn op_syscall(code: word) -> word { code << 6 }
-
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::mem::Change;
+	
+	fn op(o: u8, x: word) -> word { ((o as word) << 26) | x }
+	
+	fn op_r(f: u8, rd: Register, rs: Register, rt: Register, shamt: u8) -> word {
+		((rs as word) << 21) | ((rt as word) << 16) | ((rd as word) << 11) | ((shamt as word) << 6) | f as word
+	}
+	
+	fn op_i(rs: Register, rt: Register, imm: i16) -> word {
+		((rs as word) << 21) | ((rt as word) << 16) | ((imm as word) & 0xFFFF)
+	}
+	
+	fn op_j(addr: word) -> word { addr >> 2 }
 	
 	#[test]
 	fn basic_computation() {
@@ -809,6 +864,26 @@ mod tests {
 		assert_eq!(cpu[t3], 10 << 2);
 	}
 	
+	#[test]
+	fn traps() {
+		use Register::*;
+		
+		let mut cpu = Cpu::default();
+		let mut mem = Memory::default();
+		cpu.cp0.exception_handler = 0x180;
+		let teq = op_r(0x34, zero, t0, t1, 0);
+		assert_eq!(Cpu::get_instruction_info(teq).map(|(name, _)| name), Some("teq"));
+		
+		cpu[t0] = 1; cpu[t1] = 2;
+		cpu.do_instruction(teq, &mut mem);
+		assert_eq!(cpu.pc, 0, "they aren't equal, so it doesn't trap");
+		
+		cpu[t1] = 1;
+		cpu.do_instruction(teq, &mut mem);
+		assert_eq!((cpu.cp0[Cp0Register::Cause] >> 2) & 0x1F, ExceptionCause::Tr as u32);
+		assert_eq!(cpu.pc, 0x180);
+	}
+	
 	#[test]
 	fn sign_ext() {
 		use Register::*;
@@ -828,10 +903,228 @@ mod tests {
 	}
 	
 	#[test]
-	fn lenient_parsing() {
-		assert_eq!(
-			Cpu::from_assembly("add $t0 $t0 $t0").unwrap(),
-			Cpu::from_assembly("add $t0, $t0, $t0").unwrap()
-		);
+	fn address_errors() {
+		use Register::*;
+		use crate::map::{self, MemoryMap, Segment, Permissions};
+		
+		let mut cpu = Cpu::default();
+		let mut mem = Memory::default();
+		mem.map = MemoryMap::new(vec![
+			Segment::new(map::TEXT,  0x0000..=0x0FFF, Permissions::RX),
+			Segment::new(map::DATA,  0x1000..=0x1FFF, Permissions::RW),
+			Segment::new(map::KTEXT, 0x8000..=0x8FFF, Permissions::RX),
+		]);
+		cpu.cp0.exception_handler = 0x8000;
+		
+		let cause = |cpu: &Cpu| (cpu.cp0[Cp0Register::Cause] >> 2) & 0x1F;
+		
+		// storing into .text isn't allowed
+		cpu[t0] = 0x0010;
+		cpu.do_instruction(op(0x2b, op_i(t0, t1, 0)), &mut mem);
+		assert_eq!(cause(&cpu), ExceptionCause::AdES as u32);
+		assert_eq!(cpu.cp0[Cp0Register::BadVAddr], 0x0010);
+		assert_eq!(cpu.pc, 0x8000);
+		assert_eq!(mem.get_word(0x0010), Some(0));
+		
+		// ..but loading from it is, and so's storing into .data
+		cpu[t0] = 0x1000; cpu[t1] = 0xABCD;
+		cpu.do_instruction(op(0x2b, op_i(t0, t1, 4)), &mut mem);
+		cpu.do_instruction(op(0x23, op_i(t0, t2, 4)), &mut mem);
+		assert_eq!(cpu[t2], 0xABCD);
+		
+		// misaligned loads, and loads from nowhere
+		cpu.do_instruction(op(0x23, op_i(t0, t2, 2)), &mut mem);
+		assert_eq!(cause(&cpu), ExceptionCause::AdEL as u32);
+		assert_eq!(cpu.cp0[Cp0Register::BadVAddr], 0x1002);
+		
+		cpu.do_instruction(op(0x24, op_i(zero, t2, -4)), &mut mem);
+		assert_eq!(cause(&cpu), ExceptionCause::AdEL as u32);
+		assert_eq!(cpu.cp0[Cp0Register::BadVAddr], 0xFFFF_FFFC);
+		
+		// .data isn't executable, so the PC should land on the handler
+		// instead of going on to the next instruction.
+		cpu.pc = 0x1000;
+		cpu.tick(&mut mem);
+		assert_eq!(cause(&cpu), ExceptionCause::AdEL as u32);
+		assert_eq!(cpu.cp0[Cp0Register::BadVAddr], 0x1000);
+		assert_eq!(cpu.cp0[Cp0Register::ExPC], 0x1000);
+		assert_eq!(cpu.pc, 0x8000);
+	}
+	
+	#[test]
+	fn delay_slot_exceptions() {
+		use Register::*;
+		
+		let map = MemoryMap { exception_handler: 0x180, ..Default::default() };
+		let mut cpu = Cpu::default();
+		let mut mem = Memory::default();
+		cpu.reset(&map);
+		
+		// 	beq   $zero, $zero, 0x10
+		// 	lw    $t0, 2($zero)    # misaligned
+		// 	...
+		// 	addiu $t1, $zero, 1    # where the branch goes
+		let text = [
+			op(0x04, op_i(zero, zero, 3)),
+			op(0x23, op_i(zero, t0, 2)),
+			0, 0,
+			op(0x09, op_i(zero, t1, 1)),
+		];
+		let text: Vec<u8> = text.into_iter().flat_map(u32::to_le_bytes).collect();
+		mem.set_slice(0, &text);
+		
+		cpu.tick_branch_delay(&mut mem);
+		cpu.tick_branch_delay(&mut mem);
+		let cause = cpu.cp0[Cp0Register::Cause];
+		assert_eq!((cause >> 2) & 0x1F, ExceptionCause::AdEL as u32);
+		assert_ne!(cause & Cp0::BRANCH_DELAY, 0);
+		assert_eq!(cpu.cp0[Cp0Register::ExPC], 0, "back at the branch");
+		assert_eq!(cpu.pc, 0x180);
+		
+		// once it's fixed, coming back runs the branch again
+		cpu.pc = cpu.cp0[Cp0Register::ExPC];
+		mem.set_word(4, op(0x23, op_i(zero, t0, 0)));
+		for _ in 0..3 { cpu.tick_branch_delay(&mut mem); }
+		assert_eq!(cpu[t1], 1);
+		
+		// exceptions anywhere else don't set it
+		cpu.do_instruction(op_r(0x0c, zero, zero, zero, 0), &mut mem);
+		assert_eq!(cpu.cp0[Cp0Register::Cause] & Cp0::BRANCH_DELAY, 0);
+	}
+	
+	#[test]
+	fn mars_default_layout() {
+		use Register::*;
+		use crate::map::Preset;
+		
+		let mut cpu = Cpu::default();
+		let mut mem = Memory::default();
+		mem.map = Preset::Default.map();
+		cpu.reset(&mem.map);
+		
+		// the same thing MARS would give for:
+		// 	lui   $t0, 0x1001     # la $t0, value
+		// 	lw    $t1, 0($t0)
+		// 	addiu $sp, $sp, -4
+		// 	sw    $t1, 0($sp)
+		let text = [
+			op(0x0f, op_i(zero, t0, 0x1001)),
+			op(0x23, op_i(t0, t1, 0)),
+			op(0x09, op_i(sp, sp, -4)),
+			op(0x2b, op_i(sp, t1, 0)),
+		];
+		let text: Vec<u8> = text.into_iter().flat_map(u32::to_le_bytes).collect();
+		mem.set_slice(0x0040_0000, &text);
+		mem.set_word(0x1001_0000, 0x1234_5678);
+		
+		for _ in 0..4 { cpu.tick_branch_delay(&mut mem); }
+		
+		assert_eq!(cpu.pc, 0x0040_0010);
+		assert_eq!(cpu[sp], 0x7FFF_EFF8);
+		assert_eq!(mem.get_word(0x7FFF_EFF8), Some(0x1234_5678));
+		assert_eq!(cpu.cp0[Cp0Register::Cause], 0, "no exceptions");
+	}
+	
+	#[test]
+	fn forked_machines() {
+		use Register::*;
+		
+		// 	sw    $t1, 0x100($zero)
+		// 	addiu $t1, $t1, 1
+		// 	sw    $t1, 0x104($zero)
+		let text = [
+			op(0x2b, op_i(zero, t1, 0x100)),
+			op(0x09, op_i(t1, t1, 1)),
+			op(0x2b, op_i(zero, t1, 0x104)),
+		];
+		let text: Vec<u8> = text.into_iter().flat_map(u32::to_le_bytes).collect();
+		
+		let mut cpu = Cpu::default();
+		let mut mem = Memory::default();
+		mem.set_slice(0, &text);
+		cpu[t1] = 5;
+		cpu.tick_branch_delay(&mut mem);
+		
+		// branch off right after the first store and try a different $t1
+		let (mut cpu2, mut mem2) = (cpu.clone(), mem.fork());
+		cpu2[t1] = 10;
+		
+		for _ in 0..2 {
+			cpu.tick_branch_delay(&mut mem);
+			cpu2.tick_branch_delay(&mut mem2);
+		}
+		
+		assert_eq!(mem.get_word(0x104), Some(6));
+		assert_eq!(mem2.get_word(0x104), Some(11));
+		assert_eq!(mem.diff(&mem2), [
+			Change { addr: 0x104, old: vec![6], new: vec![11] },
+		]);
+	}
+	
+	#[test]
+	fn interrupts() {
+		use Register::*;
+		use crate::mmio::KeyboardDisplay;
+		
+		let map = MemoryMap { exception_handler: 0x180, ..Default::default() };
+		
+		let mut cpu = Cpu::default();
+		let mut mem = Memory::default();
+		cpu.reset(&map);
+		mem.attach(Box::new(KeyboardDisplay::new(0xFFFF_0000)));
+		
+		// 	lui   $k0, 0xFFFF
+		// 	ori   $t1, $zero, 2
+		// 	sw    $t1, 0($k0)      # keyboard interrupts on
+		// loop:
+		// 	j     loop
+		let main = [
+			op(0x0f, op_i(zero, k0, -1)),
+			op(0x0d, op_i(zero, t1, 2)),
+			op(0x2b, op_i(k0, t1, 0)),
+			op(0x02, op_j(0x0C)),
+		];
+		
+		// 	lui   $k0, 0xFFFF
+		// 	lw    $t0, 4($k0)      # what key was it?
+		// 	eret
+		let handler = [
+			op(0x0f, op_i(zero, k0, -1)),
+			op(0x23, op_i(k0, t0, 4)),
+			0x4200_0018,
+		];
+		
+		let bytes = |ins: &[word]| ins.iter().copied().flat_map(u32::to_le_bytes).collect::<Vec<_>>();
+		mem.set_slice(0x000, &bytes(&main));
+		mem.set_slice(0x180, &bytes(&handler));
+		
+		for _ in 0..5 { cpu.tick(&mut mem); }
+		assert_eq!(cpu.pc, 0x0C, "spinning in the loop");
+		
+		mem.device_mut::<KeyboardDisplay>().unwrap().push_key(b'x');
+		cpu.tick(&mut mem);
+		assert_eq!(cpu.pc, 0x180);
+		assert_eq!(cpu.cp0[Cp0Register::ExPC], 0x0C);
+		assert_eq!(cpu.cp0[Cp0Register::Cause], 1 << 8, "keyboard interrupt");
+		assert_ne!(cpu.cp0[Cp0Register::Status] & Cp0::EXCEPTION_LEVEL, 0);
+		
+		for _ in 0..3 { cpu.tick(&mut mem); }
+		assert_eq!(cpu[t0], b'x' as word);
+		assert_eq!(cpu.pc, 0x0C, "back where it was");
+		assert_eq!(cpu.cp0[Cp0Register::Status], Cp0::STATUS_DEFAULT);
+		assert_eq!(mem.get_word(0xFFFF_0000), Some(2), "key's been read");
+		
+		// no more keys, no more interrupts
+		cpu.tick(&mut mem);
+		assert_eq!(cpu.pc, 0x0C);
+	}
+	
+	#[test]
+	fn disassembly() {
+		assert_eq!(Cpu::get_disassembly(0x3C01_1001).as_deref(), Some("lui $at, 0x1001"));
+		assert_eq!(Cpu::get_disassembly(0x3424_000C).as_deref(), Some("ori $a0, $at, 0xC"));
+		assert_eq!(Cpu::get_disassembly(0x4008_6000).as_deref(), Some("mfc0 $t0, $12"));
+		assert_eq!(Cpu::get_disassembly(0x4089_7000).as_deref(), Some("mtc0 $t1, $14"));
+		assert_eq!(Cpu::get_disassembly(0x4200_0018).as_deref(), Some("eret"));
 	}
 }
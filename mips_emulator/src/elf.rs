@@ -0,0 +1,485 @@
+//! Loads ELF32 executables, like what `mipsel-linux-gnu-gcc -static
+//! -nostdlib` makes, straight into memory.
+//!
+//! Headers get read in either byte order, so a big-endian file gets a proper
+//! error instead of garbage, but only little-endian programs can actually be
+//! loaded since that's the only way the emulator runs.
+
+use std::{fmt, sync::Arc};
+
+use super::{word, chip::Cpu, mem::Memory, map::{self, Permissions, Segment}};
+
+const HEADER_SIZE: usize = 52;
+const PROGRAM_HEADER_SIZE: u16 = 32;
+const SECTION_HEADER_SIZE: u16 = 40;
+const SYMBOL_SIZE: usize = 16;
+
+const ET_EXEC: u16 = 2;
+const EM_MIPS: u16 = 8;
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+/// The most zeroes a segment can ask for past what's in the file, same as
+/// what `.space` and `.bss` get in the assembler.
+const MAX_ZEROES: word = 0x0100_0000;
+
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+/// Everything that can be wrong with a file, as precisely as it can be
+/// pinned down.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElfError {
+	/// How long the file is, when that's too short for the ELF header.
+	TooShort(usize),
+	NotElf,
+	/// `EI_CLASS`, when it isn't 32-bit.
+	Class(u8),
+	/// `EI_DATA`, when it's neither little- nor big-endian.
+	ByteOrder(u8),
+	/// `EI_VERSION`, when it isn't 1.
+	Version(u8),
+	/// `e_type`, when it isn't an executable.
+	NotExecutable(u16),
+	/// `e_machine`, when it isn't MIPS.
+	NotMips(u16),
+	/// `e_phentsize` or `e_shentsize`, when it isn't what ELF32 says.
+	EntrySize { what: &'static str, size: u16, expected: u16 },
+	/// Something that goes past the end of the file.
+	CutOff { what: String, end: u64, len: usize },
+	NothingToLoad,
+	/// Program header n has more bytes in the file than in memory.
+	FileBiggerThanMemory(usize),
+	/// Program header n goes past the end of the address space.
+	Wraps(usize),
+	/// Program header n wants more than `MAX_ZEROES` of `.bss`.
+	TooManyZeroes(usize),
+	/// `e_entry`, when it isn't in an executable segment.
+	EntryOutside(word),
+	/// Section n's symbols have their names in a section that isn't there.
+	NoStrings(usize),
+	/// The file's fine, but it's big-endian.
+	BigEndian,
+	/// Where loading stopped, because the memory's page limit got in the way.
+	OutOfMemory(word),
+}
+
+impl fmt::Display for ElfError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		use ElfError::*;
+		match self {
+			TooShort(len) => write!(f, "it's only {len} bytes long, which is too short to be an ELF file"),
+			NotElf => write!(f, "it isn't an ELF file, since it doesn't start with \\x7FELF"),
+			Class(2) => write!(f, "it's a 64-bit ELF file, and only 32-bit ones can be loaded"),
+			Class(c) => write!(f, "unknown ELF class {c}"),
+			ByteOrder(d) => write!(f, "unknown byte order {d}, which should be 1 for little-endian or 2 for big-endian"),
+			Version(v) => write!(f, "unknown ELF version {v}"),
+			NotExecutable(1) => write!(f, "it's an object file, which has to be linked first"),
+			NotExecutable(3) => write!(f, "it's a shared object or a position-independent executable, so build it with `-static -no-pie`"),
+			NotExecutable(4) => write!(f, "it's a core dump"),
+			NotExecutable(t) => write!(f, "unknown ELF type {t}"),
+			NotMips(m) => write!(f, "it's for machine {m}, not MIPS ({EM_MIPS})"),
+			EntrySize { what, size, expected } => write!(f, "{what} are {size} bytes each, but ELF32's are {expected}"),
+			CutOff { what, end, len } => write!(f, "{what} goes up to byte {end}, but the file's only {len} bytes long"),
+			NothingToLoad => write!(f, "there aren't any PT_LOAD segments to load"),
+			FileBiggerThanMemory(i) => write!(f, "program header {i} has more bytes in the file than in memory"),
+			Wraps(i) => write!(f, "program header {i} goes past the end of memory"),
+			TooManyZeroes(i) => write!(f, "program header {i} wants more than {MAX_ZEROES:#X} bytes of zeroes"),
+			EntryOutside(entry) => write!(f, "the entry point {entry:#010X} isn't in an executable segment"),
+			NoStrings(i) => write!(f, "section {i}'s symbols have their names in a section that isn't there"),
+			BigEndian => write!(f, "it's big-endian, but the emulator's little-endian (build it with mipsel-linux-gnu-gcc)"),
+			OutOfMemory(addr) => write!(f, "ran out of pages loading {addr:#010X}"),
+		}
+	}
+}
+
+impl std::error::Error for ElfError {}
+
+/// A `PT_LOAD` segment: what goes where, and what the program's allowed to
+/// do with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadSegment {
+	pub addr: word,
+	/// What's in the file. Anything past this up to `mem_size` is zeroes,
+	/// which is where `.bss` usually is.
+	pub data: Vec<u8>,
+	pub mem_size: word,
+	pub perms: Permissions,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+	pub name: String,
+	pub addr: word,
+	/// How many bytes it covers, or 0 if nobody said.
+	pub size: word,
+	pub function: bool,
+}
+
+/// Symbols by address, for showing where things are while debugging. See
+/// [`Memory::symbols`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols(Vec<Symbol>);
+
+impl Symbols {
+	pub fn new(mut symbols: Vec<Symbol>) -> Self {
+		symbols.sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+		Symbols(symbols)
+	}
+	
+	pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+		self.0.iter()
+	}
+	
+	pub fn len(&self) -> usize { self.0.len() }
+	pub fn is_empty(&self) -> bool { self.0.is_empty() }
+	
+	/// Finds a symbol by its name.
+	pub fn get(&self, name: &str) -> Option<&Symbol> {
+		self.0.iter().find(|s| s.name == name)
+	}
+	
+	/// The symbol `addr` is in, and how far into it, like `main+0x10`. That's
+	/// the closest one at or before it, as long as `addr` isn't past its end.
+	pub fn lookup(&self, addr: word) -> Option<(&Symbol, word)> {
+		let after = self.0.partition_point(|s| s.addr <= addr);
+		let s = self.0[..after].last()?;
+		let offset = addr - s.addr;
+		(s.size == 0 || offset < s.size).then_some((s, offset))
+	}
+	
+	/// `addr` written like `main+0x10`, or just `main` if it's right at the
+	/// start of one.
+	pub fn describe(&self, addr: word) -> Option<String> {
+		self.lookup(addr).map(|(s, offset)| match offset {
+			0 => s.name.clone(),
+			_ => format!("{}+{offset:#X}", s.name),
+		})
+	}
+}
+
+/// A parsed executable, ready to load.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Elf {
+	pub big_endian: bool,
+	pub entry: word,
+	pub segments: Vec<LoadSegment>,
+	/// Everything in `.symtab`, if it wasn't stripped.
+	pub symbols: Symbols,
+}
+
+/// Reads numbers out of the file in whichever byte order it's in.
+struct Reader<'a> {
+	bytes: &'a [u8],
+	big: bool,
+}
+
+impl Reader<'_> {
+	fn u16(&self, at: usize) -> u16 {
+		let b = [self.bytes[at], self.bytes[at + 1]];
+		if self.big { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }
+	}
+	
+	fn u32(&self, at: usize) -> u32 {
+		let b = self.bytes[at..at + 4].try_into().unwrap();
+		if self.big { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+	}
+	
+	/// `count` things of `size` bytes starting at `offset`, or which byte
+	/// they'd go up to if that's past the end.
+	fn table(&self, what: impl Into<String>, offset: word, count: usize, size: usize) -> Result<&[u8], ElfError> {
+		let end = offset as u64 + (count * size) as u64;
+		if end > self.bytes.len() as u64 {
+			return Err(ElfError::CutOff { what: what.into(), end, len: self.bytes.len() });
+		}
+		Ok(&self.bytes[offset as usize..end as usize])
+	}
+}
+
+impl Elf {
+	pub fn parse(bytes: &[u8]) -> Result<Elf, ElfError> {
+		if bytes.len() < HEADER_SIZE {
+			return Err(ElfError::TooShort(bytes.len()));
+		}
+		if bytes[..4] != *b"\x7FELF" {
+			return Err(ElfError::NotElf);
+		}
+		if bytes[4] != 1 {
+			return Err(ElfError::Class(bytes[4]));
+		}
+		let big = match bytes[5] {
+			1 => false,
+			2 => true,
+			d => return Err(ElfError::ByteOrder(d)),
+		};
+		if bytes[6] != 1 {
+			return Err(ElfError::Version(bytes[6]));
+		}
+		
+		let r = Reader { bytes, big };
+		match (r.u16(16), r.u16(18)) {
+			(ET_EXEC, EM_MIPS) => (),
+			(ET_EXEC, machine) => return Err(ElfError::NotMips(machine)),
+			(kind, _) => return Err(ElfError::NotExecutable(kind)),
+		}
+		let entry = r.u32(24);
+		
+		// a table with no entries can have any size it likes
+		let entries = |what, count: u16, size: u16, expected| match size {
+			_ if count == 0 || size == expected => Ok(count as usize),
+			size => Err(ElfError::EntrySize { what, size, expected }),
+		};
+		let programs = entries("program headers", r.u16(44), r.u16(42), PROGRAM_HEADER_SIZE)?;
+		let sections = entries("section headers", r.u16(48), r.u16(46), SECTION_HEADER_SIZE)?;
+		
+		let headers = r.table("the program header table", r.u32(28), programs, PROGRAM_HEADER_SIZE as usize)?;
+		let mut segments = Vec::new();
+		for (i, h) in headers.chunks(PROGRAM_HEADER_SIZE as usize).enumerate() {
+			let h = Reader { bytes: h, big };
+			if h.u32(0) != PT_LOAD {
+				continue;
+			}
+			let (offset, addr, file_size, mem_size, flags) = (h.u32(4), h.u32(8), h.u32(16), h.u32(20), h.u32(24));
+			
+			if file_size > mem_size {
+				return Err(ElfError::FileBiggerThanMemory(i));
+			}
+			if addr.checked_add(mem_size).is_none() {
+				return Err(ElfError::Wraps(i));
+			}
+			if mem_size - file_size > MAX_ZEROES {
+				return Err(ElfError::TooManyZeroes(i));
+			}
+			let data = r.table(format!("program header {i}'s segment"), offset, file_size as usize, 1)?;
+			segments.push(LoadSegment {
+				addr,
+				data: data.to_vec(),
+				mem_size,
+				perms: Permissions {
+					read: flags & PF_R != 0,
+					write: flags & PF_W != 0,
+					execute: flags & PF_X != 0,
+				},
+			});
+		}
+		
+		if segments.is_empty() {
+			return Err(ElfError::NothingToLoad);
+		}
+		let runnable = segments.iter().any(|s| s.perms.execute && (s.addr..s.addr + s.mem_size).contains(&entry));
+		if !runnable {
+			return Err(ElfError::EntryOutside(entry));
+		}
+		
+		let headers = r.table("the section header table", r.u32(32), sections, SECTION_HEADER_SIZE as usize)?;
+		let header = |i: usize| Reader { bytes: &headers[i * SECTION_HEADER_SIZE as usize..], big };
+		let mut symbols = Vec::new();
+		for i in (0..sections).filter(|&i| header(i).u32(4) == SHT_SYMTAB) {
+			let h = header(i);
+			let (offset, size, link) = (h.u32(16), h.u32(20), h.u32(24) as usize);
+			let table = r.table(format!("section {i}'s symbols"), offset, size as usize, 1)?;
+			if link >= sections {
+				return Err(ElfError::NoStrings(i));
+			}
+			let names = r.table(format!("section {link}'s names"), header(link).u32(16), header(link).u32(20) as usize, 1)?;
+			
+			for s in table.chunks_exact(SYMBOL_SIZE).skip(1) {
+				let s = Reader { bytes: s, big };
+				let (name, kind, section) = (s.u32(0) as usize, s.bytes[12] & 0xF, s.u16(14));
+				if section == 0 || kind == STT_SECTION || kind == STT_FILE {
+					continue;
+				}
+				let name = names.get(name..).unwrap_or_default();
+				let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+				if name.is_empty() {
+					continue;
+				}
+				symbols.push(Symbol {
+					name: String::from_utf8_lossy(name).into_owned(),
+					addr: s.u32(4),
+					size: s.u32(8),
+					function: kind == STT_FUNC,
+				});
+			}
+		}
+		
+		Ok(Elf { big_endian: big, entry, segments, symbols: Symbols::new(symbols) })
+	}
+	
+	/// Copies every segment into `mem`, with the rest of each one past what
+	/// was in the file zeroed, and starts `cpu` over at the entry point.
+	/// 
+	/// Each segment also goes at the front of the memory map, with the
+	/// permissions the file asked for, since compilers put things wherever
+	/// they like (like `.data` straight after `.text`). Those replace the
+	/// ones from whatever was loaded before, if anything. `$gp` starts at
+	/// `_gp`, if there is one, and the symbols go in `mem.symbols`.
+	pub fn load(&self, cpu: &mut Cpu, mem: &mut Memory) -> Result<(), ElfError> {
+		if self.big_endian {
+			return Err(ElfError::BigEndian);
+		}
+		
+		let loaded = mem.map.loaded.min(mem.map.segments.len());
+		mem.map.segments.drain(..loaded);
+		mem.map.loaded = 0;
+		
+		for s in self.segments.iter().rev() {
+			mem.set_slice(s.addr, &s.data).ok_or(ElfError::OutOfMemory(s.addr))?;
+			mem.zero(s.addr + s.data.len() as word, (s.mem_size as usize) - s.data.len());
+			
+			if s.mem_size > 0 {
+				let name = if s.perms.execute { map::TEXT } else { map::DATA };
+				let range = s.addr & !3..=(s.addr + s.mem_size - 1) | 3;
+				mem.map.segments.insert(0, Segment::new(name, range, s.perms));
+				mem.map.loaded += 1;
+			}
+		}
+		
+		mem.map.entry = self.entry;
+		if let Some(gp) = self.symbols.get("_gp") {
+			mem.map.gp = gp.addr;
+		}
+		mem.symbols = Arc::new(self.symbols.clone());
+		cpu.reset(&mem.map);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{chip::Register, map::Preset};
+	
+	/// A little program with code, data, `.bss`, and a couple of symbols,
+	/// the way a linker would lay it out.
+	fn fixture(big: bool) -> Vec<u8> {
+		let mut out = Vec::new();
+		let h = |out: &mut Vec<u8>, n: u16| out.extend(if big { n.to_be_bytes() } else { n.to_le_bytes() });
+		let w = |out: &mut Vec<u8>, n: u32| out.extend(if big { n.to_be_bytes() } else { n.to_le_bytes() });
+		
+		out.extend(b"\x7FELF");
+		out.extend([1, if big { 2 } else { 1 }, 1]);
+		out.resize(16, 0);
+		h(&mut out, ET_EXEC);
+		h(&mut out, EM_MIPS);
+		w(&mut out, 1);
+		w(&mut out, 0x0040_0000); // entry
+		w(&mut out, 52);          // program headers
+		w(&mut out, 212);         // section headers
+		w(&mut out, 0);
+		for n in [52, 32, 2, 40, 3, 0] {
+			h(&mut out, n);
+		}
+		
+		// .text, and then .data with 12 bytes of .bss after it
+		for n in [PT_LOAD, 116, 0x0040_0000, 0x0040_0000, 12, 12, PF_R | PF_X, 4] {
+			w(&mut out, n);
+		}
+		for n in [PT_LOAD, 128, 0x0041_0000, 0x0041_0000, 4, 16, PF_R | PF_W, 4] {
+			w(&mut out, n);
+		}
+		
+		// addiu $t0, $zero, 5 / lui $t1, 0x41 / sw $t0, 4($t1)
+		for n in [0x2408_0005, 0x3C09_0041, 0xAD28_0004, 0x1122_3344] {
+			w(&mut out, n);
+		}
+		
+		out.extend(b"\0main\0buffer\0\0\0\0");
+		out.extend([0; SYMBOL_SIZE]);
+		for (name, addr, size, info) in [(1, 0x0040_0000, 12, 0x12), (6, 0x0041_0004, 12, 0x11), (0, 0, 0, STT_SECTION)] {
+			for n in [name, addr, size] {
+				w(&mut out, n);
+			}
+			out.extend([info, 0]);
+			h(&mut out, 1);
+		}
+		
+		out.extend([0; 40]);
+		for n in [0, SHT_SYMTAB, 0, 0, 148, 64, 2, 1, 4, SYMBOL_SIZE as u32] {
+			w(&mut out, n);
+		}
+		for n in [0, 3, 0, 0, 132, 16, 0, 0, 1, 0] {
+			w(&mut out, n);
+		}
+		out
+	}
+	
+	#[test]
+	fn loading() {
+		let elf = Elf::parse(&fixture(false)).unwrap();
+		assert_eq!(elf.entry, 0x0040_0000);
+		assert_eq!(elf.segments.len(), 2);
+		assert_eq!(elf.segments[1].perms, Permissions::RW);
+		assert_eq!(elf.symbols.lookup(0x0040_0008).map(|(s, off)| (s.name.as_str(), off)), Some(("main", 8)));
+		assert_eq!(elf.symbols.lookup(0x0041_0000), None);
+		assert_eq!(elf.symbols.lookup(0x0041_0010), None, "past the end of buffer");
+		assert!(elf.symbols.get("main").unwrap().function);
+		
+		// MARS' layout has .text where the data's going, which isn't
+		// writable, and there's junk where the .bss goes
+		let mut mem = Memory::default();
+		mem.map = Preset::Default.map();
+		mem.fill(0x0041_0000, 0x20, 0xAA);
+		let mut cpu = Cpu::default();
+		elf.load(&mut cpu, &mut mem).unwrap();
+		
+		assert_eq!(cpu.pc, 0x0040_0000);
+		assert_eq!(mem.symbols.describe(0x0040_0008).as_deref(), Some("main+0x8"));
+		assert_eq!(mem.get_word(0x0041_0000), Some(0x1122_3344));
+		assert_eq!(mem.get_word(0x0041_000C), Some(0), "bss gets zeroed");
+		assert_eq!(mem.get_word(0x0041_0010), Some(0xAAAA_AAAA), "and nothing past it");
+		
+		for _ in 0..3 {
+			cpu.tick(&mut mem);
+		}
+		assert_eq!(cpu[Register::t0], 5);
+		assert_eq!(mem.get_word(0x0041_0004), Some(5));
+		
+		// loading it again swaps its segments out instead of piling more on
+		elf.load(&mut cpu, &mut mem).unwrap();
+		assert_eq!(mem.map.segments.len(), Preset::Default.map().segments.len() + 2);
+		assert_eq!(mem.map.find(0x0041_0000).unwrap().perms, Permissions::RW);
+	}
+	
+	#[test]
+	fn big_endian() {
+		let little = Elf::parse(&fixture(false)).unwrap();
+		let big = Elf::parse(&fixture(true)).unwrap();
+		assert!(big.big_endian);
+		assert_eq!((big.entry, &big.symbols), (little.entry, &little.symbols));
+		assert_eq!(big.segments[0].data[..4], [0x24, 0x08, 0x00, 0x05]);
+		
+		let mut mem = Memory::default();
+		assert_eq!(big.load(&mut Cpu::default(), &mut mem), Err(ElfError::BigEndian));
+		assert!(!mem.is_allocated(0x0040_0000));
+	}
+	
+	#[test]
+	fn errors() {
+		let elf = fixture(false);
+		let broken = |at: usize, bytes: &[u8]| {
+			let mut elf = elf.clone();
+			elf[at..at + bytes.len()].copy_from_slice(bytes);
+			Elf::parse(&elf).unwrap_err().to_string()
+		};
+		
+		assert_eq!(Elf::parse(&elf[..40]).unwrap_err().to_string(), "it's only 40 bytes long, which is too short to be an ELF file");
+		assert_eq!(broken(0, b"\x7FELG"), "it isn't an ELF file, since it doesn't start with \\x7FELF");
+		assert_eq!(broken(4, &[2]), "it's a 64-bit ELF file, and only 32-bit ones can be loaded");
+		assert_eq!(broken(5, &[3]), "unknown byte order 3, which should be 1 for little-endian or 2 for big-endian");
+		assert_eq!(broken(16, &[1, 0]), "it's an object file, which has to be linked first");
+		assert_eq!(broken(18, &[3, 0]), "it's for machine 3, not MIPS (8)");
+		assert_eq!(broken(42, &[56, 0]), "program headers are 56 bytes each, but ELF32's are 32");
+		assert_eq!(broken(28, &[0x30, 1]), "the program header table goes up to byte 368, but the file's only 332 bytes long");
+		assert_eq!(broken(88, &[0, 2]), "program header 1's segment goes up to byte 516, but the file's only 332 bytes long");
+		assert_eq!(broken(100, &[20]), "program header 1 has more bytes in the file than in memory");
+		assert_eq!(broken(104, &[0, 0, 0, 2]), "program header 1 wants more than 0x1000000 bytes of zeroes");
+		assert_eq!(broken(24, &[0, 0, 0x41]), "the entry point 0x00410000 isn't in an executable segment");
+		assert_eq!(broken(276, &[9]), "section 1's symbols have their names in a section that isn't there");
+	}
+}
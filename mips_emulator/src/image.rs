@@ -0,0 +1,486 @@
+//! Program images in the formats other tools pass around: raw binary, MARS'
+//! `HexText` and `BinaryText` dumps, Intel HEX, and Motorola S-records.
+//!
+//! Intel HEX and S-records say where everything goes. The rest are just
+//! what's in one section, so they go wherever they're told.
+
+use std::fmt;
+
+use super::{word, mem::Memory};
+
+/// How far apart the lowest and highest bytes can be for the formats without
+/// addresses, so a couple of bytes at either end of memory don't turn into
+/// gigabytes of zeroes.
+const MAX_SPAN: u64 = 0x0100_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+	/// Just the bytes, like MARS' `Binary` dumps (and `build.sh`'s).
+	Binary,
+	/// A word per line, as 8 hex digits.
+	HexText,
+	/// A word per line, as 32 binary digits.
+	BinaryText,
+	IntelHex,
+	SRecord,
+}
+
+impl Format {
+	pub const ALL: [Format; 5] = [Format::Binary, Format::HexText, Format::BinaryText, Format::IntelHex, Format::SRecord];
+	
+	/// What it's called on the command line. The first three are what MARS'
+	/// `dump` option calls them.
+	pub const fn name(self) -> &'static str {
+		match self {
+			Format::Binary => "Binary",
+			Format::HexText => "HexText",
+			Format::BinaryText => "BinaryText",
+			Format::IntelHex => "IntelHex",
+			Format::SRecord => "SRecord",
+		}
+	}
+	
+	pub fn from_name(name: &str) -> Option<Format> {
+		Format::ALL.into_iter().find(|f| f.name().eq_ignore_ascii_case(name))
+	}
+	
+	/// The file extension it usually has.
+	pub const fn extension(self) -> &'static str {
+		match self {
+			Format::Binary => "bin",
+			Format::HexText | Format::BinaryText => "txt",
+			Format::IntelHex => "hex",
+			Format::SRecord => "srec",
+		}
+	}
+	
+	/// Works out which format `bytes` is in. Anything that doesn't look like
+	/// one of the text formats is binary.
+	pub fn detect(bytes: &[u8]) -> Format {
+		let Ok(text) = std::str::from_utf8(bytes) else { return Format::Binary };
+		let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty()).peekable();
+		let Some(first) = lines.peek().copied() else { return Format::Binary };
+		
+		let all = |digits: usize, radix: u32| text.lines().map(str::trim).filter(|l| !l.is_empty())
+			.all(|l| l.len() == digits && l.chars().all(|c| c.is_digit(radix)));
+		match first.as_bytes() {
+			[b':', ..] => Format::IntelHex,
+			[b'S', b'0'..=b'9', ..] => Format::SRecord,
+			_ if all(32, 2) => Format::BinaryText,
+			_ if all(8, 16) => Format::HexText,
+			_ => Format::Binary,
+		}
+	}
+	
+	/// Reads an image in this format. If the format doesn't say where things
+	/// go, they go at `base`.
+	pub fn read(self, bytes: &[u8], base: word) -> Result<Image, ImageError> {
+		if self == Format::Binary {
+			let mut image = Image::default();
+			image.push(base, bytes);
+			return Ok(image);
+		}
+		
+		let text = std::str::from_utf8(bytes).map_err(|_| ImageError { line: 0, message: "it isn't text" })?;
+		match self {
+			Format::Binary => unreachable!(),
+			Format::HexText => read_words(text, base, 16),
+			Format::BinaryText => read_words(text, base, 2),
+			Format::IntelHex => read_intel_hex(text),
+			Format::SRecord => read_srecord(text),
+		}
+	}
+	
+	/// Writes `image` out in this format. The formats without addresses get
+	/// everything from the lowest address to the highest, with zeroes in any
+	/// gaps, and the text ones get padded out to a whole word. Those fail if
+	/// the gaps are too big; see [`Image::flatten`].
+	pub fn write(self, image: &Image) -> Result<Vec<u8>, ImageError> {
+		let words = |image: &Image, digits: fn(word) -> String| {
+			let (_, mut bytes) = image.flatten()?;
+			bytes.resize(bytes.len().next_multiple_of(4), 0);
+			Ok(bytes.chunks(4).map(|w| digits(word::from_le_bytes(w.try_into().unwrap()))).collect::<String>().into_bytes())
+		};
+		
+		match self {
+			Format::Binary => image.flatten().map(|(_, bytes)| bytes),
+			Format::HexText => words(image, |w| format!("{w:08x}\n")),
+			Format::BinaryText => words(image, |w| format!("{w:032b}\n")),
+			Format::IntelHex => Ok(write_intel_hex(image).into_bytes()),
+			Format::SRecord => Ok(write_srecord(image).into_bytes()),
+		}
+	}
+}
+
+impl fmt::Display for Format {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// Some bytes that go together, starting at `addr`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Chunk {
+	pub addr: word,
+	pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Image {
+	/// In the order they were in the file, with anything that carried on
+	/// from the record before merged together.
+	pub chunks: Vec<Chunk>,
+	/// Where to start running, if the file said.
+	pub entry: Option<word>,
+}
+
+impl Image {
+	/// Reads an image in whichever format it's in. See [`Format::read`].
+	pub fn read(bytes: &[u8], base: word) -> Result<Image, ImageError> {
+		Format::detect(bytes).read(bytes, base)
+	}
+	
+	/// Puts every chunk where it goes. Fails if the memory runs out of pages.
+	pub fn load(&self, mem: &mut Memory) -> Option<()> {
+		for chunk in &self.chunks {
+			mem.set_slice(chunk.addr, &chunk.bytes)?;
+		}
+		Some(())
+	}
+	
+	pub fn push(&mut self, addr: word, bytes: &[u8]) {
+		if bytes.is_empty() {
+			return;
+		}
+		match self.chunks.last_mut() {
+			Some(last) if last.addr.wrapping_add(last.bytes.len() as word) == addr => last.bytes.extend_from_slice(bytes),
+			_ => self.chunks.push(Chunk { addr, bytes: bytes.to_vec() }),
+		}
+	}
+	
+	/// Everything from the lowest address to the highest, with zeroes in the
+	/// gaps. Fails if that's more than 16 MiB, since it'd be almost all gap.
+	pub fn flatten(&self) -> Result<(word, Vec<u8>), ImageError> {
+		let Some(start) = self.chunks.iter().map(|c| c.addr).min() else { return Ok((0, vec![])) };
+		let end = self.chunks.iter().map(|c| c.addr as u64 + c.bytes.len() as u64).max().unwrap_or_default();
+		if end - start as u64 > MAX_SPAN {
+			return Err(ImageError { line: 0, message: "the chunks are too far apart to write without addresses" });
+		}
+		
+		let mut bytes = vec![];
+		for chunk in &self.chunks {
+			let at = (chunk.addr - start) as usize;
+			if bytes.len() < at + chunk.bytes.len() {
+				bytes.resize(at + chunk.bytes.len(), 0);
+			}
+			bytes[at..at + chunk.bytes.len()].copy_from_slice(&chunk.bytes);
+		}
+		Ok((start, bytes))
+	}
+}
+
+/// What's wrong with an image, and which line it's on (or 0 if it isn't on
+/// any line in particular).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageError {
+	pub line: usize,
+	pub message: &'static str,
+}
+
+impl fmt::Display for ImageError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.line {
+			0 => f.write_str(self.message),
+			line => write!(f, "line {line}: {}", self.message),
+		}
+	}
+}
+
+impl std::error::Error for ImageError {}
+
+/// The lines that have anything on them, numbered from 1.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+	text.lines().map(str::trim).enumerate().map(|(i, l)| (i + 1, l)).filter(|(_, l)| !l.is_empty())
+}
+
+fn read_words(text: &str, base: word, radix: u32) -> Result<Image, ImageError> {
+	let (digits, message) = match radix {
+		16 => (8, "words should be 8 hex digits"),
+		_ => (32, "words should be 32 binary digits"),
+	};
+	
+	let mut bytes = vec![];
+	for (line, w) in lines(text) {
+		if w.len() != digits || !w.chars().all(|c| c.is_digit(radix)) {
+			return Err(ImageError { line, message });
+		}
+		bytes.extend(word::from_str_radix(w, radix).unwrap().to_le_bytes());
+	}
+	
+	let mut image = Image::default();
+	image.push(base, &bytes);
+	Ok(image)
+}
+
+/// Pairs of hex digits, without anything else in between.
+fn hex_bytes(s: &str) -> Option<Vec<u8>> {
+	if !s.len().is_multiple_of(2) || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+	Some((0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect())
+}
+
+fn big_endian(bytes: &[u8]) -> word {
+	bytes.iter().fold(0, |n, &b| n << 8 | b as word)
+}
+
+fn read_intel_hex(text: &str) -> Result<Image, ImageError> {
+	let mut image = Image::default();
+	// from the extended address records
+	let mut upper: word = 0;
+	
+	for (line, record) in lines(text) {
+		let error = |message| ImageError { line, message };
+		let record = record.strip_prefix(':').ok_or(error("records should start with `:`"))?;
+		let bytes = hex_bytes(record).ok_or(error("records should be pairs of hex digits"))?;
+		if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+			return Err(error("the record's length doesn't match its byte count"));
+		}
+		if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+			return Err(error("the checksum's wrong"));
+		}
+		
+		let addr = big_endian(&bytes[1..3]);
+		let data = &bytes[4..bytes.len() - 1];
+		let sized = |len| if data.len() == len { Ok(big_endian(data)) } else { Err(error("the record's the wrong size for its type")) };
+		match bytes[3] {
+			0x00 => image.push(upper.wrapping_add(addr), data),
+			0x01 => return Ok(image),
+			0x02 => upper = sized(2)? << 4,
+			0x03 => {
+				let at = sized(4)?;
+				image.entry = Some((at >> 16 << 4).wrapping_add(at & 0xFFFF));
+			},
+			0x04 => upper = sized(2)? << 16,
+			0x05 => image.entry = Some(sized(4)?),
+			_ => return Err(error("unknown record type")),
+		}
+	}
+	Err(ImageError { line: 0, message: "there's no end-of-file record" })
+}
+
+fn read_srecord(text: &str) -> Result<Image, ImageError> {
+	let mut image = Image::default();
+	
+	for (line, record) in lines(text) {
+		let error = |message| ImageError { line, message };
+		let record = record.strip_prefix('S').ok_or(error("records should start with `S`"))?;
+		let addr_len = match record.as_bytes().first() {
+			Some(b'0' | b'1' | b'5' | b'9') => 2,
+			Some(b'2' | b'6' | b'8') => 3,
+			Some(b'3' | b'7') => 4,
+			_ => return Err(error("unknown record type")),
+		};
+		let bytes = hex_bytes(&record[1..]).ok_or(error("records should be pairs of hex digits"))?;
+		if bytes.is_empty() || bytes.len() != 1 + bytes[0] as usize {
+			return Err(error("the record's length doesn't match its byte count"));
+		}
+		if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0xFF {
+			return Err(error("the checksum's wrong"));
+		}
+		if bytes.len() < 2 + addr_len {
+			return Err(error("the record's too short for its address"));
+		}
+		
+		let addr = big_endian(&bytes[1..1 + addr_len]);
+		match record.as_bytes()[0] {
+			b'1'..=b'3' => image.push(addr, &bytes[1 + addr_len..bytes.len() - 1]),
+			b'7'..=b'9' => image.entry = Some(addr),
+			// the header, and how many records there were
+			_ => (),
+		}
+	}
+	Ok(image)
+}
+
+/// How much goes in each record. Everyone seems to use 16.
+const RECORD_BYTES: usize = 16;
+
+/// Writes a record as hex, with a checksum worked out by `check` from the
+/// sum of its bytes.
+fn record(out: &mut String, start: &str, bytes: &[u8], check: impl Fn(u8) -> u8) {
+	let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+	out.push_str(start);
+	for b in bytes.iter().chain([&check(sum)]) {
+		out.push_str(&format!("{b:02X}"));
+	}
+	out.push('\n');
+}
+
+fn write_intel_hex(image: &Image) -> String {
+	let mut out = String::new();
+	let mut upper = 0;
+	let mut data = |addr: word, bytes: &[u8], out: &mut String| {
+		if addr >> 16 != upper {
+			upper = addr >> 16;
+			record(out, ":", &[2, 0, 0, 4, (upper >> 8) as u8, upper as u8], u8::wrapping_neg);
+		}
+		let mut r = vec![bytes.len() as u8, (addr >> 8) as u8, addr as u8, 0];
+		r.extend_from_slice(bytes);
+		record(out, ":", &r, u8::wrapping_neg);
+	};
+	
+	for chunk in &image.chunks {
+		let mut addr = chunk.addr;
+		let mut rest = &chunk.bytes[..];
+		while !rest.is_empty() {
+			// records can't cross into the next 64 KiB
+			let room = 0x1_0000 - (addr & 0xFFFF) as usize;
+			let (now, later) = rest.split_at(rest.len().min(RECORD_BYTES).min(room));
+			data(addr, now, &mut out);
+			addr = addr.wrapping_add(now.len() as word);
+			rest = later;
+		}
+	}
+	if let Some(entry) = image.entry {
+		let mut r = vec![4, 0, 0, 5];
+		r.extend(entry.to_be_bytes());
+		record(&mut out, ":", &r, u8::wrapping_neg);
+	}
+	out.push_str(":00000001FF\n");
+	out
+}
+
+fn write_srecord(image: &Image) -> String {
+	let mut out = String::new();
+	// an empty header
+	record(&mut out, "S0", &[3, 0, 0], |sum| !sum);
+	
+	let mut count = 0;
+	for chunk in &image.chunks {
+		for (i, bytes) in chunk.bytes.chunks(RECORD_BYTES).enumerate() {
+			let addr = chunk.addr.wrapping_add((i * RECORD_BYTES) as word);
+			let mut r = vec![bytes.len() as u8 + 5];
+			r.extend(addr.to_be_bytes());
+			r.extend_from_slice(bytes);
+			record(&mut out, "S3", &r, |sum| !sum);
+			count += 1;
+		}
+	}
+	if count <= 0xFFFF {
+		record(&mut out, "S5", &[3, (count >> 8) as u8, count as u8], |sum| !sum);
+	}
+	let mut r = vec![5];
+	r.extend(image.entry.unwrap_or(0).to_be_bytes());
+	record(&mut out, "S7", &r, |sum| !sum);
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn image(chunks: &[(word, &[u8])], entry: Option<word>) -> Image {
+		let chunks = chunks.iter().map(|&(addr, bytes)| Chunk { addr, bytes: bytes.to_vec() }).collect();
+		Image { chunks, entry }
+	}
+	
+	#[test]
+	fn mars_dumps() {
+		// addiu $t0, $zero, 5 / syscall
+		let words = image(&[(0x0040_0000, &[0x05, 0x00, 0x08, 0x24, 0x0C, 0x00, 0x00, 0x00])], None);
+		let hex = "24080005\n0000000c\n";
+		let binary = "00100100000010000000000000000101\n00000000000000000000000000001100\n";
+		
+		assert_eq!(Format::detect(hex.as_bytes()), Format::HexText);
+		assert_eq!(Format::detect(binary.as_bytes()), Format::BinaryText);
+		assert_eq!(Format::detect(&[0x05, 0x00, 0x08, 0x24]), Format::Binary);
+		assert_eq!(Format::detect(b"deadbeef\ncafe\n"), Format::Binary, "not all words");
+		
+		assert_eq!(Image::read(hex.as_bytes(), 0x0040_0000), Ok(words.clone()));
+		assert_eq!(Image::read(binary.as_bytes(), 0x0040_0000), Ok(words.clone()));
+		assert_eq!(Format::HexText.write(&words).unwrap(), hex.as_bytes());
+		assert_eq!(Format::BinaryText.write(&words).unwrap(), binary.as_bytes());
+		
+		// gaps get filled in, and the end gets padded
+		let gappy = image(&[(0x10, &[1, 2]), (0x14, &[3])], None);
+		assert_eq!(Format::Binary.write(&gappy).unwrap(), [1, 2, 0, 0, 3]);
+		assert_eq!(Format::HexText.write(&gappy).unwrap(), b"00000201\n00000003\n");
+		
+		// ..unless they're too big, which only matters without addresses
+		let far = image(&[(0x0040_0000, &[1]), (0x7FFF_FFFC, &[2])], None);
+		let message = "the chunks are too far apart to write without addresses";
+		assert_eq!(Format::Binary.write(&far).unwrap_err().to_string(), message);
+		assert_eq!(Format::HexText.write(&far).unwrap_err().to_string(), message);
+		assert_eq!(Format::IntelHex.read(&Format::IntelHex.write(&far).unwrap(), 0), Ok(far));
+		
+		assert_eq!(Format::HexText.read(b"2408005\n", 0).unwrap_err().to_string(), "line 1: words should be 8 hex digits");
+		assert_eq!(Format::HexText.read(b"\n+2408005", 0).unwrap_err().to_string(), "line 2: words should be 8 hex digits");
+		assert_eq!(Format::BinaryText.read(b"0120", 0).unwrap_err().to_string(), "line 1: words should be 32 binary digits");
+	}
+	
+	#[test]
+	fn intel_hex() {
+		// from the spec's own example, more or less
+		let hex = ":020000040001F9\n:10010000214601360121470136007EFE09D2190140\n:0400000500010100F5\n:00000001FF\n";
+		let expected = image(&[(0x0001_0100, &[
+			0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01, 0x36, 0x00, 0x7E, 0xFE, 0x09, 0xD2, 0x19, 0x01,
+		])], Some(0x0001_0100));
+		
+		assert_eq!(Format::detect(hex.as_bytes()), Format::IntelHex);
+		assert_eq!(Image::read(hex.as_bytes(), 0), Ok(expected.clone()));
+		assert_eq!(Format::IntelHex.write(&expected).unwrap(), hex.as_bytes());
+		
+		// records get split at 16 bytes and 64 KiB, and merged back together
+		let big = image(&[(0x0040_FFF8, &[7; 40]), (0x1001_0000, &[1, 2, 3])], None);
+		let written = String::from_utf8(Format::IntelHex.write(&big).unwrap()).unwrap();
+		assert_eq!(written.lines().count(), 8);
+		assert_eq!(Image::read(written.as_bytes(), 0), Ok(big));
+		
+		let error = |s: &str| Format::IntelHex.read(s.as_bytes(), 0).unwrap_err().to_string();
+		assert_eq!(error("0100000001\n"), "line 1: records should start with `:`");
+		assert_eq!(error(":0100000001\n"), "line 1: the record's length doesn't match its byte count");
+		assert_eq!(error("\n:0100000001FF\n"), "line 2: the checksum's wrong");
+		assert_eq!(error(":00000007F9\n"), "line 1: unknown record type");
+		assert_eq!(error(":0300000400010AEE\n"), "line 1: the record's the wrong size for its type");
+		assert_eq!(error(":0100000001FE\n"), "there's no end-of-file record");
+	}
+	
+	#[test]
+	fn srecord() {
+		// wikipedia's example
+		let srec = "S00F000068656C6C6F202020202000003C\nS11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026\nS5030001FB\nS9030000FC\n";
+		let image_ = Image::read(srec.as_bytes(), 0x1234).unwrap();
+		assert_eq!(Format::detect(srec.as_bytes()), Format::SRecord);
+		assert_eq!(image_.entry, Some(0));
+		assert_eq!(image_.chunks.len(), 1);
+		assert_eq!(image_.chunks[0].addr, 0);
+		assert_eq!(image_.chunks[0].bytes[..4], [0x7C, 0x08, 0x02, 0xA6]);
+		
+		let mine = image(&[(0x0040_0000, &[0xAB; 20]), (0x1001_0000, &[1])], Some(0x0040_0000));
+		let written = String::from_utf8(Format::SRecord.write(&mine).unwrap()).unwrap();
+		assert_eq!(written.lines().collect::<Vec<_>>(), [
+			"S0030000FC",
+			"S31500400000ABABABABABABABABABABABABABABABABFA",
+			"S30900400010ABABABABFA",
+			"S3061001000001E7",
+			"S5030003F9",
+			"S70500400000BA",
+		]);
+		assert_eq!(Image::read(written.as_bytes(), 0), Ok(mine));
+		
+		let error = |s: &str| Format::SRecord.read(s.as_bytes(), 0).unwrap_err().to_string();
+		assert_eq!(error("S4030000FC\n"), "line 1: unknown record type");
+		assert_eq!(error("S1030000FD\n"), "line 1: the checksum's wrong");
+		assert_eq!(error("S1020000FD\n"), "line 1: the record's length doesn't match its byte count");
+		assert_eq!(error("S304000000FB\n"), "line 1: the record's too short for its address");
+	}
+	
+	#[test]
+	fn loading() {
+		let mut mem = Memory::default();
+		image(&[(0x10, &[1, 2, 3, 4]), (0x2000, &[5])], None).load(&mut mem).unwrap();
+		assert_eq!(mem.get_word(0x10), Some(0x0403_0201));
+		assert_eq!(mem.get_byte(0x2000), 5);
+	}
+}
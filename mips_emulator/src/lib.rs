@@ -18,4 +18,8 @@ const fn smear_bit(w: word, bit: usize) -> word {
 }
 
 pub mod mem;
+pub mod map;
+pub mod mmio;
 pub mod chip;
+pub mod elf;
+pub mod image;
@@ -0,0 +1,315 @@
+use std::ops::RangeInclusive;
+
+use super::word;
+
+// Names for the segments everyone expects to find. Nothing stops a map from
+// having other segments, these are just the ones the loaders look for.
+pub const TEXT: &str = ".text";
+pub const DATA: &str = ".data";
+pub const HEAP: &str = "heap";
+pub const KTEXT: &str = ".ktext";
+pub const KDATA: &str = ".kdata";
+pub const MMIO: &str = "MMIO";
+
+/// What the CPU is trying to do to an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+	/// Loading data with `lw`, `lbu`, etc.
+	Load,
+	
+	/// Storing data with `sw`, `sb`, etc.
+	Store,
+	
+	/// Fetching the next instruction to run.
+	Fetch,
+}
+
+/// What the CPU is allowed to do inside a [`Segment`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+	pub read: bool,
+	pub write: bool,
+	pub execute: bool,
+}
+impl Permissions {
+	pub const NONE: Self = Permissions { read: false, write: false, execute: false };
+	pub const R: Self = Permissions { read: true, ..Self::NONE };
+	pub const RW: Self = Permissions { write: true, ..Self::R };
+	pub const RX: Self = Permissions { execute: true, ..Self::R };
+	pub const RWX: Self = Permissions { execute: true, ..Self::RW };
+	
+	pub const fn allows(self, access: Access) -> bool {
+		match access {
+			Access::Load => self.read,
+			Access::Store => self.write,
+			Access::Fetch => self.execute,
+		}
+	}
+}
+impl std::fmt::Display for Permissions {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let flag = |b, c| if b { c } else { '-' };
+		write!(f, "{}{}{}",
+			flag(self.read, 'r'), flag(self.write, 'w'), flag(self.execute, 'x'))
+	}
+}
+
+/// A named stretch of memory.
+/// 
+/// The range is inclusive so a segment can reach all the way up to
+/// `0xFFFF_FFFF`, which is where MMIO tends to live.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+	pub name: &'static str,
+	pub range: RangeInclusive<word>,
+	pub perms: Permissions,
+}
+impl Segment {
+	pub const fn new(name: &'static str, range: RangeInclusive<word>, perms: Permissions) -> Self {
+		Segment { name, range, perms }
+	}
+	
+	pub fn base(&self) -> word { *self.range.start() }
+	
+	pub fn contains(&self, addr: word) -> bool { self.range.contains(&addr) }
+}
+
+/// Describes where everything lives in memory.
+/// 
+/// The CPU checks every load, store and instruction fetch against this, and
+/// raises an address error if the segment doesn't allow it (or if there's no
+/// segment there at all). Segments shouldn't overlap; if they do, the first
+/// one wins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryMap {
+	pub segments: Vec<Segment>,
+	
+	/// Where the PC starts.
+	pub entry: word,
+	
+	/// Where `$gp` and `$sp` start.
+	pub gp: word,
+	pub sp: word,
+	
+	/// Where the CPU jumps to when there's an exception.
+	pub exception_handler: word,
+	
+	/// How many of the segments at the front came from loading a program,
+	/// which get swapped out when the next one's loaded.
+	pub loaded: usize,
+}
+impl Default for MemoryMap {
+	/// One big segment that allows anything, anywhere.
+	fn default() -> Self {
+		MemoryMap::new(vec![
+			Segment::new("memory", 0..=word::MAX, Permissions::RWX),
+		])
+	}
+}
+impl MemoryMap {
+	/// Makes a map with these segments, where the CPU starts at 0 with every
+	/// register zeroed.
+	pub fn new(segments: Vec<Segment>) -> Self {
+		MemoryMap { segments, entry: 0, gp: 0, sp: 0, exception_handler: 0, loaded: 0 }
+	}
+	
+	/// Finds a segment by its name.
+	pub fn segment(&self, name: &str) -> Option<&Segment> {
+		self.segments.iter().find(|s| s.name == name)
+	}
+	
+	/// Where the segment called `name` starts, if there is one.
+	pub fn base(&self, name: &str) -> Option<word> {
+		self.segment(name).map(Segment::base)
+	}
+	
+	/// Finds the segment that `addr` is in.
+	pub fn find(&self, addr: word) -> Option<&Segment> {
+		self.segments.iter().find(|s| s.contains(addr))
+	}
+	
+	/// Returns true if the CPU can do `access` at `addr`. Addresses outside
+	/// every segment don't allow anything.
+	/// 
+	/// Only `addr` itself is checked, so aligned accesses are fine as long as
+	/// segments start and end on word boundaries.
+	pub fn permits(&self, addr: word, access: Access) -> bool {
+		self.find(addr).is_some_and(|s| s.perms.allows(access))
+	}
+}
+
+/// The memory configurations from MARS' "Settings → Memory Configuration".
+/// 
+/// These use the same addresses MARS does, so anything assembled by MARS with
+/// the matching `mc` option (or just the default one) can be loaded as is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Preset {
+	/// The one MARS uses unless told otherwise. Text at 0x0040_0000, data at
+	/// 0x1001_0000, stack just under 0x8000_0000, MMIO at 0xFFFF_0000.
+	#[default]
+	Default,
+	
+	/// Everything in 32 KiB, with `.data` at address 0.
+	CompactDataAtZero,
+	
+	/// Everything in 32 KiB, with `.text` at address 0.
+	CompactTextAtZero,
+}
+impl Preset {
+	pub const ALL: [Preset; 3] = [
+		Preset::Default,
+		Preset::CompactDataAtZero,
+		Preset::CompactTextAtZero,
+	];
+	
+	/// What MARS calls it in its settings dialog.
+	pub const fn friendly_name(self) -> &'static str {
+		match self {
+			Preset::Default => "Default",
+			Preset::CompactDataAtZero => "Compact, Data at Address 0",
+			Preset::CompactTextAtZero => "Compact, Text at Address 0",
+		}
+	}
+	
+	/// What you'd pass to MARS' `mc` command line option.
+	pub const fn mars_name(self) -> &'static str {
+		match self {
+			Preset::Default => "Default",
+			Preset::CompactDataAtZero => "CompactDataAtZero",
+			Preset::CompactTextAtZero => "CompactTextAtZero",
+		}
+	}
+	
+	pub fn map(self) -> MemoryMap {
+		use Permissions as P;
+		
+		// the kernel half is the same for both compact layouts
+		let compact_kernel = [
+			Segment::new(KTEXT, 0x4000..=0x4FFF, P::RX),
+			Segment::new(KDATA, 0x5000..=0x7EFF, P::RW),
+			Segment::new(MMIO,  0x7F00..=0x7FFF, P::RW),
+		];
+		
+		match self {
+			Preset::Default => MemoryMap {
+				segments: vec![
+					Segment::new(TEXT,      0x0040_0000..=0x0FFF_FFFF, P::RX),
+					Segment::new(".extern", 0x1000_0000..=0x1000_FFFF, P::RW),
+					Segment::new(DATA,      0x1001_0000..=0x1003_FFFF, P::RW),
+					Segment::new(HEAP,      0x1004_0000..=0x7FFF_FFFF, P::RW),
+					Segment::new(KTEXT,     0x8000_0000..=0x8FFF_FFFF, P::RX),
+					Segment::new(KDATA,     0x9000_0000..=0xFFFE_FFFF, P::RW),
+					Segment::new(MMIO,      0xFFFF_0000..=0xFFFF_FFFF, P::RW),
+				],
+				entry: 0x0040_0000,
+				gp: 0x1000_8000,
+				sp: 0x7FFF_EFFC,
+				exception_handler: 0x8000_0180,
+				loaded: 0,
+			},
+			Preset::CompactDataAtZero => MemoryMap {
+				segments: [
+					Segment::new(DATA,      0x0000..=0x0FFF, P::RW),
+					Segment::new(".extern", 0x1000..=0x1FFF, P::RW),
+					Segment::new(HEAP,      0x2000..=0x2FFF, P::RW),
+					Segment::new(TEXT,      0x3000..=0x3FFF, P::RX),
+				].into_iter().chain(compact_kernel).collect(),
+				entry: 0x3000,
+				gp: 0x1800,
+				sp: 0x2FFC,
+				exception_handler: 0x4180,
+				loaded: 0,
+			},
+			Preset::CompactTextAtZero => MemoryMap {
+				segments: [
+					Segment::new(TEXT,      0x0000..=0x0FFF, P::RX),
+					Segment::new(".extern", 0x1000..=0x1FFF, P::RW),
+					Segment::new(DATA,      0x2000..=0x2FFF, P::RW),
+					Segment::new(HEAP,      0x3000..=0x3FFF, P::RW),
+				].into_iter().chain(compact_kernel).collect(),
+				entry: 0x0000,
+				gp: 0x1800,
+				sp: 0x3FFC,
+				exception_handler: 0x4180,
+				loaded: 0,
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn flat_map_allows_everything() {
+		let m = MemoryMap::default();
+		for access in [Access::Load, Access::Store, Access::Fetch] {
+			assert!(m.permits(0, access));
+			assert!(m.permits(word::MAX, access));
+		}
+	}
+	
+	#[test]
+	fn segment_permissions() {
+		let m = MemoryMap::new(vec![
+			Segment::new(TEXT, 0x0000..=0x0FFF, Permissions::RX),
+			Segment::new(DATA, 0x1000..=0x1FFF, Permissions::RW),
+			Segment::new(MMIO, 0xFFFF_0000..=0xFFFF_FFFF, Permissions::RW),
+		]);
+		
+		assert_eq!(m.base(DATA), Some(0x1000));
+		assert_eq!(m.find(0x1234).map(|s| s.name), Some(DATA));
+		assert!(m.find(0x2000).is_none());
+		
+		assert!(m.permits(0x0FFC, Access::Fetch));
+		assert!(m.permits(0x0FFC, Access::Load));
+		assert!(!m.permits(0x0FFC, Access::Store));
+		
+		assert!(!m.permits(0x1000, Access::Fetch));
+		assert!(m.permits(0x1000, Access::Store));
+		
+		// nothing's allowed in unmapped places
+		assert!(!m.permits(0x2000, Access::Load));
+		
+		assert!(m.permits(0xFFFF_FFFC, Access::Store));
+		
+		assert_eq!(Permissions::RX.to_string(), "r-x");
+	}
+	
+	#[test]
+	fn presets_match_mars() {
+		let m = Preset::Default.map();
+		assert_eq!(m.base(TEXT), Some(0x0040_0000));
+		assert_eq!(m.base(DATA), Some(0x1001_0000));
+		assert_eq!(m.base(MMIO), Some(0xFFFF_0000));
+		assert!(m.permits(m.sp, Access::Store));
+		assert!(m.permits(m.gp, Access::Load));
+		assert!(m.permits(m.exception_handler, Access::Fetch));
+		
+		let m = Preset::CompactDataAtZero.map();
+		assert_eq!(m.base(DATA), Some(0x0000));
+		assert_eq!(m.base(TEXT), Some(0x3000));
+		assert_eq!(m.entry, 0x3000);
+		assert_eq!(m.base(MMIO), Some(0x7F00));
+		
+		let m = Preset::CompactTextAtZero.map();
+		assert_eq!(m.base(TEXT), Some(0x0000));
+		assert_eq!(m.base(DATA), Some(0x2000));
+		assert_eq!((m.gp, m.sp), (0x1800, 0x3FFC));
+		assert!(!m.permits(0x8000, Access::Load), "compact layouts end at 0x7FFF");
+		
+		// none of the segments should overlap
+		for preset in Preset::ALL {
+			let m = preset.map();
+			for (i, a) in m.segments.iter().enumerate() {
+				for b in &m.segments[i + 1..] {
+					assert!(
+						a.range.end() < b.range.start() || b.range.end() < a.range.start(),
+						"{} overlaps {} in {preset:?}", a.name, b.name
+					);
+				}
+			}
+		}
+	}
+}
@@ -1,38 +1,228 @@
-use super::{word, WORD_BYTES};
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::sync::Arc;
 
-pub const MEMORY_SIZE: usize = 0x01_2000; // 128 KiB
+use super::{word, WORD_BYTES, map::MemoryMap, mmio::Device, elf::Symbols};
+
+/// Size of the entire 32-bit address space. Every address in here can be
+/// backed by a page, but pages are only allocated once something's written.
+pub const MEMORY_SIZE: u64 = 1 << word::BITS; // 4 GiB
 pub const PAGE_SIZE: usize = 0x0_1000; // 4 KiB
-pub const PAGE_NUM: usize = MEMORY_SIZE / PAGE_SIZE;
+pub const PAGE_NUM: usize = (MEMORY_SIZE / PAGE_SIZE as u64) as usize;
 
 const OFFSET_MASK: usize = PAGE_SIZE - 1;
 
 const PAGE_SHIFT: usize = PAGE_SIZE.trailing_zeros() as usize;
 
-#[derive(Default)]
-pub struct Memory(pub [Option<Box<[u8; PAGE_SIZE]>>; PAGE_NUM]);
+/// Pages are found through a two-level table, kinda like a real MMU. A flat
+/// table of every page would be 8 MiB of `None`s before anything's loaded.
+const TABLE_SIZE: usize = 0x400; // 1024 pages (4 MiB) per table
+const TABLE_NUM: usize = PAGE_NUM / TABLE_SIZE;
+
+const TABLE_SHIFT: usize = TABLE_SIZE.trailing_zeros() as usize;
+
+pub type Page = [u8; PAGE_SIZE];
+type PageTable = [Option<Arc<Page>>; TABLE_SIZE];
+
+/// Sparse memory covering the whole 32-bit address space.
+/// 
+/// Reading from a page that was never written to gives back zeroes, but you
+/// can still tell it apart from a zero-filled page with [`Memory::is_allocated`].
+/// 
+/// Pages are copy-on-write, so cloning a `Memory` is cheap: both copies share
+/// every page until one of them writes to it. See [`Memory::fork`].
+#[derive(Clone)]
+pub struct Memory {
+	tables: Vec<Option<Arc<PageTable>>>,
+	
+	/// How many pages are currently allocated.
+	allocated: usize,
+	
+	/// Maximum number of pages that can be allocated, if any. Writes that'd
+	/// need a new page past this limit fail instead.
+	page_limit: Option<usize>,
+	
+	/// Pages that might've been written to since the last `mark_clean`.
+	dirty: BTreeSet<usize>,
+	
+	/// Where everything lives, and what the CPU's allowed to do with it.
+	/// Only the CPU cares about this; every other method here ignores it.
+	pub map: MemoryMap,
+	
+	/// Names for addresses, for showing where things are while debugging.
+	/// Shared between clones, like pages are.
+	pub symbols: Arc<Symbols>,
+	
+	/// Memory-mapped devices. See [`Memory::attach`].
+	pub(crate) devices: Vec<Box<dyn Device>>,
+}
+impl Default for Memory {
+	fn default() -> Self {
+		Memory {
+			tables: (0..TABLE_NUM).map(|_| None).collect(),
+			allocated: 0,
+			page_limit: None,
+			dirty: BTreeSet::new(),
+			map: MemoryMap::default(),
+			symbols: Arc::default(),
+			devices: Vec::new(),
+		}
+	}
+}
 impl Memory {
-	pub const fn is_aligned(addr: word) -> bool { (addr % 4) == 0 }
+	/// Makes an empty memory that won't allocate more than `limit` pages.
+	pub fn with_page_limit(limit: usize) -> Self {
+		Memory { page_limit: Some(limit), ..Default::default() }
+	}
+	
+	pub const fn is_aligned(addr: word) -> bool { addr.is_multiple_of(WORD_BYTES) }
 	
 	pub const fn addr_to_indices(addr: word) -> (usize, usize) {
 		(addr as usize >> PAGE_SHIFT, addr as usize & OFFSET_MASK)
 	}
 	
-	pub fn make_page() -> Box<[u8; PAGE_SIZE]> {
+	const fn page_to_indices(page: usize) -> (usize, usize) {
+		(page >> TABLE_SHIFT, page & (TABLE_SIZE - 1))
+	}
+	
+	pub fn make_page() -> Box<Page> {
 		vec![0u8; PAGE_SIZE].into_boxed_slice().try_into().unwrap()
 	}
 	
+	fn make_table() -> Arc<PageTable> {
+		let table: Box<PageTable> = (0..TABLE_SIZE).map(|_| None).collect::<Vec<_>>()
+			.into_boxed_slice().try_into().ok().unwrap();
+		Arc::from(table)
+	}
+	
+	/// Makes a copy of this memory that shares all of its pages. Whichever side
+	/// writes to a shared page first gets its own copy of just that page, so
+	/// forking is cheap no matter how much is allocated.
+	/// 
+	/// The fork starts out clean, so its [`Memory::dirty_pages`] are exactly
+	/// the pages it changed since forking.
+	pub fn fork(&self) -> Memory {
+		Memory { dirty: BTreeSet::new(), ..self.clone() }
+	}
+	
+	/// Returns true if page number `page` is the very same page in both
+	/// memories, meaning neither side has written to it since they were forked.
+	/// Two unallocated pages count as shared too.
+	pub fn shares_page(&self, other: &Memory, page: usize) -> bool {
+		fn get(m: &Memory, page: usize) -> Option<&Arc<Page>> {
+			let (table, entry) = Memory::page_to_indices(page);
+			m.tables.get(table)?.as_ref()?[entry].as_ref()
+		}
+		
+		match (get(self, page), get(other, page)) {
+			(Some(a), Some(b)) => Arc::ptr_eq(a, b),
+			(None, None) => true,
+			_ => false,
+		}
+	}
+	
+	/// Frees every page. The page limit and memory map stay as they were.
+	/// Devices stay attached, but get reset.
 	pub fn clear(&mut self) {
-		for p in self.0.iter_mut() { p.take(); }
+		// freed pages all just turned into zeroes
+		let freed: Vec<_> = self.pages().map(|(addr, _)| Memory::addr_to_indices(addr).0).collect();
+		self.dirty.extend(freed);
+		
+		for t in self.tables.iter_mut() { t.take(); }
+		self.allocated = 0;
+		
+		self.reset_devices();
+	}
+	
+	/// Forgets which pages have been written to, so [`Memory::dirty_pages`]
+	/// starts over from here.
+	pub fn mark_clean(&mut self) {
+		self.dirty.clear();
+	}
+	
+	/// Page numbers of every page that's been written to (or freed) since the
+	/// last [`Memory::mark_clean`], in order.
+	/// 
+	/// This is a little pessimistic: anything that *could* have written to a
+	/// page counts, even if it wrote back the same bytes.
+	pub fn dirty_pages(&self) -> impl Iterator<Item = usize> + '_ {
+		self.dirty.iter().copied()
+	}
+	
+	pub fn is_dirty(&self, addr: word) -> bool {
+		self.dirty.contains(&Memory::addr_to_indices(addr).0)
+	}
+	
+	pub fn page_limit(&self) -> Option<usize> { self.page_limit }
+	
+	/// Sets the maximum number of pages. Pages that are already allocated past
+	/// the new limit are kept, but no more can be allocated until some are
+	/// freed by [`Memory::clear`].
+	pub fn set_page_limit(&mut self, limit: Option<usize>) {
+		self.page_limit = limit;
+	}
+	
+	/// Number of pages that are currently allocated.
+	pub fn allocated_pages(&self) -> usize { self.allocated }
+	
+	/// Returns true if the page containing `addr` has been allocated, which
+	/// tells apart "zero because nothing's there" from "zero because someone
+	/// wrote zeroes".
+	pub fn is_allocated(&self, addr: word) -> bool {
+		self.page(Memory::addr_to_indices(addr).0).is_some()
+	}
+	
+	/// Gets page number `page`, if it's allocated.
+	pub fn page(&self, page: usize) -> Option<&Page> {
+		let (table, entry) = Memory::page_to_indices(page);
+		self.tables.get(table)?.as_ref()?[entry].as_deref()
 	}
 	
-	pub fn get_byte(&self, addr: word) -> Option<u8> {
+	/// Gets page number `page`, allocating it if needed. Returns `None` if the
+	/// page would go over the page limit.
+	/// 
+	/// The page counts as dirty from here on, whether or not it's written to.
+	pub fn page_mut(&mut self, page: usize) -> Option<&mut Page> {
+		let index = page;
+		let (table, entry) = Memory::page_to_indices(page);
+		let table = self.tables.get_mut(table)?.get_or_insert_with(Self::make_table);
+		
+		// if this table is shared with a fork, checking whether the page is there
+		// is fine, but anything else needs our own copy of it
+		if table[entry].is_none() && self.page_limit.is_some_and(|l| self.allocated >= l) {
+			return None;
+		}
+		let page = &mut Arc::make_mut(table)[entry];
+		
+		if page.is_none() { self.allocated += 1; }
+		
+		self.dirty.insert(index);
+		let page = page.get_or_insert_with(|| Arc::from(Self::make_page()));
+		Some(Arc::make_mut(page))
+	}
+	
+	/// Iterates over every allocated page, in order of address. Each page comes
+	/// with the address it starts at.
+	pub fn pages(&self) -> impl Iterator<Item = (word, &Page)> {
+		self.tables.iter().enumerate()
+			.filter_map(|(t, table)| Some((t, table.as_ref()?)))
+			.flat_map(|(t, table)| {
+				table.iter().enumerate()
+					.filter_map(move |(e, page)| {
+						let addr = (((t << TABLE_SHIFT) | e) << PAGE_SHIFT) as word;
+						Some((addr, page.as_deref()?))
+					})
+			})
+	}
+	
+	pub fn get_byte(&self, addr: word) -> u8 {
 		let (page, offset) = Memory::addr_to_indices(addr);
-		self.0.get(page)?.as_ref().map(|b| *b.get(offset).unwrap()).or(Some(0))
+		self.page(page).map(|p| p[offset]).unwrap_or(0)
 	}
 	
 	pub fn get_byte_mut(&mut self, addr: word) -> Option<&mut u8> {
 		let (page, offset) = Memory::addr_to_indices(addr);
-		self.0.get_mut(page)?.get_or_insert_with(Self::make_page).get_mut(offset)
+		self.page_mut(page)?.get_mut(offset)
 	}
 	
 	pub fn set_byte(&mut self, addr: word, val: u8) -> Option<()> {
@@ -40,17 +230,15 @@ impl Memory {
 	}
 	
 	pub fn get_word(&self, addr: word) -> Option<word> {
-		// If not word-aligned, we could possibly have a word across pages
-		// That'd require more logic, so it's gonna be `fn get_word_misalign`??
+		// Aligned words never cross pages. For the ones that might, there's
+		// `get_word_misaligned`.
 		if !Memory::is_aligned(addr) { return None; }
 		
 		let (page, offset) = Memory::addr_to_indices(addr);
 		
-		let page = self.0.get(page)?.as_ref();
-		
-		if let Some(page) = page {
+		if let Some(page) = self.page(page) {
 			let w = offset..(offset + WORD_BYTES as usize);
-			Some(word::from_le_bytes(page.get(w)?.try_into().unwrap()))
+			Some(word::from_le_bytes(page[w].try_into().unwrap()))
 		} else {
 			Some(0)
 		}
@@ -59,43 +247,277 @@ impl Memory {
 	// no get_word_mut because i'm scared of endianness
 	
 	pub fn set_word(&mut self, addr: word, val: word) -> Option<()> {
+		if !Memory::is_aligned(addr) { return None; }
+		self.set_slice(addr, &val.to_le_bytes())
+	}
+	
+	pub fn get_half(&self, addr: word) -> Option<u16> {
+		if !addr.is_multiple_of(2) { return None; }
+		Some(self.get_half_misaligned(addr))
+	}
+	
+	pub fn set_half(&mut self, addr: word, val: u16) -> Option<()> {
+		if !addr.is_multiple_of(2) { return None; }
+		self.set_slice(addr, &val.to_le_bytes())
+	}
+	
+	/// Reads a word from any address, even if it's split across two pages.
+	/// The CPU can't do this, but it's handy for tools.
+	pub fn get_word_misaligned(&self, addr: word) -> word {
+		let mut w = [0u8; WORD_BYTES as usize];
+		self.copy_to(addr, &mut w);
+		word::from_le_bytes(w)
+	}
+	
+	pub fn set_word_misaligned(&mut self, addr: word, val: word) -> Option<()> {
+		self.set_slice(addr, &val.to_le_bytes())
+	}
+	
+	pub fn get_half_misaligned(&self, addr: word) -> u16 {
+		let mut h = [0u8; 2];
+		self.copy_to(addr, &mut h);
+		u16::from_le_bytes(h)
+	}
+	
+	pub fn set_half_misaligned(&mut self, addr: word, val: u16) -> Option<()> {
 		self.set_slice(addr, &val.to_le_bytes())
 	}
 	
-	// TODO: what if this was an iterator instead?
-	// (supports moving across pages, zero pages)
+	/// Splits `len` bytes starting at `addr` into runs that each stay inside
+	/// a single page. Gives back the page number and the offsets in that page.
+	/// Addresses wrap around past the end of memory, just like the CPU's.
+	fn spans(addr: word, len: usize) -> impl Iterator<Item = (usize, Range<usize>)> {
+		let mut addr = addr;
+		let mut left = len;
+		std::iter::from_fn(move || {
+			if left == 0 { return None; }
+			
+			let (page, offset) = Memory::addr_to_indices(addr);
+			let take = left.min(PAGE_SIZE - offset);
+			
+			addr = addr.wrapping_add(take as word);
+			left -= take;
+			
+			Some((page, offset..offset + take))
+		})
+	}
+	
+	/// Gets a slice of memory, but only if it's all inside one allocated page.
+	/// If you don't want to care about pages, use [`Memory::copy_to`] or
+	/// [`Memory::bytes`] instead.
 	pub fn get_slice(&self, addr: word, len: usize) -> Option<&[u8]> {
-		let end_addr = addr + (len - 1) as word;
+		let end_addr = addr.checked_add(len.checked_sub(1)? as word)?;
 		
 		let (s_page, s_offset) = Memory::addr_to_indices(addr);
 		let (e_page, e_offset) = Memory::addr_to_indices(end_addr);
 		
 		if s_page != e_page { return None; }
 		
-		let page = self.0.get(s_page)?.as_ref();
+		self.page(s_page)?.get(s_offset..=e_offset)
+	}
+	
+	/// Writes `data` starting at `addr`, across as many pages as it needs.
+	/// 
+	/// Returns `None` without writing anything if the new pages would go over
+	/// the page limit.
+	pub fn set_slice(&mut self, addr: word, data: &[u8]) -> Option<()> {
+		self.reserve(addr, data.len())?;
 		
-		if let Some(page) = page {
-			page.get(s_offset..=e_offset)
-		} else {
-			None
+		let mut data = data;
+		for (page, range) in Memory::spans(addr, data.len()) {
+			let (now, later) = data.split_at(range.len());
+			self.page_mut(page)?[range].copy_from_slice(now);
+			data = later;
 		}
+		Some(())
 	}
 	
-	// TODO: allow writes across pages..
-	// TODO: less sloppy
-	pub fn set_slice(&mut self, addr: word, data: &[u8]) -> Option<()> {
-		let (page, offset) = Memory::addr_to_indices(addr);
+	/// Makes sure every page between `addr` and `addr + len` can be allocated
+	/// without going over the limit, so writes don't stop halfway through.
+	fn reserve(&self, addr: word, len: usize) -> Option<()> {
+		if let Some(limit) = self.page_limit {
+			let mut needed = 0;
+			let mut last = None;
+			for (page, _) in Memory::spans(addr, len) {
+				if last != Some(page) && self.page(page).is_none() { needed += 1; }
+				last = Some(page);
+			}
+			if self.allocated + needed > limit { return None; }
+		}
+		Some(())
+	}
+	
+	/// Copies memory starting at `addr` into `buf`. Unallocated pages are
+	/// copied as zeroes.
+	pub fn copy_to(&self, addr: word, buf: &mut [u8]) {
+		let mut buf = buf;
+		for (page, range) in Memory::spans(addr, buf.len()) {
+			let (now, later) = std::mem::take(&mut buf).split_at_mut(range.len());
+			match self.page(page) {
+				Some(p) => now.copy_from_slice(&p[range]),
+				None => now.fill(0),
+			}
+			buf = later;
+		}
+	}
+	
+	/// Like [`Memory::copy_to`], but makes the buffer for you.
+	pub fn copy_to_vec(&self, addr: word, len: usize) -> Vec<u8> {
+		let mut v = vec![0u8; len];
+		self.copy_to(addr, &mut v);
+		v
+	}
+	
+	/// Copies `len` bytes from `src` to `dst`. The two can overlap.
+	pub fn copy_within(&mut self, src: word, dst: word, len: usize) -> Option<()> {
+		let data = self.copy_to_vec(src, len);
+		self.set_slice(dst, &data)
+	}
+	
+	/// Sets `len` bytes starting at `addr` to `val`.
+	pub fn fill(&mut self, addr: word, len: usize, val: u8) -> Option<()> {
+		self.reserve(addr, len)?;
+		
+		for (page, range) in Memory::spans(addr, len) {
+			self.page_mut(page)?[range].fill(val);
+		}
+		Some(())
+	}
+	
+	/// Like `fill` with zeroes, but without allocating anything, since pages
+	/// that aren't there are zeroes already.
+	pub fn zero(&mut self, addr: word, len: usize) {
+		for (page, range) in Memory::spans(addr, len) {
+			if self.page(page).is_some() {
+				// there already, so this can't go over the limit
+				self.page_mut(page).unwrap()[range].fill(0);
+			}
+		}
+	}
+	
+	/// Iterates over `len` bytes of memory starting at `addr`, going across
+	/// pages as needed. Unallocated pages read as zeroes.
+	pub fn bytes(&self, addr: word, len: usize) -> Bytes<'_> {
+		Bytes { mem: self, addr, left: len, page: None }
+	}
+	
+	/// Finds every byte that's different between `self` and `new`, looking
+	/// through every page that either of them has allocated.
+	pub fn diff(&self, new: &Memory) -> Vec<Change> {
+		let pages = self.pages().chain(new.pages())
+			.map(|(addr, _)| Memory::addr_to_indices(addr).0);
+		self.diff_pages(new, pages)
+	}
+	
+	/// Like [`Memory::diff`], but only looks through these page numbers. Pair
+	/// this with [`Memory::dirty_pages`] to skip all the pages that couldn't
+	/// possibly have changed.
+	/// 
+	/// Runs of changed bytes next to each other are joined into one [`Change`],
+	/// even if they cross a page boundary.
+	pub fn diff_pages(&self, new: &Memory, pages: impl IntoIterator<Item = usize>) -> Vec<Change> {
+		let mut pages: Vec<usize> = pages.into_iter().collect();
+		pages.sort_unstable();
+		pages.dedup();
 		
-		let end_addr = addr + (data.len().saturating_sub(1)) as word;
-		let (end_page, end_offset) = Memory::addr_to_indices(end_addr);
+		let mut changes: Vec<Change> = Vec::new();
 		
-		if page != end_page { return None; }
-		if offset == end_offset { return None; }
+		for page in pages {
+			let (old_page, new_page) = (self.page(page), new.page(page));
+			match (old_page, new_page) {
+				(None, None) => continue,
+				_ if self.shares_page(new, page) => continue,
+				(Some(a), Some(b)) if a == b => continue,
+				_ => (),
+			}
+			
+			let base = (page << PAGE_SHIFT) as word;
+			for i in 0..PAGE_SIZE {
+				let old = old_page.map_or(0, |p| p[i]);
+				let new = new_page.map_or(0, |p| p[i]);
+				if old == new { continue; }
+				
+				let addr = base + i as word;
+				match changes.last_mut() {
+					Some(c) if c.end() == Some(addr) => {
+						c.old.push(old);
+						c.new.push(new);
+					},
+					_ => changes.push(Change { addr, old: vec![old], new: vec![new] }),
+				}
+			}
+		}
 		
-		self.0.get_mut(page)?.get_or_insert_with(Self::make_page).get_mut(offset as usize..=end_offset as usize)?.copy_from_slice(data);
-		Some(())
+		changes
+	}
+	
+	/// Writes bytes from an iterator starting at `addr`, one at a time.
+	/// 
+	/// Returns how many bytes were written, or `None` if it ran into the page
+	/// limit partway through. (Everything before that still got written.)
+	pub fn set_iter(&mut self, addr: word, data: impl IntoIterator<Item = u8>) -> Option<usize> {
+		let mut addr = addr;
+		let mut written = 0;
+		for b in data {
+			self.set_byte(addr, b)?;
+			addr = addr.wrapping_add(1);
+			written += 1;
+		}
+		Some(written)
+	}
+}
+
+/// A run of bytes that's different between two memories.
+/// See [`Memory::diff`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+	pub addr: word,
+	pub old: Vec<u8>,
+	pub new: Vec<u8>,
+}
+impl Change {
+	/// The address right after this change, if that's still in memory.
+	pub fn end(&self) -> Option<word> {
+		self.addr.checked_add(self.new.len() as word)
+	}
+}
+
+/// Iterator over a run of bytes in [`Memory`]. See [`Memory::bytes`].
+pub struct Bytes<'a> {
+	mem: &'a Memory,
+	addr: word,
+	left: usize,
+	
+	/// The page we're currently in, so we don't look it up for every byte.
+	page: Option<(usize, Option<&'a Page>)>,
+}
+impl Iterator for Bytes<'_> {
+	type Item = u8;
+	
+	fn next(&mut self) -> Option<u8> {
+		if self.left == 0 { return None; }
+		
+		let (page, offset) = Memory::addr_to_indices(self.addr);
+		let current = match self.page {
+			Some((p, current)) if p == page => current,
+			_ => {
+				let current = self.mem.page(page);
+				self.page = Some((page, current));
+				current
+			},
+		};
+		
+		self.addr = self.addr.wrapping_add(1);
+		self.left -= 1;
+		
+		Some(current.map(|p| p[offset]).unwrap_or(0))
+	}
+	
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		(self.left, Some(self.left))
 	}
 }
+impl ExactSizeIterator for Bytes<'_> {}
 
 #[cfg(test)]
 mod tests {
@@ -106,12 +528,16 @@ mod tests {
 		let m = Memory::default();
 		
 		// test if empty page returns 0s
-		assert_eq!(m.get_byte(0).expect("byte not some"), 0, "byte not 0");
+		assert_eq!(m.get_byte(0), 0, "byte not 0");
 		assert_eq!(m.get_word(0).expect("word not some"), 0, "word not 0");
 		
-		// test if beyond limit returns None
-		assert!(m.get_byte(MEMORY_SIZE as u32).is_none(), "byte not none");
-		assert!(m.get_word(MEMORY_SIZE as u32).is_none(), "word not none");
+		// test if the very end of memory still returns 0s
+		assert_eq!(m.get_byte(word::MAX), 0, "byte not 0");
+		assert_eq!(m.get_word(word::MAX - 3).expect("word not some"), 0, "word not 0");
+		
+		// ..without having allocated anything
+		assert!(!m.is_allocated(0));
+		assert_eq!(m.allocated_pages(), 0);
 	}
 	
 	#[test]
@@ -121,7 +547,242 @@ mod tests {
 		
 		m.set_slice(1, &[1, 2, 3, 4]).expect("page good");
 		assert_eq!(m.get_slice(1, 4).expect("slice have"), &[1, 2, 3, 4]);
-		assert_eq!(m.get_byte(4).unwrap(), 4);
+		assert_eq!(m.get_byte(4), 4);
+	}
+	
+	#[test]
+	fn pages_high_addresses() {
+		let mut m = Memory::default();
+		
+		// where MARS puts `.data` and the stack
+		m.set_word(0x1001_0000, 0xDEAD_BEEF).expect("data page");
+		m.set_word(0x7FFF_EFFC, 0x0BAD_F00D).expect("stack page");
+		
+		assert_eq!(m.get_word(0x1001_0000), Some(0xDEAD_BEEF));
+		assert_eq!(m.get_word(0x7FFF_EFFC), Some(0x0BAD_F00D));
+		
+		assert!(m.is_allocated(0x1001_0FFF));
+		assert!(!m.is_allocated(0x1001_1000));
+		
+		let bases: Vec<_> = m.pages().map(|(addr, _)| addr).collect();
+		assert_eq!(bases, [0x1001_0000, 0x7FFF_E000]);
+	}
+	
+	#[test]
+	fn pages_limit() {
+		let mut m = Memory::with_page_limit(2);
+		
+		m.set_byte(0x0000_0000, 1).expect("first page");
+		m.set_byte(0x8000_0000, 2).expect("second page");
+		assert!(m.set_byte(0xFFFF_F000, 3).is_none(), "page limit ignored");
+		
+		// already allocated pages are still writable
+		m.set_byte(0x0000_0001, 4).expect("first page again");
+		assert_eq!(m.allocated_pages(), 2);
+		
+		m.clear();
+		assert_eq!(m.allocated_pages(), 0);
+		m.set_byte(0xFFFF_F000, 3).expect("limit after clear");
+	}
+	
+	#[test]
+	fn slices_across_pages() {
+		let mut m = Memory::default();
+		
+		m.set_slice(0x0FFE, &[1, 2, 3, 4]).expect("write across pages");
+		assert!(m.is_allocated(0x0000) && m.is_allocated(0x1000));
+		
+		assert_eq!(m.copy_to_vec(0x0FFE, 4), [1, 2, 3, 4]);
+		assert!(m.bytes(0x0FFE, 4).eq([1, 2, 3, 4]));
+		
+		// borrowed slices still can't cross pages
+		assert!(m.get_slice(0x0FFE, 4).is_none());
+		assert_eq!(m.get_slice(0x1000, 2).expect("one page"), &[3, 4]);
+	}
+	
+	#[test]
+	fn slices_single_byte() {
+		let mut m = Memory::default();
+		m.set_slice(5, &[9]).expect("single byte write");
+		assert_eq!(m.get_byte(5), 9);
+		
+		// writing nothing doesn't allocate anything either
+		m.set_slice(0x5000, &[]).expect("empty write");
+		assert!(!m.is_allocated(0x5000));
+	}
+	
+	#[test]
+	fn slices_unallocated_zeroes() {
+		let mut m = Memory::default();
+		m.set_byte(0x1000, 7).expect("page");
+		
+		// first page is unallocated, second one isn't
+		assert_eq!(m.copy_to_vec(0x0FFE, 4), [0, 0, 7, 0]);
+		assert!(m.bytes(0x0FFE, 4).eq([0, 0, 7, 0]));
+		assert!(!m.is_allocated(0x0FFE), "reading allocated a page");
+		
+		let mut buf = [0xFF; 3];
+		m.copy_to(0x2000, &mut buf);
+		assert_eq!(buf, [0, 0, 0]);
+	}
+	
+	#[test]
+	fn slices_wrap_around() {
+		let mut m = Memory::default();
+		m.set_slice(0xFFFF_FFFE, &[1, 2, 3, 4]).expect("wrapping write");
+		
+		assert_eq!(m.get_byte(0xFFFF_FFFF), 2);
+		assert_eq!(m.get_byte(0x0000_0000), 3);
+		assert_eq!(m.copy_to_vec(0xFFFF_FFFE, 4), [1, 2, 3, 4]);
+		assert_eq!(m.get_word_misaligned(0xFFFF_FFFE), 0x0403_0201);
+	}
+	
+	#[test]
+	fn misaligned_words() {
+		let mut m = Memory::default();
+		
+		m.set_word_misaligned(0x0FFF, 0x1122_3344).expect("page");
+		assert_eq!(m.copy_to_vec(0x0FFF, 4), [0x44, 0x33, 0x22, 0x11]);
+		assert_eq!(m.get_word_misaligned(0x0FFF), 0x1122_3344);
+		
+		// the aligned versions refuse
+		assert!(m.get_word(0x0FFF).is_none());
+		assert!(m.set_word(0x0FFF, 0).is_none());
+		assert_eq!(m.get_word_misaligned(0x0FFF), 0x1122_3344);
+	}
+	
+	#[test]
+	fn halfwords() {
+		let mut m = Memory::default();
+		
+		m.set_half(2, 0xBEEF).expect("aligned half");
+		assert_eq!(m.get_half(2), Some(0xBEEF));
+		assert_eq!(m.get_word(0), Some(0xBEEF_0000));
+		
+		assert!(m.get_half(1).is_none());
+		assert!(m.set_half(1, 0).is_none());
+		
+		m.set_half_misaligned(0x0FFF, 0xCAFE).expect("page");
+		assert_eq!(m.get_half_misaligned(0x0FFF), 0xCAFE);
+		assert_eq!(m.get_byte(0x1000), 0xCA);
+	}
+	
+	#[test]
+	fn slices_respect_limit() {
+		let mut m = Memory::with_page_limit(1);
+		
+		// needs two pages, so nothing should get written
+		assert!(m.set_slice(0x0FFF, &[1, 2]).is_none());
+		assert!(m.fill(0x0FFF, 2, 1).is_none());
+		assert_eq!(m.allocated_pages(), 0);
+		
+		m.set_slice(0x0FFE, &[1, 2]).expect("fits in one page");
+		
+		// set_iter writes as far as it can
+		assert!(m.set_iter(0x0FFF, [3, 4]).is_none());
+		assert_eq!(m.get_byte(0x0FFF), 3);
+	}
+	
+	#[test]
+	fn copies_and_fills() {
+		let mut m = Memory::default();
+		
+		assert_eq!(m.set_iter(0x0FFC, 1..=8), Some(8));
+		assert_eq!(m.copy_to_vec(0x0FFC, 8), [1, 2, 3, 4, 5, 6, 7, 8]);
+		
+		// overlapping copy forward
+		m.copy_within(0x0FFC, 0x0FFE, 4).expect("page");
+		assert_eq!(m.copy_to_vec(0x0FFC, 8), [1, 2, 1, 2, 3, 4, 7, 8]);
+		
+		m.fill(0x0FFD, 6, 0xAA).expect("page");
+		assert_eq!(m.copy_to_vec(0x0FFC, 8), [1, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 8]);
+		
+		assert_eq!(m.bytes(0, 100).len(), 100);
+	}
+	
+	#[test]
+	fn dirty_pages() {
+		let mut m = Memory::default();
+		m.set_byte(0x0000, 1);
+		m.set_word(0x5000, 2);
+		
+		assert!(m.dirty_pages().eq([0x0, 0x5]));
+		
+		m.mark_clean();
+		assert_eq!(m.dirty_pages().count(), 0);
+		
+		// reading doesn't dirty anything, but writing zeroes does
+		let _ = m.get_word(0x5000);
+		m.set_slice(0x0FFF, &[0, 0]);
+		assert!(m.dirty_pages().eq([0x0, 0x1]));
+		assert!(m.is_dirty(0x1234) && !m.is_dirty(0x5000));
+		
+		// clearing dirties every page that was freed
+		m.mark_clean();
+		m.clear();
+		assert!(m.dirty_pages().eq([0x0, 0x1, 0x5]));
+	}
+	
+	#[test]
+	fn diffs() {
+		let mut old = Memory::default();
+		old.set_slice(0x0FFE, &[1, 2, 3, 4]);
+		old.set_word(0x8000, 0xFFFF_FFFF);
+		
+		let mut new = old.clone();
+		new.mark_clean();
+		new.set_slice(0x0FFF, &[9, 9]); // crosses into the next page
+		new.set_byte(0x0FFC, 7);
+		new.set_word(0x8000, 0); // same as not being there at all
+		new.set_byte(0x9000, 0); // still zero, so not a change
+		
+		let changes = vec![
+			Change { addr: 0x0FFC, old: vec![0], new: vec![7] },
+			Change { addr: 0x0FFF, old: vec![2, 3], new: vec![9, 9] },
+			Change { addr: 0x8000, old: vec![0xFF; 4], new: vec![0; 4] },
+		];
+		
+		assert_eq!(old.diff(&new), changes);
+		assert_eq!(old.diff_pages(&new, new.dirty_pages()), changes);
+		assert_eq!(new.diff(&new), []);
+		
+		// diffing the other way swaps old and new around
+		let back = new.diff(&old);
+		assert_eq!(back[1], Change { addr: 0x0FFF, old: vec![9, 9], new: vec![2, 3] });
+	}
+	
+	#[test]
+	fn forks_share_pages() {
+		let mut m = Memory::with_page_limit(3);
+		m.set_word(0x0000, 1);
+		m.set_word(0x1000, 2);
+		
+		let mut f = m.fork();
+		assert!(f.shares_page(&m, 0) && f.shares_page(&m, 1));
+		assert_eq!(f.dirty_pages().count(), 0, "forks start out clean");
+		assert_eq!(f.allocated_pages(), 2);
+		
+		// writing only un-shares the page that was written to
+		f.set_word(0x1000, 3);
+		assert!(f.shares_page(&m, 0));
+		assert!(!f.shares_page(&m, 1));
+		assert_eq!((m.get_word(0x1000), f.get_word(0x1000)), (Some(2), Some(3)));
+		
+		// and the same goes the other way
+		m.set_word(0x0000, 4);
+		assert_eq!((m.get_word(0x0000), f.get_word(0x0000)), (Some(4), Some(1)));
+		
+		// each side has its own page limit to run into
+		assert!(f.set_byte(0x2000, 5).is_some());
+		assert!(f.set_byte(0x3000, 6).is_none());
+		assert!(!m.is_allocated(0x2000));
+		
+		assert_eq!(m.diff(&f), [
+			Change { addr: 0x0000, old: vec![4], new: vec![1] },
+			Change { addr: 0x1000, old: vec![2], new: vec![3] },
+			Change { addr: 0x2000, old: vec![0], new: vec![5] },
+		]);
+		assert_eq!(f.dirty_pages().collect::<Vec<_>>(), [1, 2]);
 	}
 }
 
@@ -0,0 +1,239 @@
+use std::any::Any;
+use std::ops::RangeInclusive;
+
+use super::{word, mem::Memory};
+
+mod keyboard;
+pub use keyboard::KeyboardDisplay;
+
+mod bitmap;
+pub use bitmap::{BitmapDisplay, PixelFormat};
+
+mod text;
+pub use text::{TextDisplay, Cell};
+
+mod timer;
+pub use timer::Timer;
+
+mod disk;
+pub use disk::Disk;
+
+mod audio;
+pub use audio::{Audio, SampleFormat};
+
+mod uart;
+pub use uart::Uart;
+
+mod digital_lab;
+pub use digital_lab::DigitalLab;
+
+mod tiles;
+pub use tiles::{TileGraphics, Sprite};
+
+/// Something that lives in memory, like a keyboard or a screen.
+/// 
+/// Devices keep their registers in plain old memory, so the CPU (and the
+/// memory monitor) read and write them like anything else. The device just
+/// gets told whenever the CPU touches them, and gets to do a little work after
+/// every instruction. That's pretty much how MARS' tools work too.
+pub trait Device: Any + DeviceClone {
+	/// What to call it in the UI.
+	fn name(&self) -> &'static str;
+	
+	/// Where the device's registers are.
+	fn range(&self) -> RangeInclusive<word>;
+	
+	/// Puts the device (and its registers) back the way they'd be at power-on.
+	/// This also happens when it's first attached.
+	fn reset(&mut self, mem: &mut Memory);
+	
+	/// The CPU's about to load `size` bytes from `addr`, somewhere in
+	/// `range`. Registers that change all the time (like clocks) can be
+	/// written here, instead of on every tick.
+	fn before_load(&mut self, _mem: &mut Memory, _addr: word, _size: word) {}
+	
+	/// The CPU just loaded `size` bytes from `addr`, somewhere in `range`.
+	fn on_load(&mut self, _mem: &mut Memory, _addr: word, _size: word) {}
+	
+	/// The CPU just stored `size` bytes to `addr`, somewhere in `range`. The
+	/// new value's already in memory.
+	fn on_store(&mut self, _mem: &mut Memory, _addr: word, _size: word) {}
+	
+	/// Runs once after every instruction.
+	/// 
+	/// Returns which interrupt lines the device raised, if any. Bit `n` ends
+	/// up as bit `8 + n` of the `Cause` register.
+	fn tick(&mut self, _mem: &mut Memory) -> u8 { 0 }
+}
+
+/// Lets boxed devices be cloned, so memory with devices in it can still be
+/// forked. Anything that's a `Device` and `Clone` gets this for free.
+pub trait DeviceClone {
+	fn clone_box(&self) -> Box<dyn Device>;
+}
+impl<T: Device + Clone> DeviceClone for T {
+	fn clone_box(&self) -> Box<dyn Device> { Box::new(self.clone()) }
+}
+impl Clone for Box<dyn Device> {
+	fn clone(&self) -> Self { self.clone_box() }
+}
+
+impl Memory {
+	/// Hooks up a device, resetting it so its registers are all set up.
+	pub fn attach(&mut self, mut device: Box<dyn Device>) {
+		device.reset(self);
+		self.devices.push(device);
+	}
+	
+	/// Unhooks every device. Their registers are left in memory as they were.
+	pub fn detach_all(&mut self) {
+		self.devices.clear();
+	}
+	
+	pub fn devices(&self) -> impl Iterator<Item = &dyn Device> {
+		self.devices.iter().map(|d| d.as_ref())
+	}
+	
+	/// Finds the first attached device of type `T`.
+	pub fn device<T: Device>(&self) -> Option<&T> {
+		self.devices.iter().find_map(|d| (d.as_ref() as &dyn Any).downcast_ref())
+	}
+	
+	/// Finds the first attached device of type `T`.
+	pub fn device_mut<T: Device>(&mut self) -> Option<&mut T> {
+		self.devices.iter_mut().find_map(|d| (d.as_mut() as &mut dyn Any).downcast_mut())
+	}
+	
+	/// Resets every attached device.
+	pub fn reset_devices(&mut self) {
+		self.with_devices(|d, mem| d.reset(mem));
+	}
+	
+	/// Lets every device know the CPU's about to load `size` bytes from `addr`.
+	pub fn device_before_load(&mut self, addr: word, size: word) {
+		self.with_devices(|d, mem| if touches(d, addr, size) { d.before_load(mem, addr, size) });
+	}
+	
+	/// Lets every device know the CPU loaded `size` bytes from `addr`.
+	pub fn device_load(&mut self, addr: word, size: word) {
+		self.with_devices(|d, mem| if touches(d, addr, size) { d.on_load(mem, addr, size) });
+	}
+	
+	/// Lets every device know the CPU stored `size` bytes to `addr`.
+	pub fn device_store(&mut self, addr: word, size: word) {
+		self.with_devices(|d, mem| if touches(d, addr, size) { d.on_store(mem, addr, size) });
+	}
+	
+	/// Ticks every device, returning all the interrupt lines they raised.
+	pub fn tick_devices(&mut self) -> u8 {
+		let mut lines = 0;
+		self.with_devices(|d, mem| lines |= d.tick(mem));
+		lines
+	}
+	
+	/// Devices need to change memory while they're in it, so they're taken
+	/// out for a moment.
+	fn with_devices(&mut self, mut f: impl FnMut(&mut dyn Device, &mut Memory)) {
+		if self.devices.is_empty() { return; }
+		
+		let mut devices = std::mem::take(&mut self.devices);
+		for d in devices.iter_mut() { f(d.as_mut(), self); }
+		
+		// in case one got attached in the meantime
+		devices.append(&mut self.devices);
+		self.devices = devices;
+	}
+}
+
+fn touches(device: &dyn Device, addr: word, size: word) -> bool {
+	let range = device.range();
+	let last = addr.wrapping_add(size - 1);
+	range.contains(&addr) || range.contains(&last)
+}
+
+/// Loads and stores that go through devices exactly like the CPU's do, for
+/// testing them.
+#[cfg(test)]
+pub(crate) mod bus {
+	use super::*;
+	
+	/// Like `lb`, `lhu`, or `lw`: `before_load`, the read, and then `on_load`.
+	pub(crate) fn load(mem: &mut Memory, addr: word, size: word) -> word {
+		mem.device_before_load(addr, size);
+		let val = match size {
+			1 => mem.get_byte(addr) as word,
+			2 => mem.get_half(addr).map(word::from).unwrap(),
+			_ => mem.get_word(addr).unwrap(),
+		};
+		mem.device_load(addr, size);
+		val
+	}
+	
+	/// Like `sb`, `sh`, or `sw`: the write, and then `on_store`.
+	pub(crate) fn store(mem: &mut Memory, addr: word, size: word, val: word) {
+		match size {
+			1 => mem.set_byte(addr, val as u8),
+			2 => mem.set_half(addr, val as u16),
+			_ => mem.set_word(addr, val),
+		}.unwrap();
+		mem.device_store(addr, size);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	/// Counts loads, stores, and ticks, and raises line 2 every 3rd tick.
+	#[derive(Clone, Default)]
+	struct Counter { loads: u32, stores: u32, ticks: u32 }
+	impl Device for Counter {
+		fn name(&self) -> &'static str { "Counter" }
+		fn range(&self) -> RangeInclusive<word> { 0x100..=0x107 }
+		fn reset(&mut self, mem: &mut Memory) {
+			*self = Counter::default();
+			mem.set_word(0x100, 0xC0FFEE);
+		}
+		fn on_load(&mut self, _mem: &mut Memory, _addr: word, _size: word) { self.loads += 1; }
+		fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+			self.stores += 1;
+			mem.set_word(0x104, addr);
+		}
+		fn tick(&mut self, _mem: &mut Memory) -> u8 {
+			self.ticks += 1;
+			if self.ticks.is_multiple_of(3) { 1 << 2 } else { 0 }
+		}
+	}
+	
+	#[test]
+	fn devices_get_hooked() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(Counter::default()));
+		assert_eq!(mem.get_word(0x100), Some(0xC0FFEE), "attaching resets");
+		
+		mem.device_load(0x0FC, 4); // just before it
+		mem.device_load(0x0FE, 4); // pokes into it
+		mem.device_load(0x107, 1);
+		mem.device_store(0x102, 2);
+		assert_eq!(mem.get_word(0x104), Some(0x102));
+		
+		let lines: Vec<u8> = (0..6).map(|_| mem.tick_devices()).collect();
+		assert_eq!(lines, [0, 0, 4, 0, 0, 4]);
+		
+		let c = mem.device::<Counter>().unwrap();
+		assert_eq!((c.loads, c.stores, c.ticks), (2, 1, 6));
+		
+		// forks get their own copy of the device
+		let mut fork = mem.fork();
+		fork.tick_devices();
+		assert_eq!(fork.device::<Counter>().unwrap().ticks, 7);
+		assert_eq!(mem.device::<Counter>().unwrap().ticks, 6);
+		
+		mem.clear();
+		assert_eq!(mem.device::<Counter>().unwrap().ticks, 0, "clearing resets devices");
+		assert_eq!(mem.get_word(0x100), Some(0xC0FFEE));
+		
+		mem.detach_all();
+		assert!(mem.device_mut::<Counter>().is_none());
+	}
+}
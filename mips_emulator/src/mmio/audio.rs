@@ -0,0 +1,343 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// How samples written to an [`Audio`] device are laid out.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SampleFormat {
+	/// One unsigned byte per sample, with silence at 128. Like an old
+	/// Sound Blaster.
+	#[default]
+	U8 = 0,
+	
+	/// One signed halfword per sample, with silence at 0. Like a CD.
+	S16 = 1,
+}
+impl SampleFormat {
+	pub const ALL: [SampleFormat; 2] = [
+		SampleFormat::U8,
+		SampleFormat::S16,
+	];
+	
+	pub const fn friendly_name(self) -> &'static str {
+		match self {
+			SampleFormat::U8 => "8-bit Unsigned",
+			SampleFormat::S16 => "16-bit Signed",
+		}
+	}
+	
+	/// Turns the low bits of a stored word into a 16-bit sample.
+	pub const fn to_s16(self, val: word) -> i16 {
+		match self {
+			SampleFormat::U8 => ((val as u8 as i16) - 128) << 8,
+			SampleFormat::S16 => val as u16 as i16,
+		}
+	}
+}
+impl TryFrom<word> for SampleFormat {
+	type Error = &'static str;
+	fn try_from(w: word) -> Result<Self, Self::Error> {
+		SampleFormat::ALL.into_iter()
+			.find(|&f| f as word == w)
+			.ok_or("unknown sample format")
+	}
+}
+
+/// A sound card with a FIFO of PCM samples. The CPU keeps it topped up, and
+/// it plays them at the sample rate, as measured in instructions.
+/// 
+/// Nothing actually comes out of the speakers. What's been played is kept
+/// around instead, and can be saved as a `.wav` file.
+/// 
+/// | offset | register | what it does                                          |
+/// |--------|----------|-------------------------------------------------------|
+/// | `0x00` | sample   | storing here adds a sample to the FIFO                |
+/// | `0x04` | rate     | samples per second, per channel                       |
+/// | `0x08` | format   | a [`SampleFormat`]                                    |
+/// | `0x0C` | channels | 1 for mono, 2 for stereo (left, then right)           |
+/// | `0x10` | status   | see below                                             |
+/// | `0x14` | queued   | how many samples are in the FIFO. read-only           |
+/// | `0x18` | capacity | how many samples fit in the FIFO. read-only           |
+/// 
+/// Status has bit 0 set while there's room in the FIFO, bit 1 to raise an
+/// interrupt on line 4 when the FIFO drains down to half full, and bit 2
+/// set when a sample got dropped because the FIFO was full. Write a 0 to
+/// bit 2 to clear it.
+/// 
+/// If the FIFO runs dry, nothing gets played until there's more, so a slow
+/// program doesn't leave gaps in the file.
+#[derive(Clone, Debug)]
+pub struct Audio {
+	base: word,
+	
+	pub rate: word,
+	pub format: SampleFormat,
+	pub channels: word,
+	pub interrupts: bool,
+	
+	/// Set when a sample got dropped, until the program clears it.
+	pub overflowed: bool,
+	
+	queue: VecDeque<i16>,
+	pub capacity: usize,
+	
+	/// Every sample that's been played so far, as 16-bit no matter what
+	/// format they were written in.
+	pub played: Vec<i16>,
+	
+	/// How fast the CPU is pretending to be, which decides how many
+	/// instructions go by between samples.
+	pub instructions_per_second: word,
+	
+	/// Towards the next sample, in sample-rate-times-instructions units.
+	elapsed: u64,
+}
+
+impl Audio {
+	pub const SAMPLE: word = 0x00;
+	pub const RATE: word = 0x04;
+	pub const FORMAT: word = 0x08;
+	pub const CHANNELS: word = 0x0C;
+	pub const STATUS: word = 0x10;
+	pub const QUEUED: word = 0x14;
+	pub const CAPACITY: word = 0x18;
+	
+	pub const READY: word = 1 << 0;
+	pub const INTERRUPT_ENABLE: word = 1 << 1;
+	pub const OVERFLOW: word = 1 << 2;
+	
+	/// Interrupt line raised when the FIFO's half empty.
+	pub const AUDIO_LINE: u8 = 4;
+	
+	/// Makes one with its registers at `base`, playing 8 kHz mono with an
+	/// instruction every microsecond.
+	pub fn new(base: word) -> Self {
+		Audio {
+			base,
+			rate: 8000,
+			format: SampleFormat::default(),
+			channels: 1,
+			interrupts: false,
+			overflowed: false,
+			queue: VecDeque::new(),
+			capacity: 1024,
+			played: vec![],
+			instructions_per_second: 1_000_000,
+			elapsed: 0,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	pub fn queued(&self) -> usize { self.queue.len() }
+	
+	pub fn status(&self) -> word {
+		let mut status = 0;
+		if self.queue.len() < self.capacity { status |= Self::READY; }
+		if self.interrupts { status |= Self::INTERRUPT_ENABLE; }
+		if self.overflowed { status |= Self::OVERFLOW; }
+		status
+	}
+	
+	/// How long what's been played so far lasts.
+	pub fn duration(&self) -> std::time::Duration {
+		let frames = self.played.len() as f64 / self.channels.max(1) as f64;
+		std::time::Duration::from_secs_f64(frames / self.rate.max(1) as f64)
+	}
+	
+	/// What's been played so far as a 16-bit PCM `.wav` file, followed by
+	/// whatever's still in the FIFO. That way nothing the program wrote is
+	/// left out, even if it stopped before it all got played.
+	pub fn wav(&self) -> Vec<u8> {
+		let samples: Vec<i16> = self.played.iter()
+			.chain(&self.queue)
+			.copied()
+			.collect();
+		
+		let channels = self.channels.clamp(1, 2) as u16;
+		let data_len = samples.len() as u32 * 2;
+		let block_align = channels * 2;
+		
+		let mut wav = Vec::with_capacity(44 + data_len as usize);
+		wav.extend(b"RIFF");
+		wav.extend((36 + data_len).to_le_bytes());
+		wav.extend(b"WAVE");
+		
+		wav.extend(b"fmt ");
+		wav.extend(16u32.to_le_bytes());
+		wav.extend(1u16.to_le_bytes()); // PCM
+		wav.extend(channels.to_le_bytes());
+		wav.extend(self.rate.to_le_bytes());
+		wav.extend(self.rate.wrapping_mul(block_align as u32).to_le_bytes());
+		wav.extend(block_align.to_le_bytes());
+		wav.extend(16u16.to_le_bytes());
+		
+		wav.extend(b"data");
+		wav.extend(data_len.to_le_bytes());
+		wav.extend(samples.into_iter().flat_map(i16::to_le_bytes));
+		wav
+	}
+	
+	pub fn save_wav(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+		std::fs::write(path, self.wav())
+	}
+	
+	fn write_registers(&self, mem: &mut Memory) {
+		let regs = [
+			(Self::SAMPLE, 0),
+			(Self::RATE, self.rate),
+			(Self::FORMAT, self.format as word),
+			(Self::CHANNELS, self.channels),
+			(Self::STATUS, self.status()),
+			(Self::QUEUED, self.queue.len() as word),
+			(Self::CAPACITY, self.capacity as word),
+		];
+		for (reg, val) in regs {
+			mem.set_word(self.base + reg, val);
+		}
+	}
+}
+
+impl Device for Audio {
+	fn name(&self) -> &'static str { "Audio" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + 0x1F }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		*self = Audio {
+			capacity: self.capacity,
+			instructions_per_second: self.instructions_per_second,
+			..Audio::new(self.base)
+		};
+		self.write_registers(mem);
+	}
+	
+	fn before_load(&mut self, mem: &mut Memory, _addr: word, _size: word) {
+		self.write_registers(mem);
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		let reg = addr & !3;
+		let val = mem.get_word(reg).unwrap_or(0);
+		
+		match reg.wrapping_sub(self.base) {
+			Self::SAMPLE => {
+				if self.queue.len() < self.capacity {
+					self.queue.push_back(self.format.to_s16(val));
+				} else {
+					self.overflowed = true;
+				}
+			},
+			Self::RATE => self.rate = val,
+			Self::FORMAT => self.format = val.try_into().unwrap_or(self.format),
+			Self::CHANNELS => self.channels = val.clamp(1, 2),
+			Self::STATUS => {
+				self.interrupts = val & Self::INTERRUPT_ENABLE != 0;
+				// can be cleared, but not set
+				self.overflowed &= val & Self::OVERFLOW != 0;
+			},
+			_ => (),
+		}
+		
+		self.write_registers(mem);
+	}
+	
+	fn tick(&mut self, _mem: &mut Memory) -> u8 {
+		if self.queue.is_empty() {
+			self.elapsed = 0;
+			return 0;
+		}
+		
+		let before = self.queue.len();
+		
+		// both sides of "samples per second = instructions per second" are
+		// multiplied out, so nothing gets lost to rounding
+		self.elapsed += self.rate as u64 * self.channels as u64;
+		let ips = self.instructions_per_second.max(1) as u64;
+		while self.elapsed >= ips {
+			self.elapsed -= ips;
+			let Some(sample) = self.queue.pop_front() else { break };
+			self.played.push(sample);
+		}
+		
+		let half = self.capacity / 2;
+		if self.interrupts && before > half && self.queue.len() <= half {
+			1 << Self::AUDIO_LINE
+		} else { 0 }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::{load, store};
+	
+	#[test]
+	fn fifo() {
+		let mut mem = Memory::default();
+		let mut audio = Audio::new(0x7FA0);
+		audio.capacity = 4;
+		mem.attach(Box::new(audio));
+		
+		// one sample every 4 instructions
+		store(&mut mem, 0x7FA4, 4, 250_000);
+		for s in [128, 255, 0, 192, 64] {
+			store(&mut mem, 0x7FA0, 4, s);
+		}
+		assert_eq!(load(&mut mem, 0x7FB4, 4), 4);
+		assert_eq!(load(&mut mem, 0x7FB0, 4), Audio::OVERFLOW, "the last one didn't fit");
+		
+		let lines: Vec<u8> = (0..8).map(|_| mem.tick_devices()).collect();
+		assert_eq!(lines, [0; 8], "interrupts are off");
+		
+		let audio = mem.device::<Audio>().unwrap();
+		assert_eq!(audio.played, [0, 127 << 8]);
+		assert_eq!(audio.queued(), 2);
+		
+		store(&mut mem, 0x7FB0, 4, Audio::INTERRUPT_ENABLE);
+		store(&mut mem, 0x7FA8, 4, SampleFormat::S16 as word);
+		store(&mut mem, 0x7FA0, 4, 0xFFFF_8000);
+		store(&mut mem, 0x7FA0, 4, 0x7FFF);
+		assert_eq!(load(&mut mem, 0x7FB0, 4), Audio::INTERRUPT_ENABLE, "full, and the overflow's cleared");
+		
+		let fired: Vec<usize> = (1..=40)
+			.filter(|_| mem.tick_devices() == 1 << Audio::AUDIO_LINE)
+			.collect();
+		assert_eq!(fired, [8], "once it's down to 2");
+		
+		let audio = mem.device::<Audio>().unwrap();
+		assert_eq!(audio.played[2..], [-128 << 8, 64 << 8, i16::MIN, i16::MAX]);
+		assert_eq!(audio.duration(), std::time::Duration::from_micros(24));
+	}
+	
+	#[test]
+	fn wav() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(Audio::new(0x7FA0)));
+		
+		store(&mut mem, 0x7FA4, 4, 44100);
+		store(&mut mem, 0x7FA8, 4, SampleFormat::S16 as word);
+		store(&mut mem, 0x7FAC, 4, 2);
+		for s in [1, 2, 0xFFFF, 0xFFFE] {
+			store(&mut mem, 0x7FA0, 4, s);
+		}
+		
+		let wav = mem.device::<Audio>().unwrap().wav();
+		assert_eq!(wav.len(), 44 + 8);
+		assert_eq!(&wav[..4], b"RIFF");
+		assert_eq!(wav[4..8], 44u32.to_le_bytes());
+		assert_eq!(wav[22..24], 2u16.to_le_bytes(), "stereo");
+		assert_eq!(wav[24..28], 44100u32.to_le_bytes());
+		assert_eq!(wav[28..32], (44100u32 * 4).to_le_bytes());
+		assert_eq!(wav[44..], [1, 0, 2, 0, 0xFF, 0xFF, 0xFE, 0xFF], "still queued, but written anyway");
+		
+		let path = std::env::temp_dir().join(format!("mips_audio_test_{}.wav", std::process::id()));
+		mem.device::<Audio>().unwrap().save_wav(&path).unwrap();
+		assert_eq!(std::fs::read(&path).unwrap(), wav);
+		std::fs::remove_file(&path).unwrap();
+	}
+}
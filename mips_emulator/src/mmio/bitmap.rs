@@ -0,0 +1,314 @@
+use std::ops::RangeInclusive;
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// How pixels are laid out in the framebuffer.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+	/// One word per pixel, `0x00RRGGBB`. Same thing MARS' Bitmap Display uses.
+	#[default]
+	Xrgb8888 = 0,
+	
+	/// One halfword per pixel, `RRRRRGGGGGGBBBBB`.
+	Rgb565 = 1,
+	
+	/// One byte per pixel, which picks one of 256 `0x00RRGGBB` words in the
+	/// palette.
+	Indexed8 = 2,
+}
+impl PixelFormat {
+	pub const ALL: [PixelFormat; 3] = [
+		PixelFormat::Xrgb8888,
+		PixelFormat::Rgb565,
+		PixelFormat::Indexed8,
+	];
+	
+	pub const fn friendly_name(self) -> &'static str {
+		match self {
+			PixelFormat::Xrgb8888 => "32-bit XRGB",
+			PixelFormat::Rgb565 => "16-bit RGB565",
+			PixelFormat::Indexed8 => "8-bit Palette",
+		}
+	}
+	
+	pub const fn bytes_per_pixel(self) -> usize {
+		match self {
+			PixelFormat::Xrgb8888 => 4,
+			PixelFormat::Rgb565 => 2,
+			PixelFormat::Indexed8 => 1,
+		}
+	}
+}
+impl TryFrom<word> for PixelFormat {
+	type Error = &'static str;
+	fn try_from(w: word) -> Result<Self, Self::Error> {
+		PixelFormat::ALL.into_iter()
+			.find(|&f| f as word == w)
+			.ok_or("unknown pixel format")
+	}
+}
+
+/// A bitmap display that draws straight out of memory.
+/// 
+/// The pixels live in a framebuffer somewhere else in memory. The device's
+/// own registers just say where, and how to read them:
+/// 
+/// | offset | register    | what it does                                       |
+/// |--------|-------------|----------------------------------------------------|
+/// | `0x00` | status      | bit 0: vblank (read clears it), bit 1: interrupts  |
+/// | `0x04` | framebuffer | address of the top-left pixel                      |
+/// | `0x08` | palette     | address of the palette, for 8-bit pixels           |
+/// | `0x0C` | format      | a [`PixelFormat`]                                  |
+/// | `0x10` | width       | in pixels, read-only                               |
+/// | `0x14` | height      | in pixels, read-only                               |
+/// | `0x18` | frame       | how many frames have gone by, read-only            |
+/// 
+/// A frame is [`BitmapDisplay::frame_length`] instructions long. When one
+/// ends, the vblank bit gets set, and with interrupts on, line 2 gets raised.
+#[derive(Clone, Debug)]
+pub struct BitmapDisplay {
+	base: word,
+	
+	pub framebuffer: word,
+	pub palette: word,
+	pub format: PixelFormat,
+	
+	pub width: usize,
+	pub height: usize,
+	
+	/// How many instructions each frame lasts.
+	pub frame_length: u32,
+	
+	/// How far into the current frame it is.
+	elapsed: u32,
+	frame: word,
+	
+	/// Whether a frame ended since the status register was last read.
+	vblank: bool,
+}
+
+impl BitmapDisplay {
+	pub const STATUS: word = 0x00;
+	pub const FRAMEBUFFER: word = 0x04;
+	pub const PALETTE: word = 0x08;
+	pub const FORMAT: word = 0x0C;
+	pub const WIDTH: word = 0x10;
+	pub const HEIGHT: word = 0x14;
+	pub const FRAME: word = 0x18;
+	
+	pub const VBLANK: word = 1 << 0;
+	pub const INTERRUPT_ENABLE: word = 1 << 1;
+	
+	/// Interrupt line raised when a frame ends.
+	pub const VBLANK_LINE: u8 = 2;
+	
+	/// Number of entries in the palette.
+	pub const PALETTE_SIZE: usize = 256;
+	
+	/// Makes one with its registers at `base`, showing `width` × `height`
+	/// 32-bit pixels starting at `framebuffer`.
+	pub fn new(base: word, framebuffer: word, width: usize, height: usize) -> Self {
+		BitmapDisplay {
+			base,
+			framebuffer,
+			palette: 0,
+			format: PixelFormat::default(),
+			width,
+			height,
+			frame_length: 1000,
+			elapsed: 0,
+			frame: 0,
+			vblank: false,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	/// How many frames have gone by since it was reset.
+	pub fn frame(&self) -> word { self.frame }
+	
+	/// How many bytes of memory the framebuffer takes up.
+	pub fn framebuffer_len(&self) -> usize {
+		self.width * self.height * self.format.bytes_per_pixel()
+	}
+	
+	/// Reads the framebuffer out of memory, giving back every pixel as
+	/// `0x00RRGGBB`, row by row.
+	pub fn render(&self, mem: &Memory) -> Vec<u32> {
+		let data = mem.copy_to_vec(self.framebuffer, self.framebuffer_len());
+		
+		match self.format {
+			PixelFormat::Xrgb8888 => data.chunks_exact(4)
+				.map(|c| u32::from_le_bytes(c.try_into().unwrap()) & 0x00FF_FFFF)
+				.collect(),
+			PixelFormat::Rgb565 => data.chunks_exact(2)
+				.map(|c| rgb565(u16::from_le_bytes(c.try_into().unwrap())))
+				.collect(),
+			PixelFormat::Indexed8 => {
+				let palette: Vec<u32> = mem.copy_to_vec(self.palette, Self::PALETTE_SIZE * 4)
+					.chunks_exact(4)
+					.map(|c| u32::from_le_bytes(c.try_into().unwrap()) & 0x00FF_FFFF)
+					.collect();
+				data.iter().map(|&i| palette[i as usize]).collect()
+			},
+		}
+	}
+	
+	/// Writes every register but status, since those can all be changed from
+	/// outside the CPU.
+	fn write_registers(&self, mem: &mut Memory) {
+		let regs = [
+			(Self::FRAMEBUFFER, self.framebuffer),
+			(Self::PALETTE, self.palette),
+			(Self::FORMAT, self.format as word),
+			(Self::WIDTH, self.width as word),
+			(Self::HEIGHT, self.height as word),
+			(Self::FRAME, self.frame),
+		];
+		for (reg, val) in regs {
+			mem.set_word(self.base + reg, val);
+		}
+	}
+	
+	/// Writes the status register, keeping the interrupt enable bit from what
+	/// the program wrote there. Returns what got written.
+	fn write_status(&self, mem: &mut Memory) -> word {
+		let ie = mem.get_word(self.base + Self::STATUS).unwrap_or(0) & Self::INTERRUPT_ENABLE;
+		let status = ie | if self.vblank { Self::VBLANK } else { 0 };
+		mem.set_word(self.base + Self::STATUS, status);
+		status
+	}
+}
+
+/// Spreads each channel out to 8 bits, so full brightness is still `0xFF`.
+fn rgb565(p: u16) -> u32 {
+	let p = p as u32;
+	let (r, g, b) = ((p >> 11) & 0x1F, (p >> 5) & 0x3F, p & 0x1F);
+	let (r, g, b) = ((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2));
+	(r << 16) | (g << 8) | b
+}
+
+impl Device for BitmapDisplay {
+	fn name(&self) -> &'static str { "Bitmap Display" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + 0x1F }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		self.elapsed = 0;
+		self.frame = 0;
+		self.vblank = false;
+		
+		mem.set_word(self.base + Self::STATUS, 0);
+		self.write_registers(mem);
+	}
+	
+	fn on_load(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		if addr & !3 == self.base + Self::STATUS {
+			self.vblank = false;
+			self.write_status(mem);
+		}
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		let reg = addr & !3;
+		let val = mem.get_word(reg).unwrap_or(0);
+		
+		match reg.wrapping_sub(self.base) {
+			// vblank can only be cleared by reading it
+			Self::STATUS => { self.write_status(mem); },
+			Self::FRAMEBUFFER => self.framebuffer = val,
+			Self::PALETTE => self.palette = val,
+			Self::FORMAT => if let Ok(f) = PixelFormat::try_from(val) { self.format = f },
+			_ => (),
+		}
+		
+		self.write_registers(mem);
+	}
+	
+	fn tick(&mut self, mem: &mut Memory) -> u8 {
+		self.elapsed += 1;
+		if self.elapsed < self.frame_length { return 0; }
+		
+		self.elapsed = 0;
+		self.frame = self.frame.wrapping_add(1);
+		
+		self.vblank = true;
+		let status = self.write_status(mem);
+		self.write_registers(mem);
+		
+		if status & Self::INTERRUPT_ENABLE != 0 { 1 << Self::VBLANK_LINE } else { 0 }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::{load, store};
+	
+	type BD = BitmapDisplay;
+	
+	#[test]
+	fn pixel_formats() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(BD::new(0x7F20, 0x1000, 2, 2)));
+		
+		let pixels = [0x00FF_0000u32, 0x0000_FF00, 0xFF00_00FF, 0x0012_3456];
+		mem.set_slice(0x1000, &pixels.map(u32::to_le_bytes).concat());
+		let bd = mem.device::<BD>().unwrap();
+		assert_eq!(bd.render(&mem), [0xFF_0000, 0x00_FF00, 0x00_00FF, 0x12_3456], "top byte's ignored");
+		
+		// switching formats from the CPU's side
+		store(&mut mem, 0x7F2C, 4, PixelFormat::Rgb565 as word);
+		let rgb565 = [0xF800u16, 0x07E0, 0x001F, 0xFFFF];
+		mem.set_slice(0x1000, &rgb565.map(u16::to_le_bytes).concat());
+		let bd = mem.device::<BD>().unwrap();
+		assert_eq!(bd.format, PixelFormat::Rgb565);
+		assert_eq!(bd.render(&mem), [0xFF_0000, 0x00_FF00, 0x00_00FF, 0xFF_FFFF]);
+		
+		// and on to the palette
+		store(&mut mem, 0x7F28, 4, 0x2000);
+		store(&mut mem, 0x7F2C, 4, PixelFormat::Indexed8 as word);
+		mem.set_slice(0x1000, &[0, 1, 255, 1]);
+		mem.set_word(0x2000, 0x0011_2233);
+		mem.set_word(0x2004, 0x0044_5566);
+		mem.set_word(0x23FC, 0x0077_8899);
+		let bd = mem.device::<BD>().unwrap();
+		assert_eq!(bd.render(&mem), [0x11_2233, 0x44_5566, 0x77_8899, 0x44_5566]);
+		
+		// nonsense formats get ignored
+		store(&mut mem, 0x7F2C, 4, 7);
+		assert_eq!(mem.get_word(0x7F2C), Some(PixelFormat::Indexed8 as word));
+		
+		// the size can't be changed by the CPU
+		store(&mut mem, 0x7F30, 4, 100);
+		assert_eq!(mem.get_word(0x7F30), Some(2));
+	}
+	
+	#[test]
+	fn vblank() {
+		let mut mem = Memory::default();
+		let mut bd = BD::new(0xFFFF_0020, 0x1001_0000, 512, 256);
+		bd.frame_length = 3;
+		mem.attach(Box::new(bd));
+		
+		let status = |mem: &Memory| mem.get_word(0xFFFF_0020).unwrap();
+		
+		assert_eq!(mem.tick_devices(), 0);
+		assert_eq!(mem.tick_devices(), 0);
+		assert_eq!(mem.tick_devices(), 0, "interrupts are off");
+		assert_eq!(status(&mem), BD::VBLANK);
+		assert_eq!(mem.get_word(0xFFFF_0038), Some(1), "frame counter");
+		
+		// writing can't clear vblank, but reading can
+		store(&mut mem, 0xFFFF_0020, 4, BD::INTERRUPT_ENABLE);
+		assert_eq!(status(&mem), BD::VBLANK | BD::INTERRUPT_ENABLE);
+		load(&mut mem, 0xFFFF_0020, 4);
+		assert_eq!(status(&mem), BD::INTERRUPT_ENABLE);
+		
+		let lines: Vec<u8> = (0..3).map(|_| mem.tick_devices()).collect();
+		assert_eq!(lines, [0, 0, 1 << BD::VBLANK_LINE]);
+		assert_eq!(mem.device::<BD>().unwrap().frame(), 2);
+	}
+}
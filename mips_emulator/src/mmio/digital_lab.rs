@@ -0,0 +1,225 @@
+use std::ops::RangeInclusive;
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// MARS' "Digital Lab Sim" tool: two seven-segment displays, a 4×4 hex
+/// keypad, and a counter that interrupts every so often. The registers are
+/// bytes, in the same spots as in MARS if `base` is `0xFFFF_0010`:
+/// 
+/// | offset | register | what it does                                          |
+/// |--------|----------|-------------------------------------------------------|
+/// | `0x0`  | right    | segments of the right display                         |
+/// | `0x1`  | left     | segments of the left display                          |
+/// | `0x2`  | keypad   | bits 0-3 pick rows to scan, bit 7 turns on interrupts |
+/// | `0x3`  | counter  | bit 7 turns on the counter's interrupts               |
+/// | `0x4`  | key      | the key that's down, if it's in a picked row          |
+/// 
+/// Segments are bit 0 for the top one, going clockwise to bit 5, then bit 6
+/// for the middle and bit 7 for the dot.
+/// 
+/// Keys read back as one bit for the row in the low nibble, and one bit for
+/// the column in the high nibble, so key 0 is `0x11` and key F is `0x88`.
+/// Pressing one with interrupts on raises line 1, and the counter raises
+/// line 0 every 30 instructions. Same as MARS, those clash with the
+/// keyboard's lines.
+#[derive(Clone, Debug)]
+pub struct DigitalLab {
+	base: word,
+	
+	pub left: u8,
+	pub right: u8,
+	
+	/// Which key's held down, from 0 to 15.
+	pub pressed: Option<u8>,
+	rows: u8,
+	pub keypad_interrupts: bool,
+	
+	pub counter_interrupts: bool,
+	counter: u32,
+	
+	/// A key got pressed since the last tick.
+	raised: bool,
+}
+
+impl DigitalLab {
+	pub const RIGHT: word = 0x0;
+	pub const LEFT: word = 0x1;
+	pub const KEYPAD: word = 0x2;
+	pub const COUNTER: word = 0x3;
+	pub const KEY: word = 0x4;
+	
+	pub const INTERRUPT_ENABLE: u8 = 1 << 7;
+	
+	/// How many instructions go by between counter interrupts.
+	pub const COUNTER_PERIOD: u32 = 30;
+	
+	pub const COUNTER_LINE: u8 = 0;
+	pub const KEYPAD_LINE: u8 = 1;
+	
+	/// The segments for each hex digit, which is what most programs end up
+	/// putting in a table anyway.
+	pub const DIGITS: [u8; 16] = [
+		0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07,
+		0x7F, 0x6F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71,
+	];
+	
+	pub fn new(base: word) -> Self {
+		DigitalLab {
+			base,
+			left: 0,
+			right: 0,
+			pressed: None,
+			rows: 0,
+			keypad_interrupts: false,
+			counter_interrupts: false,
+			counter: 0,
+			raised: false,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	/// Holds down a key, letting go of whichever one was down before.
+	pub fn press(&mut self, key: u8) {
+		self.pressed = Some(key & 0xF);
+		self.raised |= self.keypad_interrupts;
+	}
+	
+	pub fn release(&mut self) {
+		self.pressed = None;
+	}
+	
+	/// What a key reads back as.
+	pub const fn key_code(key: u8) -> u8 {
+		let (row, column) = (key / 4 % 4, key % 4);
+		1 << (column + 4) | 1 << row
+	}
+	
+	/// What the key register says, given which rows are being scanned.
+	pub fn key(&self) -> u8 {
+		match self.pressed {
+			Some(key) if self.rows & Self::key_code(key) != 0 => Self::key_code(key),
+			_ => 0,
+		}
+	}
+	
+	fn write_registers(&self, mem: &mut Memory) {
+		let keypad = self.rows | if self.keypad_interrupts { Self::INTERRUPT_ENABLE } else { 0 };
+		let counter = if self.counter_interrupts { Self::INTERRUPT_ENABLE } else { 0 };
+		let regs = [
+			(Self::RIGHT, self.right),
+			(Self::LEFT, self.left),
+			(Self::KEYPAD, keypad),
+			(Self::COUNTER, counter),
+			(Self::KEY, self.key()),
+		];
+		for (reg, val) in regs {
+			mem.set_byte(self.base + reg, val);
+		}
+	}
+}
+
+impl Device for DigitalLab {
+	fn name(&self) -> &'static str { "Digital Lab Sim" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + Self::KEY }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		*self = DigitalLab::new(self.base);
+		self.write_registers(mem);
+	}
+	
+	fn before_load(&mut self, mem: &mut Memory, _addr: word, _size: word) {
+		// the key can change whenever someone clicks
+		self.write_registers(mem);
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, size: word) {
+		let regs = (addr..addr.saturating_add(size))
+			.filter(|a| self.range().contains(a))
+			.map(|a| (a - self.base, mem.get_byte(a)))
+			.collect::<Vec<_>>();
+		
+		for (reg, val) in regs {
+			match reg {
+				Self::RIGHT => self.right = val,
+				Self::LEFT => self.left = val,
+				Self::KEYPAD => {
+					self.rows = val & 0xF;
+					self.keypad_interrupts = val & Self::INTERRUPT_ENABLE != 0;
+				},
+				Self::COUNTER => self.counter_interrupts = val & Self::INTERRUPT_ENABLE != 0,
+				_ => (),
+			}
+		}
+		
+		self.write_registers(mem);
+	}
+	
+	fn tick(&mut self, _mem: &mut Memory) -> u8 {
+		let mut lines = 0;
+		
+		if std::mem::take(&mut self.raised) {
+			lines |= 1 << Self::KEYPAD_LINE;
+		}
+		
+		if self.counter_interrupts {
+			self.counter += 1;
+			if self.counter >= Self::COUNTER_PERIOD {
+				self.counter = 0;
+				lines |= 1 << Self::COUNTER_LINE;
+			}
+		}
+		
+		lines
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::{load, store};
+	
+	#[test]
+	fn segments_and_keypad() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(DigitalLab::new(0xFFFF_0010)));
+		
+		// "42", with a halfword store to do both at once
+		store(&mut mem, 0xFFFF_0010, 2, word::from(u16::from_le_bytes([DigitalLab::DIGITS[2], DigitalLab::DIGITS[4]])));
+		let lab = mem.device::<DigitalLab>().unwrap();
+		assert_eq!((lab.left, lab.right), (0x66, 0x5B));
+		
+		let codes: Vec<u8> = (0..16).map(DigitalLab::key_code).collect();
+		assert_eq!(codes[..5], [0x11, 0x21, 0x41, 0x81, 0x12]);
+		assert_eq!(codes[15], 0x88);
+		
+		// key 6 is in row 1, so only shows up when that row's picked
+		store(&mut mem, 0xFFFF_0012, 1, word::from(DigitalLab::INTERRUPT_ENABLE | 0b0001));
+		mem.device_mut::<DigitalLab>().unwrap().press(6);
+		assert_eq!(load(&mut mem, 0xFFFF_0014, 1) as u8, 0);
+		store(&mut mem, 0xFFFF_0012, 1, word::from(DigitalLab::INTERRUPT_ENABLE | 0b0010));
+		assert_eq!(load(&mut mem, 0xFFFF_0014, 1) as u8, 0x42);
+		
+		assert_eq!(mem.tick_devices(), 1 << DigitalLab::KEYPAD_LINE);
+		assert_eq!(mem.tick_devices(), 0);
+		
+		mem.device_mut::<DigitalLab>().unwrap().release();
+		assert_eq!(load(&mut mem, 0xFFFF_0014, 1) as u8, 0);
+	}
+	
+	#[test]
+	fn counter() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(DigitalLab::new(0x7F10)));
+		
+		assert!((0..100).all(|_| mem.tick_devices() == 0), "it's off");
+		
+		store(&mut mem, 0x7F13, 1, word::from(DigitalLab::INTERRUPT_ENABLE));
+		let fired: Vec<usize> = (1..=100)
+			.filter(|_| mem.tick_devices() == 1 << DigitalLab::COUNTER_LINE)
+			.collect();
+		assert_eq!(fired, [30, 60, 90]);
+	}
+}
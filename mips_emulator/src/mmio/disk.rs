@@ -0,0 +1,268 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// A disk controller, backed by an image file on the host. It moves whole
+/// 512-byte sectors straight between the image and memory, without the CPU
+/// having to copy anything itself.
+/// 
+/// | offset | register | what it does                                          |
+/// |--------|----------|-------------------------------------------------------|
+/// | `0x00` | sector   | which sector to read or write                         |
+/// | `0x04` | buffer   | where in memory the sector goes, or comes from        |
+/// | `0x08` | command  | write 1 to read a sector, or 2 to write one           |
+/// | `0x0C` | status   | see below                                             |
+/// | `0x10` | sectors  | how big the disk is, in sectors. read-only            |
+/// 
+/// Status has bit 0 set when the disk's ready for a command, bit 1 set if the
+/// last command failed, and bit 2 to raise an interrupt on line 3 when a
+/// command's done. Only bit 2 can be written to.
+/// 
+/// Commands happen as soon as they're written, so the disk's always ready
+/// by the next instruction. The interrupt still waits for that instruction,
+/// though, so there's time to get out of the way.
+/// 
+/// The image isn't kept in memory, it's opened for every command. That means
+/// forked cores write to the same file, same as two computers sharing a
+/// disk would.
+#[derive(Clone, Debug)]
+pub struct Disk {
+	base: word,
+	image: Option<PathBuf>,
+	sectors: word,
+	
+	pub sector: word,
+	pub buffer: word,
+	pub interrupts: bool,
+	
+	/// Why the last command failed, if it did.
+	pub error: Option<&'static str>,
+	
+	/// A command finished, and the interrupt hasn't gone off yet.
+	done: bool,
+}
+
+impl Disk {
+	pub const SECTOR: word = 0x00;
+	pub const BUFFER: word = 0x04;
+	pub const COMMAND: word = 0x08;
+	pub const STATUS: word = 0x0C;
+	pub const SECTORS: word = 0x10;
+	
+	pub const READ: word = 1;
+	pub const WRITE: word = 2;
+	
+	pub const READY: word = 1 << 0;
+	pub const ERROR: word = 1 << 1;
+	pub const INTERRUPT_ENABLE: word = 1 << 2;
+	
+	/// Interrupt line raised when a command's done.
+	pub const DISK_LINE: u8 = 3;
+	
+	pub const SECTOR_SIZE: usize = 512;
+	
+	/// Makes one with its registers at `base`, and no disk in it yet.
+	pub fn new(base: word) -> Self {
+		Disk {
+			base,
+			image: None,
+			sectors: 0,
+			sector: 0,
+			buffer: 0,
+			interrupts: false,
+			error: None,
+			done: false,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	pub fn image(&self) -> Option<&Path> { self.image.as_deref() }
+	
+	pub fn sectors(&self) -> word { self.sectors }
+	
+	/// Puts a disk image in. Any bytes past the last whole sector are left
+	/// alone.
+	pub fn insert(&mut self, path: impl Into<PathBuf>) -> Result<(), &'static str> {
+		let path = path.into();
+		let len = std::fs::metadata(&path)
+			.map_err(|_| "couldn't find the disk image")?
+			.len();
+		
+		self.sectors = (len / Self::SECTOR_SIZE as u64).min(word::MAX as u64) as word;
+		self.image = Some(path);
+		Ok(())
+	}
+	
+	pub fn eject(&mut self) {
+		self.image = None;
+		self.sectors = 0;
+	}
+	
+	pub fn status(&self) -> word {
+		let mut status = Self::READY;
+		if self.error.is_some() { status |= Self::ERROR; }
+		if self.interrupts { status |= Self::INTERRUPT_ENABLE; }
+		status
+	}
+	
+	/// Opens the image, and seeks to the start of the current sector.
+	fn open(&self, write: bool) -> Result<File, &'static str> {
+		let path = self.image.as_ref().ok_or("there's no disk in the drive")?;
+		if self.sector >= self.sectors {
+			return Err("that sector's past the end of the disk");
+		}
+		
+		let mut file = OpenOptions::new()
+			.read(true)
+			.write(write)
+			.open(path)
+			.map_err(|_| "couldn't open the disk image")?;
+		
+		let offset = self.sector as u64 * Self::SECTOR_SIZE as u64;
+		file.seek(SeekFrom::Start(offset)).map_err(|_| "couldn't seek in the disk image")?;
+		Ok(file)
+	}
+	
+	/// Reads the current sector into memory at the buffer address.
+	pub fn read_sector(&self, mem: &mut Memory) -> Result<(), &'static str> {
+		let mut data = [0u8; Self::SECTOR_SIZE];
+		self.open(false)?
+			.read_exact(&mut data)
+			.map_err(|_| "couldn't read from the disk image")?;
+		
+		mem.set_slice(self.buffer, &data).ok_or("ran out of memory for the buffer")
+	}
+	
+	/// Writes the current sector from memory at the buffer address.
+	pub fn write_sector(&self, mem: &Memory) -> Result<(), &'static str> {
+		let data = mem.copy_to_vec(self.buffer, Self::SECTOR_SIZE);
+		self.open(true)?
+			.write_all(&data)
+			.map_err(|_| "couldn't write to the disk image")
+	}
+	
+	fn write_registers(&self, mem: &mut Memory) {
+		let regs = [
+			(Self::SECTOR, self.sector),
+			(Self::BUFFER, self.buffer),
+			(Self::COMMAND, 0),
+			(Self::STATUS, self.status()),
+			(Self::SECTORS, self.sectors),
+		];
+		for (reg, val) in regs {
+			mem.set_word(self.base + reg, val);
+		}
+	}
+}
+
+impl Device for Disk {
+	fn name(&self) -> &'static str { "Disk" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + 0x1F }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		self.sector = 0;
+		self.buffer = 0;
+		self.interrupts = false;
+		self.error = None;
+		self.done = false;
+		self.write_registers(mem);
+	}
+	
+	fn before_load(&mut self, mem: &mut Memory, _addr: word, _size: word) {
+		// a disk might've been put in since the last time
+		self.write_registers(mem);
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		let reg = addr & !3;
+		let val = mem.get_word(reg).unwrap_or(0);
+		
+		match reg.wrapping_sub(self.base) {
+			Self::SECTOR => self.sector = val,
+			Self::BUFFER => self.buffer = val,
+			Self::STATUS => self.interrupts = val & Self::INTERRUPT_ENABLE != 0,
+			Self::COMMAND => {
+				let result = match val {
+					Self::READ => self.read_sector(mem),
+					Self::WRITE => self.write_sector(mem),
+					_ => Err("that's not a disk command"),
+				};
+				self.error = result.err();
+				self.done = true;
+			},
+			_ => (),
+		}
+		
+		self.write_registers(mem);
+	}
+	
+	fn tick(&mut self, _mem: &mut Memory) -> u8 {
+		if !std::mem::take(&mut self.done) || !self.interrupts { return 0; }
+		1 << Self::DISK_LINE
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::{load, store};
+	
+	#[test]
+	fn sectors() {
+		let path = std::env::temp_dir().join(format!("mips_disk_test_{}.img", std::process::id()));
+		let mut image = vec![0u8; 3 * Disk::SECTOR_SIZE + 100];
+		image[512..1024].fill(0xAB);
+		std::fs::write(&path, &image).unwrap();
+		
+		let mut mem = Memory::default();
+		let mut disk = Disk::new(0x7F80);
+		disk.insert(&path).unwrap();
+		mem.attach(Box::new(disk));
+		
+		assert_eq!(load(&mut mem, 0x7F90, 4), 3, "the leftover bytes aren't a sector");
+		assert_eq!(load(&mut mem, 0x7F8C, 4), Disk::READY);
+		
+		// sector 1 into 0x1000
+		store(&mut mem, 0x7F80, 4, 1);
+		store(&mut mem, 0x7F84, 4, 0x1000);
+		store(&mut mem, 0x7F88, 4, Disk::READ);
+		assert_eq!(load(&mut mem, 0x7F8C, 4), Disk::READY);
+		assert_eq!(load(&mut mem, 0x7F88, 4), 0);
+		assert_eq!(mem.get_word(0x1000), Some(0xABAB_ABAB));
+		assert_eq!(mem.get_word(0x11FC), Some(0xABAB_ABAB));
+		
+		// and back out to sector 2, with an interrupt this time
+		mem.set_word(0x1000, 0x1234_5678);
+		store(&mut mem, 0x7F8C, 4, Disk::INTERRUPT_ENABLE);
+		store(&mut mem, 0x7F80, 4, 2);
+		store(&mut mem, 0x7F88, 4, Disk::WRITE);
+		assert_eq!(mem.tick_devices(), 1 << Disk::DISK_LINE);
+		assert_eq!(mem.tick_devices(), 0, "only once per command");
+		
+		let image = std::fs::read(&path).unwrap();
+		assert_eq!(image[1024..1028], 0x1234_5678u32.to_le_bytes());
+		assert_eq!(image[1028..1536], [0xAB; 508]);
+		assert_eq!(image.len(), 3 * Disk::SECTOR_SIZE + 100, "the image doesn't grow");
+		
+		// off the end
+		store(&mut mem, 0x7F80, 4, 3);
+		store(&mut mem, 0x7F88, 4, Disk::READ);
+		assert_eq!(load(&mut mem, 0x7F8C, 4), Disk::READY | Disk::ERROR | Disk::INTERRUPT_ENABLE);
+		assert!(mem.device::<Disk>().unwrap().error.is_some());
+		
+		// no disk at all
+		mem.device_mut::<Disk>().unwrap().eject();
+		store(&mut mem, 0x7F80, 4, 0);
+		store(&mut mem, 0x7F88, 4, Disk::READ);
+		assert_eq!(load(&mut mem, 0x7F90, 4), 0);
+		assert_eq!(mem.device::<Disk>().unwrap().error, Some("there's no disk in the drive"));
+		
+		std::fs::remove_file(&path).unwrap();
+	}
+}
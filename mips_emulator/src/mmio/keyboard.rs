@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// MARS' "Keyboard and Display MMIO Simulator".
+/// 
+/// It's four registers, one word each:
+/// 
+/// | offset | register            | what it does                               |
+/// |--------|---------------------|--------------------------------------------|
+/// | `0x0`  | receiver control    | bit 0: a key's ready, bit 1: interrupts    |
+/// | `0x4`  | receiver data       | the key. reading it clears the ready bit   |
+/// | `0x8`  | transmitter control | bit 0: ready to print, bit 1: interrupts   |
+/// | `0xC`  | transmitter data    | writing a character here prints it         |
+/// 
+/// Only the interrupt enable bits can be written to in the control registers.
+/// With interrupts on, a key coming in raises line 0, and the display getting
+/// done with a character raises line 1. (`Cause` bits 8 and 9, same as MARS.)
+#[derive(Clone, Debug)]
+pub struct KeyboardDisplay {
+	base: word,
+	
+	/// Keys that have been typed, but haven't made it to the program yet.
+	keys: VecDeque<u8>,
+	
+	/// Whether there's a key in the receiver data register.
+	received: bool,
+	
+	/// Everything the program has printed so far.
+	pub output: String,
+	
+	/// How many instructions it takes to print a character. MARS defaults to
+	/// 5, so programs that don't wait for the ready bit break the same way.
+	pub delay: u32,
+	
+	/// The character being printed, and how many instructions are left.
+	sending: Option<(u8, u32)>,
+}
+
+impl KeyboardDisplay {
+	pub const RECEIVER_CONTROL: word = 0x0;
+	pub const RECEIVER_DATA: word = 0x4;
+	pub const TRANSMITTER_CONTROL: word = 0x8;
+	pub const TRANSMITTER_DATA: word = 0xC;
+	
+	pub const READY: word = 1 << 0;
+	pub const INTERRUPT_ENABLE: word = 1 << 1;
+	
+	/// Interrupt line raised when a key comes in.
+	pub const KEYBOARD_LINE: u8 = 0;
+	
+	/// Interrupt line raised when a character's done printing.
+	pub const DISPLAY_LINE: u8 = 1;
+	
+	/// Makes one with its registers starting at `base`, which is usually the
+	/// start of the MMIO segment.
+	pub fn new(base: word) -> Self {
+		KeyboardDisplay {
+			base,
+			keys: VecDeque::new(),
+			received: false,
+			output: String::new(),
+			delay: 5,
+			sending: None,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	/// Types a key. If the program hasn't read the last one yet, it waits in
+	/// line behind it.
+	pub fn push_key(&mut self, key: u8) {
+		self.keys.push_back(key);
+	}
+	
+	/// Types every byte of `s`.
+	pub fn push_str(&mut self, s: &str) {
+		self.keys.extend(s.bytes());
+	}
+	
+	/// Number of keys typed that the program hasn't gotten to yet.
+	pub fn keys_waiting(&self) -> usize { self.keys.len() }
+	
+	fn control(&self, mem: &Memory, reg: word) -> word {
+		mem.get_word(self.base + reg).unwrap_or(0)
+	}
+	
+	/// Writes a control register, keeping the interrupt enable bit from what
+	/// the program wrote there.
+	fn set_control(&self, mem: &mut Memory, reg: word, ready: bool) {
+		let ie = self.control(mem, reg) & Self::INTERRUPT_ENABLE;
+		mem.set_word(self.base + reg, ie | ready as word);
+	}
+}
+
+impl Device for KeyboardDisplay {
+	fn name(&self) -> &'static str { "Keyboard and Display" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + 0xF }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		self.keys.clear();
+		self.received = false;
+		self.output.clear();
+		self.sending = None;
+		
+		mem.set_word(self.base + Self::RECEIVER_CONTROL, 0);
+		mem.set_word(self.base + Self::RECEIVER_DATA, 0);
+		mem.set_word(self.base + Self::TRANSMITTER_CONTROL, Self::READY);
+		mem.set_word(self.base + Self::TRANSMITTER_DATA, 0);
+	}
+	
+	fn on_load(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		if addr & !3 == self.base + Self::RECEIVER_DATA {
+			self.received = false;
+			self.set_control(mem, Self::RECEIVER_CONTROL, false);
+		}
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		match (addr & !3).wrapping_sub(self.base) {
+			Self::RECEIVER_CONTROL => self.set_control(mem, Self::RECEIVER_CONTROL, self.received),
+			Self::TRANSMITTER_CONTROL => self.set_control(mem, Self::TRANSMITTER_CONTROL, self.sending.is_none()),
+			
+			// writing while it's still busy just gets ignored, like in MARS
+			Self::TRANSMITTER_DATA if self.sending.is_none() => {
+				let c = mem.get_byte(self.base + Self::TRANSMITTER_DATA);
+				self.sending = Some((c, self.delay));
+				self.set_control(mem, Self::TRANSMITTER_CONTROL, false);
+			},
+			
+			_ => (),
+		}
+	}
+	
+	fn tick(&mut self, mem: &mut Memory) -> u8 {
+		let mut lines = 0;
+		
+		match &mut self.sending {
+			Some((_, left)) if *left > 0 => *left -= 1,
+			&mut Some((c, _)) => {
+				match c {
+					// form feed clears the screen
+					0x0C => self.output.clear(),
+					c => self.output.push(char::from(c)),
+				}
+				self.sending = None;
+				self.set_control(mem, Self::TRANSMITTER_CONTROL, true);
+				
+				if self.control(mem, Self::TRANSMITTER_CONTROL) & Self::INTERRUPT_ENABLE != 0 {
+					lines |= 1 << Self::DISPLAY_LINE;
+				}
+			},
+			None => (),
+		}
+		
+		if !self.received {
+			if let Some(key) = self.keys.pop_front() {
+				self.received = true;
+				mem.set_word(self.base + Self::RECEIVER_DATA, key as word);
+				self.set_control(mem, Self::RECEIVER_CONTROL, true);
+				
+				if self.control(mem, Self::RECEIVER_CONTROL) & Self::INTERRUPT_ENABLE != 0 {
+					lines |= 1 << Self::KEYBOARD_LINE;
+				}
+			}
+		}
+		
+		lines
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::{load, store};
+	
+	type KD = KeyboardDisplay;
+	
+	#[test]
+	fn keyboard() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(KD::new(0xFFFF_0000)));
+		
+		let ready = |mem: &Memory| mem.get_word(0xFFFF_0000).unwrap() & KD::READY != 0;
+		assert!(!ready(&mem));
+		
+		mem.device_mut::<KD>().unwrap().push_str("hi");
+		assert_eq!(mem.tick_devices(), 0, "interrupts are off");
+		assert!(ready(&mem));
+		assert_eq!(mem.get_word(0xFFFF_0004), Some(b'h' as word));
+		
+		// the next key waits until this one's read
+		mem.tick_devices();
+		assert_eq!(mem.get_word(0xFFFF_0004), Some(b'h' as word));
+		load(&mut mem, 0xFFFF_0004, 4);
+		assert!(!ready(&mem));
+		
+		// the ready bit can't be written to, but interrupt enable can
+		store(&mut mem, 0xFFFF_0000, 4, KD::READY | KD::INTERRUPT_ENABLE);
+		assert_eq!(mem.get_word(0xFFFF_0000), Some(KD::INTERRUPT_ENABLE));
+		
+		assert_eq!(mem.tick_devices(), 1 << KD::KEYBOARD_LINE);
+		assert_eq!(mem.get_word(0xFFFF_0004), Some(b'i' as word));
+	}
+	
+	#[test]
+	fn display() {
+		let mut mem = Memory::default();
+		let mut kd = KD::new(0x7F00);
+		kd.delay = 2;
+		mem.attach(Box::new(kd));
+		
+		let ready = |mem: &Memory| mem.get_word(0x7F08).unwrap() & KD::READY != 0;
+		assert!(ready(&mem));
+		
+		store(&mut mem, 0x7F08, 4, KD::INTERRUPT_ENABLE);
+		store(&mut mem, 0x7F0C, 4, b'A' as word);
+		assert!(!ready(&mem));
+		
+		// not ready, so this one's dropped
+		store(&mut mem, 0x7F0C, 1, word::from(b'B'));
+		
+		assert_eq!(mem.tick_devices(), 0);
+		assert_eq!(mem.tick_devices(), 0);
+		assert_eq!(mem.tick_devices(), 1 << KD::DISPLAY_LINE);
+		assert!(ready(&mem));
+		assert_eq!(mem.device::<KD>().unwrap().output, "A");
+	}
+}
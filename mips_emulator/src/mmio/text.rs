@@ -0,0 +1,214 @@
+use std::ops::RangeInclusive;
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// One character on a [`TextDisplay`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+	pub ch: char,
+	
+	/// Colours, as indices into [`TextDisplay::PALETTE`].
+	pub fg: u8,
+	pub bg: u8,
+}
+impl Cell {
+	/// Splits up a character byte and an attribute byte. The attribute's low
+	/// nibble is the foreground colour, and the high one's the background.
+	/// 
+	/// An attribute of 0 would be black on black, which is never what anyone
+	/// wants, so it's shown as light grey on black instead. That way writing
+	/// just the characters is enough to see something.
+	pub fn new(ch: u8, attr: u8) -> Self {
+		let attr = if attr == 0 { TextDisplay::DEFAULT_ATTRIBUTE } else { attr };
+		Cell { ch: CP437[ch as usize], fg: attr & 0xF, bg: attr >> 4 }
+	}
+}
+
+/// A text-mode display, kinda like a PC's. It's a grid of character cells,
+/// each one being two bytes in memory: the character, then its colours.
+/// 
+/// The cells live somewhere else in memory, row by row. The device's own
+/// registers say where:
+/// 
+/// | offset | register | what it does                                      |
+/// |--------|----------|---------------------------------------------------|
+/// | `0x00` | buffer   | address of the top-left cell                      |
+/// | `0x04` | columns  | read-only                                         |
+/// | `0x08` | rows     | read-only                                         |
+/// | `0x0C` | cursor   | column in the low half, row in the high half      |
+/// | `0x10` | control  | bit 0: show the cursor                            |
+/// 
+/// Characters use code page 437, so the box drawing characters are there for
+/// drawing menus and such.
+#[derive(Clone, Debug)]
+pub struct TextDisplay {
+	base: word,
+	
+	pub buffer: word,
+	pub columns: usize,
+	pub rows: usize,
+	
+	/// Which column and row the cursor's on.
+	pub cursor: (u16, u16),
+	pub show_cursor: bool,
+}
+
+impl TextDisplay {
+	pub const BUFFER: word = 0x00;
+	pub const COLUMNS: word = 0x04;
+	pub const ROWS: word = 0x08;
+	pub const CURSOR: word = 0x0C;
+	pub const CONTROL: word = 0x10;
+	
+	pub const SHOW_CURSOR: word = 1 << 0;
+	
+	/// Light grey on black.
+	pub const DEFAULT_ATTRIBUTE: u8 = 0x07;
+	
+	/// The 16 CGA colours, as `0x00RRGGBB`.
+	pub const PALETTE: [u32; 16] = [
+		0x00_0000, 0x00_00AA, 0x00_AA00, 0x00_AAAA,
+		0xAA_0000, 0xAA_00AA, 0xAA_5500, 0xAA_AAAA,
+		0x55_5555, 0x55_55FF, 0x55_FF55, 0x55_FFFF,
+		0xFF_5555, 0xFF_55FF, 0xFF_FF55, 0xFF_FFFF,
+	];
+	
+	/// Makes one with its registers at `base`, showing `columns` × `rows`
+	/// cells starting at `buffer`.
+	pub fn new(base: word, buffer: word, columns: usize, rows: usize) -> Self {
+		TextDisplay {
+			base,
+			buffer,
+			columns,
+			rows,
+			cursor: (0, 0),
+			show_cursor: true,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	/// How many bytes of memory the cells take up.
+	pub fn buffer_len(&self) -> usize { self.columns * self.rows * 2 }
+	
+	/// Reads every cell out of memory, row by row.
+	pub fn cells(&self, mem: &Memory) -> Vec<Cell> {
+		mem.copy_to_vec(self.buffer, self.buffer_len())
+			.chunks_exact(2)
+			.map(|c| Cell::new(c[0], c[1]))
+			.collect()
+	}
+	
+	/// Everything on screen as plain text, with trailing spaces trimmed off
+	/// each row. Empty cells count as spaces.
+	pub fn text(&self, mem: &Memory) -> String {
+		let cells = self.cells(mem);
+		let mut s = String::new();
+		for row in cells.chunks(self.columns.max(1)) {
+			let line: String = row.iter()
+				.map(|c| if c.ch == '\0' { ' ' } else { c.ch })
+				.collect();
+			s.push_str(line.trim_end());
+			s.push('\n');
+		}
+		s
+	}
+	
+	fn write_registers(&self, mem: &mut Memory) {
+		let (col, row) = self.cursor;
+		let regs = [
+			(Self::BUFFER, self.buffer),
+			(Self::COLUMNS, self.columns as word),
+			(Self::ROWS, self.rows as word),
+			(Self::CURSOR, (row as word) << 16 | col as word),
+			(Self::CONTROL, if self.show_cursor { Self::SHOW_CURSOR } else { 0 }),
+		];
+		for (reg, val) in regs {
+			mem.set_word(self.base + reg, val);
+		}
+	}
+}
+
+impl Device for TextDisplay {
+	fn name(&self) -> &'static str { "Text Display" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + 0x1F }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		self.cursor = (0, 0);
+		self.show_cursor = true;
+		self.write_registers(mem);
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		let reg = addr & !3;
+		let val = mem.get_word(reg).unwrap_or(0);
+		
+		match reg.wrapping_sub(self.base) {
+			Self::BUFFER => self.buffer = val,
+			Self::CURSOR => self.cursor = (val as u16, (val >> 16) as u16),
+			Self::CONTROL => self.show_cursor = val & Self::SHOW_CURSOR != 0,
+			_ => (),
+		}
+		
+		self.write_registers(mem);
+	}
+}
+
+/// Code page 437, the original IBM PC character set. The control characters
+/// get their glyphs too, since there's nothing else to show for them.
+const CP437: [char; 256] = [
+	'\0','☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+	'►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+	' ', '!', '"', '#', '$', '%', '&', '\'','(', ')', '*', '+', ',', '-', '.', '/',
+	'0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+	'@', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+	'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '[', '\\',']', '^', '_',
+	'`', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+	'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '{', '|', '}', '~', '⌂',
+	'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+	'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+	'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+	'░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+	'└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+	'╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+	'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+	'≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::store;
+	
+	#[test]
+	fn cells_and_cursor() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(TextDisplay::new(0x7F40, 0x1000, 4, 2)));
+		
+		assert_eq!(mem.get_word(0x7F44), Some(4));
+		assert_eq!(mem.get_word(0x7F48), Some(2));
+		
+		mem.set_slice(0x1000, b"H\x1Fi\x00");
+		mem.set_slice(0x1008, &[0xC9, 0x4E, 0xCD, 0x4E, 0, 0, b'!', 0]);
+		
+		let td = mem.device::<TextDisplay>().unwrap();
+		let cells = td.cells(&mem);
+		assert_eq!(cells[0], Cell { ch: 'H', fg: 0xF, bg: 0x1 });
+		assert_eq!(cells[1], Cell { ch: 'i', fg: 0x7, bg: 0x0 }, "attribute 0 shows up anyway");
+		assert_eq!(cells[4], Cell { ch: '╔', fg: 0xE, bg: 0x4 });
+		assert_eq!(td.text(&mem), "Hi\n╔═ !\n");
+		
+		// moving the cursor to column 3, row 1
+		store(&mut mem, 0x7F4C, 4, 0x0001_0003);
+		store(&mut mem, 0x7F50, 4, 0);
+		let td = mem.device::<TextDisplay>().unwrap();
+		assert_eq!(td.cursor, (3, 1));
+		assert!(!td.show_cursor);
+		
+		// the size can't be changed by the CPU
+		store(&mut mem, 0x7F44, 4, 80);
+		assert_eq!(mem.get_word(0x7F44), Some(4));
+	}
+}
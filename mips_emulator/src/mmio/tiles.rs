@@ -0,0 +1,435 @@
+use std::ops::RangeInclusive;
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// One hardware sprite, as it's laid out in the sprite table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sprite {
+	/// Where its top-left corner goes on screen. It can hang off any edge.
+	pub x: i16,
+	pub y: i16,
+	pub tile: u16,
+	pub flags: u16,
+}
+impl Sprite {
+	pub const VISIBLE: u16 = 1 << 0;
+	pub const FLIP_X: u16 = 1 << 1;
+	pub const FLIP_Y: u16 = 1 << 2;
+	
+	/// Drawn behind the background, so it only shows through where the
+	/// background's pixels are 0.
+	pub const BEHIND: u16 = 1 << 3;
+	
+	/// How many bytes each one takes up in the table.
+	pub const SIZE: usize = 8;
+	
+	pub fn from_bytes(b: &[u8]) -> Self {
+		let half = |i: usize| u16::from_le_bytes([b[i], b[i + 1]]);
+		Sprite { x: half(0) as i16, y: half(2) as i16, tile: half(4), flags: half(6) }
+	}
+	
+	pub fn to_bytes(self) -> [u8; Self::SIZE] {
+		let mut b = [0; Self::SIZE];
+		b[0..2].copy_from_slice(&self.x.to_le_bytes());
+		b[2..4].copy_from_slice(&self.y.to_le_bytes());
+		b[4..6].copy_from_slice(&self.tile.to_le_bytes());
+		b[6..8].copy_from_slice(&self.flags.to_le_bytes());
+		b
+	}
+}
+
+/// A graphics chip like the ones in old consoles. Instead of a framebuffer,
+/// there's a scrolling background made of 8×8 tiles, with sprites on top.
+/// At the end of every frame, it's all put together into one picture.
+/// 
+/// Everything it draws from lives somewhere else in memory:
+/// 
+/// - **Tiles**: 64 bytes each, one per pixel, row by row. Each byte picks
+///   a colour from the palette.
+/// - **Map**: a halfword per tile, row by row. Bits 0-9 are which tile, bit
+///   10 flips it sideways, and bit 11 flips it upside down.
+/// - **Palette**: 256 `0x00RRGGBB` words. Colour 0 is the backdrop, and it's
+///   see-through in sprites.
+/// - **Sprites**: 64 of them, as laid out by [`Sprite`]. Lower numbered ones
+///   are drawn on top.
+/// 
+/// | offset | register | what it does                                          |
+/// |--------|----------|-------------------------------------------------------|
+/// | `0x00` | status   | bit 0: vblank (read clears it), bit 1: interrupts     |
+/// | `0x04` | control  | bit 0: show background, bit 1: show sprites           |
+/// | `0x08` | tiles    | address of tile 0                                     |
+/// | `0x0C` | map      | address of the top-left map entry                     |
+/// | `0x10` | palette  | address of the palette                                |
+/// | `0x14` | sprites  | address of sprite 0                                   |
+/// | `0x18` | scroll x | how far the background's moved left, in pixels        |
+/// | `0x1C` | scroll y | ...and up                                             |
+/// | `0x20` | map size | columns in the low half, rows in the high half        |
+/// | `0x24` | width    | of the screen in pixels, read-only                    |
+/// | `0x28` | height   | ...read-only too                                      |
+/// | `0x2C` | frame    | how many frames have gone by, read-only               |
+/// 
+/// The background wraps around at the edges of the map. Frames last
+/// [`TileGraphics::frame_length`] instructions, and with interrupts on,
+/// line 2 gets raised at the end of each one, same as the bitmap display.
+#[derive(Clone, Debug)]
+pub struct TileGraphics {
+	base: word,
+	
+	pub tiles: word,
+	pub map: word,
+	pub palette: word,
+	pub sprites: word,
+	
+	pub scroll_x: word,
+	pub scroll_y: word,
+	
+	/// How big the map is, in tiles.
+	pub map_columns: u16,
+	pub map_rows: u16,
+	
+	pub show_background: bool,
+	pub show_sprites: bool,
+	pub interrupts: bool,
+	
+	pub width: usize,
+	pub height: usize,
+	
+	/// How many instructions each frame lasts.
+	pub frame_length: u32,
+	
+	elapsed: u32,
+	frame: word,
+	vblank: bool,
+	
+	/// What got put together at the end of the last frame.
+	screen: Vec<u32>,
+}
+
+impl TileGraphics {
+	pub const STATUS: word = 0x00;
+	pub const CONTROL: word = 0x04;
+	pub const TILES: word = 0x08;
+	pub const MAP: word = 0x0C;
+	pub const PALETTE: word = 0x10;
+	pub const SPRITES: word = 0x14;
+	pub const SCROLL_X: word = 0x18;
+	pub const SCROLL_Y: word = 0x1C;
+	pub const MAP_SIZE: word = 0x20;
+	pub const WIDTH: word = 0x24;
+	pub const HEIGHT: word = 0x28;
+	pub const FRAME: word = 0x2C;
+	
+	pub const VBLANK: word = 1 << 0;
+	pub const INTERRUPT_ENABLE: word = 1 << 1;
+	
+	pub const SHOW_BACKGROUND: word = 1 << 0;
+	pub const SHOW_SPRITES: word = 1 << 1;
+	
+	pub const MAP_FLIP_X: u16 = 1 << 10;
+	pub const MAP_FLIP_Y: u16 = 1 << 11;
+	
+	/// Interrupt line raised when a frame ends.
+	pub const VBLANK_LINE: u8 = 2;
+	
+	pub const TILE_SIZE: usize = 8;
+	pub const TILE_BYTES: usize = Self::TILE_SIZE * Self::TILE_SIZE;
+	pub const TILE_COUNT: usize = 1024;
+	pub const SPRITE_COUNT: usize = 64;
+	pub const PALETTE_SIZE: usize = 256;
+	
+	/// Makes one with its registers at `base`, drawing a `width` × `height`
+	/// screen from a 32×32 tile map.
+	pub fn new(base: word, width: usize, height: usize) -> Self {
+		TileGraphics {
+			base,
+			tiles: 0,
+			map: 0,
+			palette: 0,
+			sprites: 0,
+			scroll_x: 0,
+			scroll_y: 0,
+			map_columns: 32,
+			map_rows: 32,
+			show_background: true,
+			show_sprites: true,
+			interrupts: false,
+			width,
+			height,
+			frame_length: 1000,
+			elapsed: 0,
+			frame: 0,
+			vblank: false,
+			screen: vec![0; width * height],
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	/// How many frames have gone by since it was reset.
+	pub fn frame(&self) -> word { self.frame }
+	
+	/// What was on screen at the end of the last frame, as `0x00RRGGBB`
+	/// pixels, row by row.
+	pub fn screen(&self) -> &[u32] { &self.screen }
+	
+	/// Reads every sprite out of the sprite table.
+	pub fn sprite_table(&self, mem: &Memory) -> Vec<Sprite> {
+		mem.copy_to_vec(self.sprites, Self::SPRITE_COUNT * Sprite::SIZE)
+			.chunks_exact(Sprite::SIZE)
+			.map(Sprite::from_bytes)
+			.collect()
+	}
+	
+	/// Puts together what's on screen right now, as `0x00RRGGBB` pixels,
+	/// row by row. Normally this happens at the end of every frame, but it
+	/// works any time.
+	pub fn compose(&self, mem: &Memory) -> Vec<u32> {
+		let palette: Vec<u32> = mem.copy_to_vec(self.palette, Self::PALETTE_SIZE * 4)
+			.chunks_exact(4)
+			.map(|c| u32::from_le_bytes(c.try_into().unwrap()) & 0x00FF_FFFF)
+			.collect();
+		let tiles = mem.copy_to_vec(self.tiles, Self::TILE_COUNT * Self::TILE_BYTES);
+		
+		// which colour from a tile, with its flips taken into account
+		let texel = |tile: usize, x: usize, y: usize, flip_x: bool, flip_y: bool| {
+			let x = if flip_x { Self::TILE_SIZE - 1 - x } else { x };
+			let y = if flip_y { Self::TILE_SIZE - 1 - y } else { y };
+			tiles[(tile % Self::TILE_COUNT) * Self::TILE_BYTES + y * Self::TILE_SIZE + x]
+		};
+		
+		// colour indices first, since sprites behind the background need
+		// to know where it's see-through
+		let mut background = vec![0u8; self.width * self.height];
+		if self.show_background {
+			let (columns, rows) = (self.map_columns.max(1) as usize, self.map_rows.max(1) as usize);
+			let map: Vec<u16> = mem.copy_to_vec(self.map, columns * rows * 2)
+				.chunks_exact(2)
+				.map(|c| u16::from_le_bytes([c[0], c[1]]))
+				.collect();
+			let (map_width, map_height) = (columns * Self::TILE_SIZE, rows * Self::TILE_SIZE);
+			
+			// scrolling can be negative too
+			let scroll_x = (self.scroll_x as i32 as i64).rem_euclid(map_width as i64) as usize;
+			let scroll_y = (self.scroll_y as i32 as i64).rem_euclid(map_height as i64) as usize;
+			
+			for (i, px) in background.iter_mut().enumerate() {
+				let x = (i % self.width + scroll_x) % map_width;
+				let y = (i / self.width + scroll_y) % map_height;
+				let entry = map[(y / Self::TILE_SIZE) * columns + x / Self::TILE_SIZE];
+				*px = texel(
+					(entry & 0x3FF) as usize,
+					x % Self::TILE_SIZE, y % Self::TILE_SIZE,
+					entry & Self::MAP_FLIP_X != 0, entry & Self::MAP_FLIP_Y != 0,
+				);
+			}
+		}
+		
+		let mut indices = background.clone();
+		if self.show_sprites {
+			// backwards, so the lower numbered ones end up on top
+			for sprite in self.sprite_table(mem).into_iter().rev() {
+				if sprite.flags & Sprite::VISIBLE == 0 { continue; }
+				
+				for (ty, tx) in (0..Self::TILE_SIZE).flat_map(|y| (0..Self::TILE_SIZE).map(move |x| (y, x))) {
+					let (x, y) = (sprite.x as isize + tx as isize, sprite.y as isize + ty as isize);
+					if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize { continue; }
+					let i = y as usize * self.width + x as usize;
+					
+					let colour = texel(
+						sprite.tile as usize, tx, ty,
+						sprite.flags & Sprite::FLIP_X != 0, sprite.flags & Sprite::FLIP_Y != 0,
+					);
+					if colour == 0 { continue; }
+					if sprite.flags & Sprite::BEHIND != 0 && background[i] != 0 { continue; }
+					indices[i] = colour;
+				}
+			}
+		}
+		
+		indices.into_iter().map(|i| palette[i as usize]).collect()
+	}
+	
+	pub fn status(&self) -> word {
+		let mut status = 0;
+		if self.vblank { status |= Self::VBLANK; }
+		if self.interrupts { status |= Self::INTERRUPT_ENABLE; }
+		status
+	}
+	
+	fn write_registers(&self, mem: &mut Memory) {
+		let control = if self.show_background { Self::SHOW_BACKGROUND } else { 0 }
+			| if self.show_sprites { Self::SHOW_SPRITES } else { 0 };
+		let regs = [
+			(Self::STATUS, self.status()),
+			(Self::CONTROL, control),
+			(Self::TILES, self.tiles),
+			(Self::MAP, self.map),
+			(Self::PALETTE, self.palette),
+			(Self::SPRITES, self.sprites),
+			(Self::SCROLL_X, self.scroll_x),
+			(Self::SCROLL_Y, self.scroll_y),
+			(Self::MAP_SIZE, (self.map_rows as word) << 16 | self.map_columns as word),
+			(Self::WIDTH, self.width as word),
+			(Self::HEIGHT, self.height as word),
+			(Self::FRAME, self.frame),
+		];
+		for (reg, val) in regs {
+			mem.set_word(self.base + reg, val);
+		}
+	}
+}
+
+impl Device for TileGraphics {
+	fn name(&self) -> &'static str { "Tile Graphics" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + 0x2F }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		*self = TileGraphics {
+			map_columns: self.map_columns,
+			map_rows: self.map_rows,
+			frame_length: self.frame_length,
+			..TileGraphics::new(self.base, self.width, self.height)
+		};
+		self.write_registers(mem);
+	}
+	
+	fn on_load(&mut self, mem: &mut Memory, addr: word, size: word) {
+		let status = self.base + Self::STATUS;
+		if addr <= status + 3 && status < addr.saturating_add(size) {
+			self.vblank = false;
+			self.write_registers(mem);
+		}
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		let reg = addr & !3;
+		let val = mem.get_word(reg).unwrap_or(0);
+		
+		match reg.wrapping_sub(self.base) {
+			// vblank can only be cleared by reading it
+			Self::STATUS => self.interrupts = val & Self::INTERRUPT_ENABLE != 0,
+			Self::CONTROL => {
+				self.show_background = val & Self::SHOW_BACKGROUND != 0;
+				self.show_sprites = val & Self::SHOW_SPRITES != 0;
+			},
+			Self::TILES => self.tiles = val,
+			Self::MAP => self.map = val,
+			Self::PALETTE => self.palette = val,
+			Self::SPRITES => self.sprites = val,
+			Self::SCROLL_X => self.scroll_x = val,
+			Self::SCROLL_Y => self.scroll_y = val,
+			Self::MAP_SIZE => {
+				self.map_columns = (val as u16).max(1);
+				self.map_rows = ((val >> 16) as u16).max(1);
+			},
+			_ => (),
+		}
+		
+		self.write_registers(mem);
+	}
+	
+	fn tick(&mut self, mem: &mut Memory) -> u8 {
+		self.elapsed += 1;
+		if self.elapsed < self.frame_length { return 0; }
+		
+		self.elapsed = 0;
+		self.frame = self.frame.wrapping_add(1);
+		self.screen = self.compose(mem);
+		
+		self.vblank = true;
+		self.write_registers(mem);
+		
+		if self.interrupts { 1 << Self::VBLANK_LINE } else { 0 }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::store;
+	
+	type TG = TileGraphics;
+	
+	/// Turns a picture made of digits into what it should compose to, with
+	/// each digit being a palette index.
+	fn picture(rows: &[&str], palette: &[u32]) -> Vec<u32> {
+		rows.iter()
+			.flat_map(|r| r.bytes())
+			.map(|b| palette[(b - b'0') as usize])
+			.collect()
+	}
+	
+	/// A tile that's all `colour`, with a dot of `dot` in its top-left.
+	fn tile(colour: u8, dot: u8) -> [u8; TG::TILE_BYTES] {
+		let mut t = [colour; TG::TILE_BYTES];
+		t[0] = dot;
+		t
+	}
+	
+	#[test]
+	fn frames() {
+		const TILES: word = 0x1000;
+		const MAP: word = 0x2000;
+		const PALETTE: word = 0x3000;
+		const SPRITES: word = 0x4000;
+		let palette = [0x00_0000, 0xFF_0000, 0x00_FF00, 0x00_00FF, 0xFF_FFFF];
+		
+		let mut mem = Memory::default();
+		let mut tg = TG::new(0x7FE0, 12, 4);
+		tg.frame_length = 10;
+		mem.attach(Box::new(tg));
+		
+		for (reg, val) in [(TG::TILES, TILES), (TG::MAP, MAP), (TG::PALETTE, PALETTE), (TG::SPRITES, SPRITES)] {
+			store(&mut mem, 0x7FE0 + reg, 4, val);
+		}
+		store(&mut mem, 0x7FE0 + TG::MAP_SIZE, 4, 2 << 16 | 2);
+		store(&mut mem, 0x7FE0 + TG::STATUS, 4, TG::INTERRUPT_ENABLE);
+		
+		mem.set_slice(PALETTE, &palette.map(u32::to_le_bytes).concat());
+		mem.set_slice(TILES, &[tile(0, 0), tile(1, 2), tile(3, 4)].concat());
+		
+		// a 2×2 map of tile 1, with the bottom right one flipped sideways
+		let map = [1u16, 1, 1, 1 | TG::MAP_FLIP_X];
+		mem.set_slice(MAP, &map.map(u16::to_le_bytes).concat());
+		
+		// sprite 0 goes over sprite 1, and sprite 2's behind the background
+		let sprites = [
+			Sprite { x: 9, y: -6, tile: 2, flags: Sprite::VISIBLE },
+			Sprite { x: 10, y: -5, tile: 2, flags: Sprite::VISIBLE | Sprite::FLIP_Y },
+			Sprite { x: -7, y: 2, tile: 2, flags: Sprite::VISIBLE | Sprite::BEHIND },
+			Sprite { x: 0, y: 0, tile: 2, flags: 0 },
+		];
+		mem.set_slice(SPRITES, &sprites.map(Sprite::to_bytes).concat());
+		
+		// scrolled so the bottom row's the top of the second row of tiles,
+		// and the right edge wraps back around to the first column. on a
+		// 16 pixel tall map, -11 is the same as 5
+		store(&mut mem, 0x7FE0 + TG::SCROLL_X, 4, 5);
+		store(&mut mem, 0x7FE0 + TG::SCROLL_Y, 4, -11i32 as word);
+		
+		let lines: Vec<u8> = (0..10).map(|_| mem.tick_devices()).collect();
+		assert_eq!(lines[9], 1 << TG::VBLANK_LINE);
+		assert_eq!(mem.get_word(0x7FE0 + TG::FRAME), Some(1));
+		
+		let tg = mem.device::<TG>().unwrap();
+		assert_eq!(tg.screen(), picture(&[
+			"111111111333",
+			"111111111333",
+			"111111111143",
+			"111111111122",
+		], &palette));
+		assert_eq!(tg.compose(&mem), tg.screen(), "nothing's changed since");
+		
+		// without the background, it's the backdrop and sprite 2 shows
+		store(&mut mem, 0x7FE0 + TG::CONTROL, 4, TG::SHOW_SPRITES);
+		let tg = mem.device::<TG>().unwrap();
+		assert_eq!(tg.compose(&mem), picture(&[
+			"000000000333",
+			"000000000333",
+			"300000000043",
+			"300000000000",
+		], &palette));
+	}
+}
@@ -0,0 +1,241 @@
+use std::ops::RangeInclusive;
+use std::time::{Duration, SystemTime};
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// A programmable timer, plus a couple of clocks.
+/// 
+/// The timer counts instructions, not real time, so a program always gets
+/// interrupted at the exact same spot. Handy for tests, and for teaching
+/// preemptive scheduling without things changing from run to run.
+/// 
+/// | offset | register | what it does                                          |
+/// |--------|----------|-------------------------------------------------------|
+/// | `0x00` | control  | see below                                             |
+/// | `0x04` | counter  | goes up by 1 every instruction while enabled          |
+/// | `0x08` | compare  | when the counter gets here, the timer goes off        |
+/// | `0x0C` | reload   | what the counter goes back to, with auto-reload on    |
+/// | `0x10` | cycles   | instructions run since reset, low word. read-only     |
+/// | `0x14` | cycles   | ...and the high word                                  |
+/// | `0x18` | clock    | seconds since 1970, from the host. read-only          |
+/// | `0x1C` | clock    | ...and the milliseconds                               |
+/// 
+/// Control has bit 0 to enable the timer, bit 1 to reload it when it goes off
+/// (otherwise it turns itself off), bit 2 to raise an interrupt on line 5
+/// (`Cause` bit 15, like a real MIPS's timer) when it goes off, and bit 3 is
+/// set when it's gone off. Write a 0 to bit 3 to clear it.
+/// 
+/// The counter and clocks aren't written to memory until they're loaded, so
+/// they'll look stale in a memory monitor.
+#[derive(Clone, Debug)]
+pub struct Timer {
+	base: word,
+	
+	pub enabled: bool,
+	pub auto_reload: bool,
+	pub interrupts: bool,
+	
+	/// Set when the timer went off, until the program clears it.
+	pub expired: bool,
+	
+	pub counter: word,
+	pub compare: word,
+	pub reload: word,
+	
+	/// Instructions run since reset.
+	pub cycles: u64,
+	
+	/// Where the clock registers get the time from. It's the system clock
+	/// by default, but tests might want to stop time.
+	pub clock: fn() -> Duration,
+}
+
+fn system_clock() -> Duration {
+	SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default()
+}
+
+impl Timer {
+	pub const CONTROL: word = 0x00;
+	pub const COUNTER: word = 0x04;
+	pub const COMPARE: word = 0x08;
+	pub const RELOAD: word = 0x0C;
+	pub const CYCLES_LO: word = 0x10;
+	pub const CYCLES_HI: word = 0x14;
+	pub const CLOCK_SECS: word = 0x18;
+	pub const CLOCK_MILLIS: word = 0x1C;
+	
+	pub const ENABLE: word = 1 << 0;
+	pub const AUTO_RELOAD: word = 1 << 1;
+	pub const INTERRUPT_ENABLE: word = 1 << 2;
+	pub const EXPIRED: word = 1 << 3;
+	
+	/// Interrupt line raised when the timer goes off.
+	pub const TIMER_LINE: u8 = 5;
+	
+	pub fn new(base: word) -> Self {
+		Timer {
+			base,
+			enabled: false,
+			auto_reload: false,
+			interrupts: false,
+			expired: false,
+			counter: 0,
+			compare: 0,
+			reload: 0,
+			cycles: 0,
+			clock: system_clock,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	pub fn control(&self) -> word {
+		[
+			(self.enabled, Self::ENABLE),
+			(self.auto_reload, Self::AUTO_RELOAD),
+			(self.interrupts, Self::INTERRUPT_ENABLE),
+			(self.expired, Self::EXPIRED),
+		].into_iter()
+			.filter(|&(on, _)| on)
+			.fold(0, |c, (_, bit)| c | bit)
+	}
+	
+	fn write_registers(&self, mem: &mut Memory) {
+		let now = (self.clock)();
+		let regs = [
+			(Self::CONTROL, self.control()),
+			(Self::COUNTER, self.counter),
+			(Self::COMPARE, self.compare),
+			(Self::RELOAD, self.reload),
+			(Self::CYCLES_LO, self.cycles as word),
+			(Self::CYCLES_HI, (self.cycles >> 32) as word),
+			(Self::CLOCK_SECS, now.as_secs() as word),
+			(Self::CLOCK_MILLIS, now.subsec_millis()),
+		];
+		for (reg, val) in regs {
+			mem.set_word(self.base + reg, val);
+		}
+	}
+}
+
+impl Device for Timer {
+	fn name(&self) -> &'static str { "Timer" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + 0x1F }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		*self = Timer { clock: self.clock, ..Timer::new(self.base) };
+		self.write_registers(mem);
+	}
+	
+	fn before_load(&mut self, mem: &mut Memory, _addr: word, _size: word) {
+		self.write_registers(mem);
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, _size: word) {
+		let reg = addr & !3;
+		let val = mem.get_word(reg).unwrap_or(0);
+		
+		match reg.wrapping_sub(self.base) {
+			Self::CONTROL => {
+				self.enabled = val & Self::ENABLE != 0;
+				self.auto_reload = val & Self::AUTO_RELOAD != 0;
+				self.interrupts = val & Self::INTERRUPT_ENABLE != 0;
+				// can be cleared, but not set
+				self.expired &= val & Self::EXPIRED != 0;
+			},
+			Self::COUNTER => self.counter = val,
+			Self::COMPARE => self.compare = val,
+			Self::RELOAD => self.reload = val,
+			_ => (),
+		}
+		
+		self.write_registers(mem);
+	}
+	
+	fn tick(&mut self, _mem: &mut Memory) -> u8 {
+		self.cycles += 1;
+		
+		if !self.enabled { return 0; }
+		
+		self.counter = self.counter.wrapping_add(1);
+		if self.counter != self.compare { return 0; }
+		
+		self.expired = true;
+		if self.auto_reload {
+			self.counter = self.reload;
+		} else {
+			self.enabled = false;
+		}
+		
+		if self.interrupts { 1 << Self::TIMER_LINE } else { 0 }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::{load, store};
+	
+	#[test]
+	fn one_shot() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(Timer::new(0x7F60)));
+		
+		store(&mut mem, 0x7F68, 4, 3);
+		store(&mut mem, 0x7F60, 4, Timer::ENABLE);
+		
+		let lines: Vec<u8> = (0..5).map(|_| mem.tick_devices()).collect();
+		assert_eq!(lines, [0; 5], "interrupts are off");
+		
+		// it went off, and turned itself off
+		assert_eq!(load(&mut mem, 0x7F60, 4), Timer::EXPIRED);
+		assert_eq!(load(&mut mem, 0x7F64, 4), 3);
+		
+		store(&mut mem, 0x7F60, 4, Timer::EXPIRED);
+		assert_eq!(load(&mut mem, 0x7F60, 4), Timer::EXPIRED, "can't set it, only clear it");
+		store(&mut mem, 0x7F60, 4, 0);
+		assert_eq!(load(&mut mem, 0x7F60, 4), 0);
+	}
+	
+	#[test]
+	fn auto_reload() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(Timer::new(0xFFFF_0060)));
+		
+		store(&mut mem, 0xFFFF_0068, 4, 4);
+		store(&mut mem, 0xFFFF_006C, 4, 1);
+		store(&mut mem, 0xFFFF_0060, 4, Timer::ENABLE | Timer::AUTO_RELOAD | Timer::INTERRUPT_ENABLE);
+		
+		// 0 → 4, then 1 → 4 every 3 instructions after that
+		let fired: Vec<usize> = (1..=10)
+			.filter(|_| mem.tick_devices() == 1 << Timer::TIMER_LINE)
+			.collect();
+		assert_eq!(fired, [4, 7, 10]);
+		
+		assert_eq!(load(&mut mem, 0xFFFF_0064, 4), 1);
+		assert_eq!(load(&mut mem, 0xFFFF_0070, 4), 10, "cycles count whether it's enabled or not");
+	}
+	
+	#[test]
+	fn clocks() {
+		let mut mem = Memory::default();
+		let mut timer = Timer::new(0x7F60);
+		timer.clock = || Duration::from_millis(1_700_000_000_123);
+		mem.attach(Box::new(timer));
+		
+		// resetting doesn't go back to the system clock
+		mem.clear();
+		assert_eq!(load(&mut mem, 0x7F78, 4), 1_700_000_000);
+		assert_eq!(load(&mut mem, 0x7F7C, 4), 123);
+		
+		mem.device_mut::<Timer>().unwrap().cycles = 0xFFFF_FFFF;
+		mem.tick_devices();
+		assert_eq!((load(&mut mem, 0x7F70, 4), load(&mut mem, 0x7F74, 4)), (0, 1));
+		
+		// writing to them does nothing
+		store(&mut mem, 0x7F70, 4, 5);
+		assert_eq!(load(&mut mem, 0x7F70, 4), 0);
+	}
+}
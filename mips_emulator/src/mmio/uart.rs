@@ -0,0 +1,350 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+use super::Device;
+use crate::{word, mem::Memory};
+
+/// A serial port, laid out like the 16550 UART that's on most real MIPS
+/// boards (and every PC). Each register is one byte:
+/// 
+/// | offset | read                       | write                            |
+/// |--------|----------------------------|----------------------------------|
+/// | `0`    | receive buffer             | transmit holding                 |
+/// | `1`    | interrupt enable           | interrupt enable                 |
+/// | `2`    | interrupt identification   | FIFO control                     |
+/// | `3`    | line control               | line control                     |
+/// | `4`    | modem control              | modem control                    |
+/// | `5`    | line status                | -                                |
+/// | `6`    | modem status               | -                                |
+/// | `7`    | scratch                    | scratch                          |
+/// 
+/// With bit 7 of line control set, offsets 0 and 1 are the baud rate divisor
+/// instead. It's kept around so drivers can set it, but it doesn't do
+/// anything, and neither does the rest of line control. Everything's sent
+/// the moment it's written.
+/// 
+/// Interrupts go out on line 6, for received data (interrupt enable bit 0)
+/// and for the transmitter being empty again (bit 1). Loopback mode (bit 4
+/// of modem control) sends everything straight back to the receiver.
+#[derive(Clone, Debug)]
+pub struct Uart {
+	base: word,
+	
+	/// What the host sent, that hasn't made it into the receive FIFO yet.
+	incoming: VecDeque<u8>,
+	received: VecDeque<u8>,
+	
+	/// Everything the program sent. Whatever's on the other end of the
+	/// line should take it out of here.
+	pub output: Vec<u8>,
+	
+	interrupt_enable: u8,
+	line_control: u8,
+	modem_control: u8,
+	scratch: u8,
+	divisor: u16,
+	overrun: bool,
+	
+	/// The transmitter emptied, and the program hasn't heard about it.
+	transmitter_empty: bool,
+	
+	/// Interrupts that happened since the last tick.
+	raised: bool,
+}
+
+impl Uart {
+	pub const DATA: word = 0;
+	pub const INTERRUPT_ENABLE: word = 1;
+	pub const INTERRUPT_ID: word = 2;
+	pub const LINE_CONTROL: word = 3;
+	pub const MODEM_CONTROL: word = 4;
+	pub const LINE_STATUS: word = 5;
+	pub const MODEM_STATUS: word = 6;
+	pub const SCRATCH: word = 7;
+	
+	/// Interrupt enable bits.
+	pub const RECEIVED_DATA: u8 = 1 << 0;
+	pub const TRANSMITTER_EMPTY: u8 = 1 << 1;
+	
+	/// Line status bits.
+	pub const DATA_READY: u8 = 1 << 0;
+	pub const OVERRUN: u8 = 1 << 1;
+	pub const HOLDING_EMPTY: u8 = 1 << 5;
+	pub const TRANSMITTER_IDLE: u8 = 1 << 6;
+	
+	/// Line control bit that swaps in the divisor.
+	pub const DIVISOR_LATCH: u8 = 1 << 7;
+	
+	/// Modem control bit that loops the transmitter back to the receiver.
+	pub const LOOPBACK: u8 = 1 << 4;
+	
+	/// FIFO control bits.
+	pub const CLEAR_RECEIVER: u8 = 1 << 1;
+	pub const CLEAR_TRANSMITTER: u8 = 1 << 2;
+	
+	/// Interrupt identification values, with the "FIFOs on" bits set.
+	pub const NO_INTERRUPT: u8 = 0xC1;
+	pub const RECEIVED_DATA_ID: u8 = 0xC4;
+	pub const TRANSMITTER_EMPTY_ID: u8 = 0xC2;
+	
+	/// How many bytes fit in the receive FIFO.
+	pub const FIFO_SIZE: usize = 16;
+	
+	/// Interrupt line raised for received data, or an empty transmitter.
+	pub const UART_LINE: u8 = 6;
+	
+	pub fn new(base: word) -> Self {
+		Uart {
+			base,
+			incoming: VecDeque::new(),
+			received: VecDeque::new(),
+			output: vec![],
+			interrupt_enable: 0,
+			line_control: 0x03, // 8N1, what everything uses anyway
+			modem_control: 0,
+			scratch: 0,
+			divisor: 1,
+			overrun: false,
+			transmitter_empty: false,
+			raised: false,
+		}
+	}
+	
+	pub fn base(&self) -> word { self.base }
+	
+	/// Sends bytes to the program. They go into the receive FIFO as there's
+	/// room for them.
+	pub fn send(&mut self, bytes: &[u8]) {
+		self.incoming.extend(bytes);
+	}
+	
+	/// How many bytes the program hasn't read yet, in the FIFO or not.
+	pub fn bytes_waiting(&self) -> usize {
+		self.incoming.len() + self.received.len()
+	}
+	
+	fn divisor_latch(&self) -> bool { self.line_control & Self::DIVISOR_LATCH != 0 }
+	
+	pub fn line_status(&self) -> u8 {
+		let mut status = Self::HOLDING_EMPTY | Self::TRANSMITTER_IDLE;
+		if !self.received.is_empty() { status |= Self::DATA_READY; }
+		if self.overrun { status |= Self::OVERRUN; }
+		status
+	}
+	
+	/// Which interrupt's pending, the most important one first.
+	pub fn interrupt_id(&self) -> u8 {
+		if self.interrupt_enable & Self::RECEIVED_DATA != 0 && !self.received.is_empty() {
+			Self::RECEIVED_DATA_ID
+		} else if self.interrupt_enable & Self::TRANSMITTER_EMPTY != 0 && self.transmitter_empty {
+			Self::TRANSMITTER_EMPTY_ID
+		} else {
+			Self::NO_INTERRUPT
+		}
+	}
+	
+	fn receive(&mut self, byte: u8) {
+		if self.received.len() < Self::FIFO_SIZE {
+			self.received.push_back(byte);
+			self.raised |= self.interrupt_enable & Self::RECEIVED_DATA != 0;
+		} else {
+			self.overrun = true;
+		}
+	}
+	
+	fn transmit(&mut self, byte: u8) {
+		if self.modem_control & Self::LOOPBACK != 0 {
+			self.receive(byte);
+		} else {
+			self.output.push(byte);
+		}
+		
+		self.transmitter_empty = true;
+		self.raised |= self.interrupt_enable & Self::TRANSMITTER_EMPTY != 0;
+	}
+	
+	fn register(&self, reg: word) -> u8 {
+		let [divisor_lo, divisor_hi] = self.divisor.to_le_bytes();
+		match reg {
+			Self::DATA if self.divisor_latch() => divisor_lo,
+			Self::DATA => self.received.front().copied().unwrap_or(0),
+			Self::INTERRUPT_ENABLE if self.divisor_latch() => divisor_hi,
+			Self::INTERRUPT_ENABLE => self.interrupt_enable,
+			Self::INTERRUPT_ID => self.interrupt_id(),
+			Self::LINE_CONTROL => self.line_control,
+			Self::MODEM_CONTROL => self.modem_control,
+			Self::LINE_STATUS => self.line_status(),
+			// clear to send, data set ready, and carrier detect
+			Self::MODEM_STATUS => 0xB0,
+			_ => self.scratch,
+		}
+	}
+	
+	fn write_registers(&self, mem: &mut Memory) {
+		for reg in Self::DATA..=Self::SCRATCH {
+			mem.set_byte(self.base + reg, self.register(reg));
+		}
+	}
+	
+	/// Which registers an access of `size` bytes at `addr` covers.
+	fn registers(&self, addr: word, size: word) -> impl Iterator<Item = word> + '_ {
+		(addr..addr.saturating_add(size))
+			.filter(|a| self.range().contains(a))
+			.map(|a| a - self.base)
+	}
+}
+
+impl Device for Uart {
+	fn name(&self) -> &'static str { "UART" }
+	
+	fn range(&self) -> RangeInclusive<word> { self.base..=self.base + Self::SCRATCH }
+	
+	fn reset(&mut self, mem: &mut Memory) {
+		*self = Uart::new(self.base);
+		self.write_registers(mem);
+	}
+	
+	fn before_load(&mut self, mem: &mut Memory, _addr: word, _size: word) {
+		self.write_registers(mem);
+	}
+	
+	fn on_load(&mut self, mem: &mut Memory, addr: word, size: word) {
+		for reg in self.registers(addr, size).collect::<Vec<_>>() {
+			match reg {
+				Self::DATA if !self.divisor_latch() => { self.received.pop_front(); },
+				// reading it is how the transmitter interrupt gets acknowledged
+				Self::INTERRUPT_ID if self.interrupt_id() == Self::TRANSMITTER_EMPTY_ID => {
+					self.transmitter_empty = false;
+				},
+				Self::LINE_STATUS => self.overrun = false,
+				_ => (),
+			}
+		}
+		self.write_registers(mem);
+	}
+	
+	fn on_store(&mut self, mem: &mut Memory, addr: word, size: word) {
+		for reg in self.registers(addr, size).collect::<Vec<_>>() {
+			let val = mem.get_byte(self.base + reg);
+			let [divisor_lo, divisor_hi] = self.divisor.to_le_bytes();
+			
+			match reg {
+				Self::DATA if self.divisor_latch() => {
+					self.divisor = u16::from_le_bytes([val, divisor_hi]);
+				},
+				Self::DATA => self.transmit(val),
+				Self::INTERRUPT_ENABLE if self.divisor_latch() => {
+					self.divisor = u16::from_le_bytes([divisor_lo, val]);
+				},
+				Self::INTERRUPT_ENABLE => {
+					let turned_on = val & !self.interrupt_enable;
+					self.interrupt_enable = val & 0x0F;
+					
+					// an interrupt that's already true goes off right away
+					let id = self.interrupt_id();
+					self.raised |= turned_on & Self::RECEIVED_DATA != 0 && id == Self::RECEIVED_DATA_ID;
+					if turned_on & Self::TRANSMITTER_EMPTY != 0 {
+						self.transmitter_empty = true;
+						self.raised = true;
+					}
+				},
+				Self::INTERRUPT_ID => {
+					if val & Self::CLEAR_RECEIVER != 0 { self.received.clear(); }
+					if val & Self::CLEAR_TRANSMITTER != 0 { self.transmitter_empty = true; }
+				},
+				Self::LINE_CONTROL => self.line_control = val,
+				Self::MODEM_CONTROL => self.modem_control = val & 0x1F,
+				Self::SCRATCH => self.scratch = val,
+				_ => (),
+			}
+		}
+		
+		self.write_registers(mem);
+	}
+	
+	fn tick(&mut self, mem: &mut Memory) -> u8 {
+		if !self.incoming.is_empty() {
+			while self.received.len() < Self::FIFO_SIZE {
+				let Some(byte) = self.incoming.pop_front() else { break };
+				self.receive(byte);
+			}
+			self.write_registers(mem);
+		}
+		
+		if std::mem::take(&mut self.raised) { 1 << Self::UART_LINE } else { 0 }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mmio::bus::{load, store};
+	
+	#[test]
+	fn send_and_receive() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(Uart::new(0x7FC0)));
+		
+		assert_eq!(load(&mut mem, 0x7FC5, 1) as u8, Uart::HOLDING_EMPTY | Uart::TRANSMITTER_IDLE);
+		
+		for &b in b"hi\n" { store(&mut mem, 0x7FC0, 1, word::from(b)); }
+		assert_eq!(mem.device::<Uart>().unwrap().output, b"hi\n");
+		assert_eq!(mem.tick_devices(), 0, "interrupts are off");
+		
+		// more than fits in the FIFO
+		mem.device_mut::<Uart>().unwrap().send(&[b'x'; 20]);
+		mem.tick_devices();
+		assert_eq!(load(&mut mem, 0x7FC5, 1) as u8 & Uart::DATA_READY, Uart::DATA_READY);
+		let mut got = 0;
+		while load(&mut mem, 0x7FC5, 1) as u8 & Uart::DATA_READY != 0 {
+			assert_eq!(load(&mut mem, 0x7FC0, 1) as u8, b'x');
+			got += 1;
+		}
+		assert_eq!(got, 16);
+		
+		mem.tick_devices();
+		assert_eq!(mem.device::<Uart>().unwrap().bytes_waiting(), 4, "the rest came in after");
+		
+		// the divisor's in the way while the latch bit's set
+		store(&mut mem, 0x7FC3, 1, word::from(Uart::DIVISOR_LATCH | 0x03));
+		store(&mut mem, 0x7FC0, 1, 12);
+		store(&mut mem, 0x7FC1, 1, 0);
+		assert_eq!(load(&mut mem, 0x7FC0, 1) as u8, 12);
+		store(&mut mem, 0x7FC3, 1, 0x03);
+		assert_eq!(load(&mut mem, 0x7FC0, 1) as u8, b'x');
+		assert_eq!(mem.device::<Uart>().unwrap().output, b"hi\n", "the divisor isn't data");
+	}
+	
+	#[test]
+	fn interrupts() {
+		let mut mem = Memory::default();
+		mem.attach(Box::new(Uart::new(0x7FC0)));
+		
+		// loopback, so everything sent comes right back
+		store(&mut mem, 0x7FC4, 1, word::from(Uart::LOOPBACK));
+		store(&mut mem, 0x7FC1, 1, word::from(Uart::RECEIVED_DATA));
+		assert_eq!(mem.tick_devices(), 0);
+		
+		store(&mut mem, 0x7FC0, 1, word::from(b'A'));
+		assert_eq!(mem.tick_devices(), 1 << Uart::UART_LINE);
+		assert_eq!(mem.tick_devices(), 0, "only once");
+		assert_eq!(load(&mut mem, 0x7FC2, 1) as u8, Uart::RECEIVED_DATA_ID);
+		assert_eq!(load(&mut mem, 0x7FC0, 1) as u8, b'A');
+		assert_eq!(load(&mut mem, 0x7FC2, 1) as u8, Uart::NO_INTERRUPT);
+		assert!(mem.device::<Uart>().unwrap().output.is_empty());
+		
+		// the transmitter's already empty, so it goes off right away
+		store(&mut mem, 0x7FC1, 1, word::from(Uart::RECEIVED_DATA | Uart::TRANSMITTER_EMPTY));
+		assert_eq!(mem.tick_devices(), 1 << Uart::UART_LINE);
+		assert_eq!(load(&mut mem, 0x7FC2, 1) as u8, Uart::TRANSMITTER_EMPTY_ID);
+		assert_eq!(load(&mut mem, 0x7FC2, 1) as u8, Uart::NO_INTERRUPT, "reading it acknowledges it");
+		
+		// overrunning the FIFO
+		for _ in 0..17 { store(&mut mem, 0x7FC0, 1, word::from(b'B')); }
+		assert_eq!(load(&mut mem, 0x7FC5, 1) as u8 & Uart::OVERRUN, Uart::OVERRUN);
+		assert_eq!(load(&mut mem, 0x7FC5, 1) as u8 & Uart::OVERRUN, 0, "reading it clears it");
+		
+		store(&mut mem, 0x7FC2, 1, word::from(Uart::CLEAR_RECEIVER));
+		assert_eq!(load(&mut mem, 0x7FC5, 1) as u8 & Uart::DATA_READY, 0);
+	}
+}
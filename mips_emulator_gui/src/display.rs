@@ -1,29 +1,39 @@
-use eframe::egui::{Ui, Vec2, Response, Sense, Rect, Pos2, Color32};
+use eframe::egui::{Ui, Vec2, Response, Sense, Rect, Pos2, Color32, ColorImage, TextureHandle, TextureOptions};
 
-pub fn mmio_display(ui: &mut Ui, data: &[u8], cells: (usize, usize), cell_size: Vec2) -> Response {
-	let cells_v = Vec2::new(cells.0 as f32, cells.1 as f32);
+/// Draws `size.0` × `size.1` pixels, each one `0x00RRGGBB` and `pixel_size`
+/// big on screen. The pixels get uploaded to `texture`, since a rectangle per
+/// pixel gets real slow for anything bigger than a few hundred of them.
+pub fn mmio_display(
+	ui: &mut Ui, texture: &mut Option<TextureHandle>,
+	pixels: &[u32], size: (usize, usize), pixel_size: Vec2
+) -> Response {
+	let size_v = Vec2::new(size.0 as f32, size.1 as f32);
 	
 	let (rect, response) =
-		ui.allocate_exact_size(cells_v * cell_size, Sense::hover());
+		ui.allocate_exact_size(size_v * pixel_size, Sense::hover());
 	
-	if ui.is_rect_visible(rect) {
-		let mut addr = 0;
+	if ui.is_rect_visible(rect) && pixels.len() == size.0 * size.1 && !pixels.is_empty() {
+		let image = ColorImage {
+			size: [size.0, size.1],
+			pixels: pixels.iter()
+				.map(|p| {
+					let [b, g, r, _] = p.to_le_bytes();
+					Color32::from_rgb(r, g, b)
+				})
+				.collect(),
+		};
 		
-		for y in 0..cells.1 {
-			let tly = cell_size.y * y as f32;
-			for x in 0..cells.0 {
-				let tlx = cell_size.x * x as f32;
-				let tl = Pos2::new(tlx, tly);
-				let c_rect = Rect::from_min_size(tl, cell_size).translate(rect.left_top().round().to_vec2());
-				
-				let c: [u8; 4] = data[addr..][..4].try_into().unwrap();
-				let [b, g, r, _] = c;
-				let fill_color = Color32::from_rgb(r, g, b);
-				
-				ui.painter().rect_filled(c_rect, 0.0, fill_color);
-				
-				addr += 4;
-			}
+		// pixels should stay crisp when they're blown up
+		if let Some(texture) = texture {
+			texture.set(image, TextureOptions::NEAREST);
+		} else {
+			*texture = Some(ui.ctx().load_texture("mmio_display", image, TextureOptions::NEAREST));
+		}
+		
+		if let Some(texture) = texture {
+			let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+			let rect = Rect::from_min_size(rect.left_top().round(), rect.size());
+			ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
 		}
 	}
 	
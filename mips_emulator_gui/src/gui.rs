@@ -1,6 +1,8 @@
 use eframe::egui;
 
 use mips_emulator::mem::Memory;
+use mips_emulator::map::{self, MemoryMap, Segment, Permissions, Preset};
+use mips_emulator::mmio::{KeyboardDisplay, BitmapDisplay, TextDisplay, Timer, Disk, Audio, Uart, DigitalLab, TileGraphics};
 use mips_emulator::chip::{Cpu, Register, Cp0Register, ExceptionCause};
 
 use crate::util;
@@ -12,6 +14,13 @@ mod registers; use registers::RegisterMonitorState;
 mod memory; use memory::MemoryWindowState;
 mod display; use display::VirtScreen;
 mod assembler; use assembler::AssemblerWindowState;
+mod changes; use changes::ChangesWindowState;
+mod keyboard; use keyboard::KeyboardWindowState;
+mod text; use text::TextScreen;
+mod disk; use disk::DiskWindowState;
+mod audio; use audio::AudioWindowState;
+mod terminal; use terminal::TerminalWindowState;
+mod digital_lab; use digital_lab::DigitalLabWindowState;
 
 pub struct EmuGui {
 	dark_theme: bool,
@@ -19,12 +28,21 @@ pub struct EmuGui {
 	cpus: Vec<Core>,
 	focused_core: usize,
 	
-	mem: Memory,
-	// places: MemoryPlaces,
+	preset: Preset,
 	
 	screen: VirtScreen,
+	text_screen: TextScreen,
 	mem_win: MemoryWindowState,
 	assember: AssemblerWindowState,
+	keyboard: KeyboardWindowState,
+	disk: DiskWindowState,
+	audio: AudioWindowState,
+	terminal: TerminalWindowState,
+	digital_lab: DigitalLabWindowState,
+	
+	/// Which core the changes window compares against, instead of the last
+	/// time the focused core paused.
+	compare_to: Option<usize>,
 }
 
 struct Core {
@@ -33,16 +51,28 @@ struct Core {
 	play: bool,
 	timer: CpuTimer,
 	
+	/// Every core has its own memory, but forked cores share pages until one
+	/// of them writes, so they're cheap.
+	mem: Memory,
+	
 	branch_delay: bool,
 	
 	reg_state: RegisterMonitorState,
+	changes: ChangesWindowState,
+	
+	/// Whether this was running last frame, to catch when it pauses.
+	was_playing: bool,
 	
 	breakpoints: Vec<u32>,
 }
-impl Default for Core {
-	fn default() -> Self {
+impl Core {
+	/// Makes a core that's just been reset, using `mem` for its memory.
+	fn new(mut mem: Memory) -> Self {
+		let mut inner = Cpu::default();
+		inner.reset(&mem.map);
+		
 		Core {
-			inner: Cpu::default(),
+			inner,
 			play: false,
 			
 			#[cfg(target_arch = "wasm32")]
@@ -50,13 +80,40 @@ impl Default for Core {
 			#[cfg(not(target_arch = "wasm32"))]
 			timer: CpuTimer::micro(100_000),
 			
+			changes: ChangesWindowState::new(&mut mem),
+			mem,
+			
 			branch_delay: true,
 			
 			reg_state: RegisterMonitorState::Cpu,
+			was_playing: false,
 			
 			breakpoints: Vec::new(),
 		}
 	}
+	
+	/// Makes a paused copy of this core, right down to the memory. Handy for
+	/// trying out "what if" without losing where you were.
+	fn fork(&self) -> Self {
+		Core {
+			inner: self.inner.clone(),
+			timer: match self.timer {
+				CpuTimer::Micro { interval, .. } => CpuTimer::micro(interval),
+				CpuTimer::Frames { interval, .. } => CpuTimer::frames(interval),
+			},
+			branch_delay: self.branch_delay,
+			reg_state: self.reg_state,
+			breakpoints: self.breakpoints.clone(),
+			..Core::new(self.mem.fork())
+		}
+	}
+	
+	/// Puts the program back in memory and starts the CPU over.
+	fn reset(&mut self) {
+		reset_mem(&mut self.mem);
+		self.inner.reset(&self.mem.map);
+		self.changes.reset(&mut self.mem);
+	}
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -64,6 +121,19 @@ enum MemoryPosition {
 	ProgramCounter,
 	LastException,
 	Position(u32),
+	
+	/// Start of the memory map's segment with this name.
+	Segment(&'static str),
+}
+impl MemoryPosition {
+	/// Where this is right now, if it doesn't depend on a CPU.
+	fn resolve(self, mem: &Memory) -> Option<u32> {
+		match self {
+			MemoryPosition::Position(p) => Some(p),
+			MemoryPosition::Segment(name) => mem.map.base(name),
+			_ => None,
+		}
+	}
 }
 
 const PRG_TEXT: &[u8] = include_bytes!("../../program/out.text.bin");
@@ -71,98 +141,101 @@ const PRG_DATA: &[u8] = include_bytes!("../../program/out.data.bin");
 
 impl Default for EmuGui {
 	fn default() -> Self {
+		// this is what `program/build.sh` assembles for
+		let preset = Preset::CompactTextAtZero;
+		
+		let mut mem = Memory::default();
+		mem.map = memory_map(preset);
+		attach_devices(&mut mem);
+		reset_mem(&mut mem);
+		
 		EmuGui {
 			dark_theme: true,
 			
 			cpus: vec![
-				Core {
-					inner: {
-						let mut cpu = Cpu::default();
-						reset_cpu(&mut cpu);
-						cpu
-					},
-					..Default::default()
-				},
+				Core::new(mem),
 				/*Core {
-					inner: {
-						let mut cpu = Cpu::default();
-						reset_cpu(&mut cpu);
-						cpu
-					},
-					
 					#[cfg(target_arch = "wasm32")]
 					timer: CpuTimer::frames(18.0),
 					#[cfg(not(target_arch = "wasm32"))]
 					timer: CpuTimer::micro(110_000),
 					
-					..Default::default()
+					..Core::new(mem.fork())
 				},*/
 			],
 			focused_core: 0,
 			
-			mem: {
-				let mut mem = Memory::default();
-				reset_mem(&mut mem);
-				mem
-			},
-			// places: MemoryPlaces::default(),
+			preset,
 			
 			screen: VirtScreen::default(),
+			text_screen: TextScreen::default(),
 			mem_win: MemoryWindowState::default(),
 			assember: AssemblerWindowState::default(),
+			keyboard: KeyboardWindowState::default(),
+			disk: DiskWindowState::default(),
+			audio: AudioWindowState::default(),
+			terminal: TerminalWindowState::default(),
+			digital_lab: DigitalLabWindowState,
+			compare_to: None,
 		}
 	}
 }
 
-// struct MemoryPlaces {
-// 	memory: std::ops::Range<u32>,
-// 	text: std::ops::Range<u32>,
-// 	data: std::ops::Range<u32>,
-// 	heap_stack: std::ops::Range<u32>,
-// 	mmio: std::ops::Range<u32>,
-// }
-// impl Default for MemoryPlaces {
-// 	fn default() -> Self {
-// 		MemoryPlaces {
-// 			memory: 0x0000..0x8000,
-			
-// 			text: 0x0000..0x1000,
-// 			data: 0x2000..0x3000,
-// 			heap_stack: 0x3000..0x4000,
-			
-// 			mmio: 0x7F00..0x8000,
-// 		}
-// 	}
-// }
+/// Where the sample programs draw to. It's outside of every MARS layout, so
+/// it gets tacked on as its own segment.
+const DISPLAY: &str = "display";
 
-fn reset_cpu(cpu: &mut Cpu) {
-	cpu.cp0.halt = false;
-	
-	cpu[Register::gp] = 0x1800;
-	cpu[Register::sp] = 0x3FFC;
+fn memory_map(preset: Preset) -> MemoryMap {
+	let mut map = preset.map();
+	map.segments.push(Segment::new(DISPLAY, 0x01_0000..=0x01_FFFF, Permissions::RW));
+	map
+}
+
+/// Hooks up all the MMIO devices wherever the memory map has room for them.
+fn attach_devices(mem: &mut Memory) {
+	mem.detach_all();
 	
-	cpu.pc = 0x00_0000;
-	cpu.after_delay = None;
+	if let Some(mmio) = mem.map.base(map::MMIO) {
+		mem.attach(Box::new(KeyboardDisplay::new(mmio)));
+		mem.attach(Box::new(DigitalLab::new(mmio + 0x10)));
+		
+		// the sample programs draw 16×16 pixels into the display segment
+		let framebuffer = mem.map.base(DISPLAY).unwrap_or(0);
+		mem.attach(Box::new(BitmapDisplay::new(mmio + 0x20, framebuffer, 16, 16)));
+		
+		// and there's plenty of room left over for text
+		mem.attach(Box::new(TextDisplay::new(mmio + 0x40, framebuffer + 0x8000, 80, 25)));
+		
+		mem.attach(Box::new(Timer::new(mmio + 0x60)));
+		mem.attach(Box::new(Disk::new(mmio + 0x80)));
+		mem.attach(Box::new(Audio::new(mmio + 0xA0)));
+		mem.attach(Box::new(Uart::new(mmio + 0xC0)));
+		
+		// a bit bigger than a Game Boy
+		mem.attach(Box::new(TileGraphics::new(mmio + 0xD0, 256, 192)));
+	}
 }
 
 fn reset_mem(mem: &mut Memory) {
 	mem.clear();
-	mem.set_slice(0x00_0000, PRG_TEXT);
-	mem.set_slice(0x00_2000, PRG_DATA);
 	
-	// HACK: init MMIO page because I'm lazy.
-	// I later access it directly, which is a sin.
-	mem.set_byte(0x01_0000, 0);
+	if let Some(text) = mem.map.base(map::TEXT) {
+		mem.set_slice(text, PRG_TEXT);
+	}
+	if let Some(data) = mem.map.base(map::DATA) {
+		mem.set_slice(data, PRG_DATA);
+	}
 }
 
 impl eframe::App for EmuGui {
 	fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-		let Self { cpus: cores, mem, .. } = self;
+		let Self { cpus: cores, .. } = self;
 		
 		for core in cores.iter_mut() {
 			if core.inner.cp0.halt { core.play = false; }
 			if core.play {
 				let ticked = core.timer.tick();
+				let mem = &mut core.mem;
 				
 				if core.branch_delay {
 					for _ in 0..ticked { core.inner.tick_branch_delay(mem); }
@@ -194,6 +267,23 @@ impl eframe::App for EmuGui {
 				
 				ui.separator();
 				
+				ui.menu_button("Memory Layout", |ui| {
+					ui.weak("Changing this resets every core.\nPrograms need to be assembled for\nthe same layout to work.");
+					for preset in Preset::ALL {
+						if ui.selectable_label(self.preset == preset, preset.friendly_name()).clicked() {
+							self.preset = preset;
+							for core in cores.iter_mut() {
+								core.mem.map = memory_map(preset);
+								attach_devices(&mut core.mem);
+								core.reset();
+							}
+							ui.close_menu();
+						}
+					}
+				});
+				
+				ui.separator();
+				
 				// if ui.button("Add core").clicked() {
 				// 	cores.push(Core::default());
 				// }
@@ -202,6 +292,7 @@ impl eframe::App for EmuGui {
 			ui.separator();
 			
 			let mut remove_which_core = None;
+			let mut fork_which_core = None;
 			
 			fn v_separator(ui: &mut egui::Ui) -> egui::Response {
 				ui.add(egui::Separator::default().spacing(0.0).vertical())
@@ -224,8 +315,13 @@ impl eframe::App for EmuGui {
 					.on_hover_text("Resets the CPU's state -- the memory,\nthe registers, the PC, everything.")
 					.clicked() {
 						println!("~~ Reset CPU ~~");
-						reset_cpu(&mut core.inner);
-						reset_mem(mem);
+						core.reset();
+					}
+					
+					if ui.button("Fork")
+					.on_hover_text("Adds a paused copy of this core, memory and all.\nThe copy shares memory with this one until\neither of them writes to it.")
+					.clicked() {
+						fork_which_core = Some(i);
 					}
 					
 					ui.add_enabled_ui(!core.inner.cp0.halt, |ui| {
@@ -233,7 +329,8 @@ impl eframe::App for EmuGui {
 						.on_hover_text("Steps the CPU forward a single instruction.")
 						.on_disabled_hover_text("The CPU has halted, and needs to reset\nbefore it can do more.")
 						.clicked() {
-							core.inner.tick(mem);
+							core.inner.tick(&mut core.mem);
+							core.changes.capture(&mut core.mem);
 						}
 						
 						let play_text = if core.play { "⏸" } else { "▶" };
@@ -278,10 +375,16 @@ impl eframe::App for EmuGui {
 				}
 			});
 			
+			if let Some(core) = fork_which_core {
+				let fork = cores[core].fork();
+				cores.push(fork);
+			}
+			
 			if let Some(core) = remove_which_core {
 				if cores.len() > 1 {
 					cores.remove(core);
 					self.focused_core = self.focused_core.min(cores.len().saturating_sub(1));
+					self.compare_to = None;
 				}
 			}
 			
@@ -290,14 +393,36 @@ impl eframe::App for EmuGui {
 		
 		egui::CentralPanel::default().show(ctx, |_|());
 		
-		self.assember.show(&mut self.mem, ctx);
+		let focused = &mut self.cpus[self.focused_core];
+		
+		self.assember.show(&mut focused.mem, ctx);
+		
+		self.mem_win.show(focused, ctx);
+		
+		self.screen.show(&mut focused.mem, ctx);
+		
+		self.text_screen.show(&mut focused.mem, ctx);
 		
-		self.mem_win.show(&mut self.cpus[self.focused_core], &mut self.mem, ctx);
+		self.keyboard.show(&mut focused.mem, ctx);
+		
+		self.disk.show(&mut focused.mem, ctx);
+		
+		self.audio.show(&mut focused.mem, ctx);
+		
+		self.terminal.show(&mut focused.mem, ctx);
+		
+		self.digital_lab.show(&mut focused.mem, ctx);
 		
 		for (i, core) in self.cpus.iter_mut().enumerate() {
 			core.reg_state.show((i, &mut core.inner), ctx);
+			
+			if core.was_playing && !core.play {
+				core.changes.capture(&mut core.mem);
+			}
+			core.was_playing = core.play;
 		}
 		
-		self.screen.show(&self.mem, ctx);
+		let focused = &self.cpus[self.focused_core];
+		focused.changes.show((self.focused_core, &self.cpus), &mut self.compare_to, ctx);
 	}
 }
@@ -1,16 +1,39 @@
 use super::*;
 
-struct AssemblerError(usize, &'static str);
+use std::collections::BTreeMap;
+
+use mips_assembler::{Program, Error, Bases};
 
-#[derive(Default)]
 pub(super) struct AssemblerWindowState {
-	source: String,
+	/// Every tab, by name and source. These are also what `.include` can
+	/// pull in, so macro libraries can go in a tab of their own.
+	files: Vec<(String, String)>,
+	/// The tab being shown, which is also the one that gets assembled.
+	open: usize,
+	/// What's in the name box, which only renames the tab once it's done
+	/// being typed.
+	name: String,
 	show_line_nums: bool,
 	
-	result: Option<Result<Vec<u32>, Vec<AssemblerError>>>,
+	result: Option<Result<Program, Vec<Error>>>,
+	assembled_with: Option<Bases>,
 	insert_at: u32,
 }
 
+impl Default for AssemblerWindowState {
+	fn default() -> Self {
+		AssemblerWindowState {
+			files: vec![("main.asm".into(), String::new())],
+			open: 0,
+			name: "main.asm".into(),
+			show_line_nums: false,
+			result: None,
+			assembled_with: None,
+			insert_at: 0,
+		}
+	}
+}
+
 impl AssemblerWindowState {
 	fn layout_line_numbers(source: &str, ui: &egui::Ui, show: bool) -> egui::text::LayoutJob {
 		use egui::{TextStyle, TextFormat, text::LayoutJob};
@@ -18,7 +41,7 @@ impl AssemblerWindowState {
 		let monospace = TextStyle::Monospace.resolve(ui.style());
 		let text_color = ui.visuals().override_text_color
 			.unwrap_or_else(|| ui.visuals().widgets.inactive.text_color());
-			
+		
 		if !show {
 			return LayoutJob::simple(source.to_owned(), monospace, text_color, 0.0);
 		}
@@ -39,10 +62,73 @@ impl AssemblerWindowState {
 		layout
 	}
 	
+	/// `.text` goes wherever it's been asked to, and the rest of the sections
+	/// go where the memory map says.
+	fn bases(&self, mem: &Memory) -> Bases {
+		Bases { text: self.insert_at, ..Bases::from(&mem.map) }
+	}
+	
+	fn assemble(&mut self, mem: &Memory) {
+		let bases = self.bases(mem);
+		let files: BTreeMap<String, String> = self.files.iter().cloned().collect();
+		self.result = Some(mips_assembler::assemble_files(&files, &self.files[self.open].0, bases));
+		self.assembled_with = Some(bases);
+	}
+	
+	fn show_tabs(&mut self, ui: &mut egui::Ui) {
+		let open = self.open;
+		
+		ui.horizontal_wrapped(|ui| {
+			for (i, (name, _)) in self.files.iter().enumerate() {
+				ui.selectable_value(&mut self.open, i, name);
+			}
+			
+			if ui.small_button("+").clicked() {
+				let name = (1..)
+					.map(|n| format!("untitled{n}.asm"))
+					.find(|name| self.files.iter().all(|(other, _)| other != name))
+					.unwrap();
+				self.files.push((name, String::new()));
+				self.open = self.files.len() - 1;
+			}
+		});
+		
+		ui.horizontal(|ui| {
+			ui.label("Name: ");
+			let edit = ui.text_edit_singleline(&mut self.name);
+			
+			// tabs are looked up by name for `.include`, so no two can
+			// have the same one
+			let name = self.name.trim();
+			let taken = self.files.iter().enumerate().any(|(i, (other, _))| i != open && other == name);
+			if taken {
+				ui.colored_label(egui::Color32::RED, "Another tab's already called that.");
+			}
+			if edit.lost_focus() {
+				if !taken && !name.is_empty() {
+					self.files[open].0 = name.to_owned();
+				}
+				self.name = self.files[open].0.clone();
+			}
+			
+			if ui.add_enabled(self.files.len() > 1, egui::Button::new("Close").small()).clicked() {
+				self.files.remove(self.open);
+				self.open = self.open.min(self.files.len() - 1);
+				self.name = self.files[self.open].0.clone();
+			}
+		});
+		
+		if self.open != open {
+			self.name = self.files[self.open].0.clone();
+		}
+	}
+	
 	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
 		egui::Window::new("Assembler").show(ctx, |ui| {
+			self.show_tabs(ui);
+			
 			self.show_line_nums = !ui.add(
-				egui::TextEdit::multiline(&mut self.source)
+				egui::TextEdit::multiline(&mut self.files[self.open].1)
 				.font(egui::TextStyle::Monospace)
 				.code_editor()
 				.desired_rows(8)
@@ -55,7 +141,7 @@ impl AssemblerWindowState {
 			).has_focus();
 			
 			if ui.button("Assemble").clicked() {
-				self.result = Some(Self::assemble(&self.source));
+				self.assemble(mem);
 			}
 			
 			ui.separator();
@@ -80,8 +166,11 @@ impl AssemblerWindowState {
 					.auto_shrink([false, true])
 					.max_height(70.0)
 					.show(ui, |ui| {
-						for &AssemblerError(line, err) in inner {
-							ui.monospace(format!("Line {line}: {err}"));
+						for Error { span, file, message, notes } in inner {
+							ui.monospace(format!("{file}, line {}, column {}: {message}", span.line, span.column));
+							for Error { span, file, message, .. } in notes {
+								ui.monospace(format!("    {file}, line {}, column {}: {message}", span.line, span.column));
+							}
 						}
 					});
 			}
@@ -96,66 +185,16 @@ impl AssemblerWindowState {
 				);
 				
 				if ui.add_enabled(matches!(self.result, Some(Ok(_))), egui::Button::new("Insert")).clicked() {
-					if let Some(Ok(ref slice)) = self.result {
-						let bytes: Vec<u8> = slice.iter().cloned().flat_map(u32::to_le_bytes).collect();
-						mem.set_slice(self.insert_at, &bytes);
+					// labels depend on where the code goes, so this has to be
+					// assembled again if that's changed since
+					if self.assembled_with != Some(self.bases(mem)) {
+						self.assemble(mem);
+					}
+					if let Some(Ok(ref program)) = self.result {
+						program.load(mem);
 					}
 				}
 			});
 		});
 	}
-	
-	fn assemble(source: &str) -> Result<Vec<u32>, Vec<AssemblerError>> {
-		use std::collections::HashMap;
-		
-		let mut code = Vec::new();
-		let mut errors = Vec::new();
-		
-		let mut labels = HashMap::new();
-		
-		for (i, l) in source.lines().enumerate()
-			.map(|(i, s)| (i, s.trim_start()))
-			.filter(|&(_, s)| !(s.is_empty() || s.starts_with(['#'])))
-			.map(|(i, s)| (i, s.split('#').next().unwrap().trim_end()))
-		{
-			if l.ends_with(':') && !l.contains(' ') {
-				// parse labels
-				
-				// TODO: some way to handle labels
-				labels.insert(l, code.len().wrapping_shl(2));
-				errors.push(AssemblerError(i + 1, "labels don't work yet"));
-			} else if let Some(_l) = l.strip_prefix('.') {
-				// parse preprocessor stuff
-				
-				// TODO: handle .text / .data and stuff
-				
-				// match l.split_once(' ')
-				// 	.map(|(a, b)| (a, Some(b)))
-				// 	.unwrap_or_else(|| (l, None)) {
-				// 	("text", None) => ,
-				// }
-				errors.push(AssemblerError(i + 1, "preprocessor stuff doesn't work yet"));
-			} else {
-				// hand off to CPU crate's built-in assembler
-				
-				// TODO: maybe remove it from the CPU crate and
-				//  make a proper parser in this zone??
-				//  hell, make a new crate?
-				
-				// let x = Cow::from(l);
-				// 
-				// for label in
-				// 	l.split_ascii_whitespace().skip(1)
-				// 	.filter(|&s| !s.is_empty()) {
-				// }
-				
-				match Cpu::from_assembly(l) {
-					Ok(w) => code.push(w),
-					Err(m) => errors.push(AssemblerError(i + 1, m)),
-				};
-			}
-		}
-		
-		if errors.is_empty() { Ok(code) } else { Err(errors) }
-	}
 }
@@ -0,0 +1,68 @@
+use super::*;
+
+use mips_emulator::mmio::Audio;
+
+/// Shows what the focused core's sound card has played, and saves it.
+pub(super) struct AudioWindowState {
+	path: String,
+	
+	/// How saving went last time.
+	result: Option<Result<String, String>>,
+}
+impl Default for AudioWindowState {
+	fn default() -> Self {
+		AudioWindowState { path: "out.wav".to_owned(), result: None }
+	}
+}
+
+impl AudioWindowState {
+	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
+		egui::Window::new("Audio").show(ctx, |ui| {
+			let Some(audio) = mem.device_mut::<Audio>() else {
+				ui.weak("This core's memory doesn't have a sound card hooked up.");
+				return;
+			};
+			
+			ui.horizontal(|ui| {
+				ui.monospace(format!("{:#010X}", audio.base()));
+				
+				ui.separator();
+				
+				let channels = if audio.channels == 2 { "stereo" } else { "mono" };
+				ui.label(format!("{} Hz {channels}, {}", audio.rate, audio.format.friendly_name()));
+			});
+			
+			ui.horizontal(|ui| {
+				ui.label(format!("{:.2}s played", audio.duration().as_secs_f64()));
+				ui.weak(format!("{} of {} queued", audio.queued(), audio.capacity));
+				
+				if ui.small_button("Clear").clicked() {
+					audio.played.clear();
+				}
+			});
+			
+			ui.separator();
+			
+			ui.horizontal(|ui| {
+				ui.add(
+					egui::TextEdit::singleline(&mut self.path)
+						.font(egui::TextStyle::Monospace)
+						.desired_width(160.0)
+				);
+				
+				if ui.button("Save WAV").clicked() {
+					self.result = Some(match audio.save_wav(self.path.trim()) {
+						Ok(()) => Ok(format!("Saved to {}", self.path.trim())),
+						Err(e) => Err(e.to_string()),
+					});
+				}
+			});
+			
+			match &self.result {
+				Some(Ok(msg)) => { ui.weak(msg); },
+				Some(Err(e)) => { ui.colored_label(ui.visuals().error_fg_color, e); },
+				None => (),
+			}
+		});
+	}
+}
@@ -0,0 +1,108 @@
+use super::*;
+
+use std::borrow::Cow;
+
+use mips_emulator::mem::Change;
+
+/// Lists everything in a core's memory that changed since it last paused.
+/// Way easier than squinting at the memory monitor.
+/// 
+/// It can also compare against another core instead, to see where a fork
+/// went its own way.
+pub(super) struct ChangesWindowState {
+	/// What memory looked like the last time the core paused. Since memory is
+	/// copy-on-write, keeping this around only costs the pages that changed.
+	before: Memory,
+	changes: Vec<Change>,
+}
+
+/// Changes longer than this get cut off with a "…".
+const SHOW_BYTES: usize = 8;
+
+fn hex_bytes(bytes: &[u8]) -> String {
+	let mut s = bytes.iter()
+		.take(SHOW_BYTES)
+		.map(|b| format!("{b:02X}"))
+		.collect::<Vec<_>>()
+		.join(" ");
+	if bytes.len() > SHOW_BYTES { s.push_str(" …"); }
+	s
+}
+
+impl ChangesWindowState {
+	pub(super) fn new(mem: &mut Memory) -> Self {
+		mem.mark_clean();
+		ChangesWindowState { before: mem.clone(), changes: Vec::new() }
+	}
+	
+	/// Call this whenever the core stops, so the list shows what it did since
+	/// the last time it stopped.
+	pub(super) fn capture(&mut self, mem: &mut Memory) {
+		self.changes = self.before.diff_pages(mem, mem.dirty_pages());
+		self.before = mem.clone();
+		mem.mark_clean();
+	}
+	
+	/// Forgets every change, like after a reset.
+	pub(super) fn reset(&mut self, mem: &mut Memory) {
+		*self = ChangesWindowState::new(mem);
+	}
+	
+	/// Shows the changes for core number `i`. If `compare_to` is another core,
+	/// it shows how that core's memory differs from this one's instead.
+	pub(super) fn show(&self, (i, cores): (usize, &[Core]), compare_to: &mut Option<usize>, ctx: &egui::Context) {
+		egui::Window::new("Memory Changes").show(ctx, |ui| {
+			let other = compare_to.filter(|&j| j != i).and_then(|j| cores.get(j));
+			
+			ui.horizontal(|ui| {
+				ui.label(format!("Core {} vs.", i + 1));
+				egui::ComboBox::from_id_source("CompareTo")
+					.selected_text(match other {
+						Some(_) => format!("Core {}", compare_to.unwrap() + 1),
+						None => "Last pause".to_owned(),
+					})
+					.show_ui(ui, |ui| {
+					ui.selectable_value(compare_to, None, "Last pause");
+					for j in (0..cores.len()).filter(|&j| j != i) {
+						ui.selectable_value(compare_to, Some(j), format!("Core {}", j + 1));
+					}
+				});
+			});
+			
+			// comparing cores is diffed fresh every frame. forks share most of
+			// their pages, so it's usually not much work.
+			let changes = match other {
+				Some(other) => Cow::Owned(other.mem.diff(&cores[i].mem)),
+				None => Cow::Borrowed(&self.changes),
+			};
+			
+			let bytes: usize = changes.iter().map(|c| c.new.len()).sum();
+			ui.label(match other {
+				Some(_) => format!("{bytes} bytes in {} places are different.", changes.len()),
+				None => format!("{bytes} bytes in {} places changed since the last pause.", changes.len()),
+			});
+			
+			ui.separator();
+			
+			let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+			
+			egui::ScrollArea::vertical()
+				.auto_shrink([false, true])
+				.max_height(200.0)
+				.show_rows(ui, row_height, changes.len(), |ui, row_range| {
+				egui::Grid::new("MemoryChanges")
+					.num_columns(4)
+					.striped(true)
+					.show(ui, |ui| {
+					for Change { addr, old, new } in &changes[row_range] {
+						ui.monospace(format!("{addr:#010X}"));
+						ui.weak(hex_bytes(old));
+						ui.label("→");
+						ui.monospace(hex_bytes(new));
+						ui.end_row();
+					}
+				});
+			});
+		});
+	}
+}
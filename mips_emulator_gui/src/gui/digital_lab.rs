@@ -0,0 +1,85 @@
+use super::*;
+
+use mips_emulator::mmio::DigitalLab;
+
+/// Like MARS' "Digital Lab Sim" tool, for the focused core.
+#[derive(Default)]
+pub(super) struct DigitalLabWindowState;
+
+/// Draws one seven-segment digit, lit up however `segments` says.
+fn seven_segment(ui: &mut egui::Ui, segments: u8) {
+	let size = egui::vec2(48.0, 80.0);
+	let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+	if !ui.is_rect_visible(rect) { return; }
+	
+	let painter = ui.painter_at(rect);
+	painter.rect_filled(rect, 4.0, egui::Color32::from_gray(24));
+	
+	let lit = egui::Color32::from_rgb(255, 48, 32);
+	let unlit = egui::Color32::from_rgb(56, 16, 12);
+	let color = |bit: u8| if segments & 1 << bit != 0 { lit } else { unlit };
+	
+	let r = rect.shrink2(egui::vec2(10.0, 10.0));
+	let (l, t, m, b) = (r.left() - 2.0, r.top(), r.center().y, r.bottom());
+	let right = r.right() - 4.0;
+	let gap = 4.0;
+	let stroke = |bit| egui::Stroke::new(5.0, color(bit));
+	
+	// top, then clockwise, then the middle. same order as the bits
+	let lines = [
+		[(l + gap, t), (right - gap, t)],
+		[(right, t + gap), (right, m - gap)],
+		[(right, m + gap), (right, b - gap)],
+		[(l + gap, b), (right - gap, b)],
+		[(l, m + gap), (l, b - gap)],
+		[(l, t + gap), (l, m - gap)],
+		[(l + gap, m), (right - gap, m)],
+	];
+	for (bit, [(x0, y0), (x1, y1)]) in lines.into_iter().enumerate() {
+		painter.line_segment([egui::pos2(x0, y0), egui::pos2(x1, y1)], stroke(bit as u8));
+	}
+	
+	painter.circle_filled(egui::pos2(r.right() + 3.0, b), 3.0, color(7));
+}
+
+impl DigitalLabWindowState {
+	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
+		egui::Window::new("Digital Lab Sim").show(ctx, |ui| {
+			let Some(lab) = mem.device_mut::<DigitalLab>() else {
+				ui.weak("This core's memory doesn't have a Digital Lab Sim hooked up.");
+				return;
+			};
+			
+			ui.horizontal(|ui| {
+				ui.monospace(format!("{:#010X}", lab.base()));
+				
+				if lab.keypad_interrupts { ui.weak("keypad interrupts"); }
+				if lab.counter_interrupts { ui.weak("counter interrupts"); }
+			});
+			
+			ui.separator();
+			
+			ui.horizontal(|ui| {
+				seven_segment(ui, lab.left);
+				seven_segment(ui, lab.right);
+				
+				ui.separator();
+				
+				// clicking a key holds it down until it's clicked again, like MARS
+				egui::Grid::new("keypad").spacing([4.0, 4.0]).show(ui, |ui| {
+					for key in 0..16 {
+						let down = lab.pressed == Some(key);
+						let button = egui::Button::new(
+							egui::RichText::new(format!("{key:X}")).monospace().size(16.0)
+						).min_size(egui::vec2(32.0, 32.0)).selected(down);
+						
+						if ui.add(button).clicked() {
+							if down { lab.release(); } else { lab.press(key); }
+						}
+						if key % 4 == 3 { ui.end_row(); }
+					}
+				});
+			});
+		});
+	}
+}
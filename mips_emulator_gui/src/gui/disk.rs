@@ -0,0 +1,66 @@
+use super::*;
+
+use mips_emulator::mmio::Disk;
+
+/// Picks which image file is in the focused core's disk drive.
+#[derive(Default)]
+pub(super) struct DiskWindowState {
+	path: String,
+	
+	/// Why the image couldn't be put in.
+	error: Option<&'static str>,
+}
+
+impl DiskWindowState {
+	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
+		egui::Window::new("Disk").show(ctx, |ui| {
+			let Some(disk) = mem.device_mut::<Disk>() else {
+				ui.weak("This core's memory doesn't have a disk drive hooked up.");
+				return;
+			};
+			
+			ui.horizontal(|ui| {
+				ui.monospace(format!("{:#010X}", disk.base()));
+				
+				ui.separator();
+				
+				match disk.image() {
+					Some(image) => {
+						ui.label(format!("{} sectors", disk.sectors()))
+							.on_hover_text(image.display().to_string());
+						if ui.small_button("Eject").clicked() {
+							disk.eject();
+						}
+					},
+					None => { ui.weak("No disk"); },
+				}
+			});
+			
+			ui.horizontal(|ui| {
+				let response = ui.add(
+					egui::TextEdit::singleline(&mut self.path)
+						.font(egui::TextStyle::Monospace)
+						.hint_text("Path to an image...")
+						.desired_width(200.0)
+				);
+				
+				let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+				if ui.button("Insert").clicked() || enter {
+					self.error = disk.insert(self.path.trim()).err();
+				}
+			});
+			
+			if let Some(error) = self.error {
+				ui.colored_label(ui.visuals().error_fg_color, error);
+			}
+			
+			if let Some(error) = disk.error {
+				ui.separator();
+				ui.horizontal(|ui| {
+					ui.label(format!("Sector {}:", disk.sector));
+					ui.colored_label(ui.visuals().warn_fg_color, error);
+				});
+			}
+		});
+	}
+}
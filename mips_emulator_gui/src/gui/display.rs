@@ -1,103 +1,201 @@
 use super::*;
 
+use mips_emulator::mmio::{BitmapDisplay, PixelFormat, TileGraphics};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Source {
+	#[default]
+	Bitmap,
+	Tiles,
+}
+
+/// Shows the focused core's bitmap display, or what its graphics chip drew.
 pub(super) struct VirtScreen {
-	look: MemoryPosition,
-	cells: (usize, usize),
+	source: Source,
+	
+	/// How big each pixel is on screen.
 	size: egui::Vec2,
+	texture: Option<egui::TextureHandle>,
 }
 impl Default for VirtScreen {
 	fn default() -> Self {
 		VirtScreen {
-			look: MemoryPosition::Position(0x01_0000),
-			cells: (16, 16),
+			source: Source::default(),
 			size: egui::vec2(16.0, 16.0),
+			texture: None,
 		}
 	}
 }
 
 impl VirtScreen {
-	pub(super) fn show(&mut self, mem: &Memory, ctx: &egui::Context) {
+	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
 		egui::Window::new("Virtual Display").show(ctx, |ui| {
-			ui.menu_button("View", |ui| {
-				ui.horizontal(|ui| {
-					ui.label("Cells:");
-					ui.add(
-						egui::DragValue::new(&mut self.cells.0)
-							.clamp_range(2..=128)
-							.speed(0.125)
-					);
-					ui.label("×");
-					ui.add(
-						egui::DragValue::new(&mut self.cells.1)
-							.clamp_range(2..=128)
-							.speed(0.125)
-					);
+			ui.horizontal(|ui| {
+				ui.selectable_value(&mut self.source, Source::Bitmap, "Bitmap");
+				ui.selectable_value(&mut self.source, Source::Tiles, "Tiles & Sprites");
+			});
+			
+			ui.separator();
+			
+			if self.source == Source::Tiles {
+				self.show_tiles(mem, ui);
+				return;
+			}
+			
+			// the device is borrowed the whole time the menu's open, so the
+			// segments need to be grabbed first
+			let segments: Vec<_> = mem.map.segments.iter()
+				.map(|s| (s.name, s.base()))
+				.collect();
+			
+			let Some(bd) = mem.device_mut::<BitmapDisplay>() else {
+				ui.weak("This core's memory doesn't have a bitmap display hooked up.");
+				return;
+			};
+			
+			ui.horizontal(|ui| {
+				ui.menu_button("View", |ui| {
+					ui.horizontal(|ui| {
+						ui.label("Pixels:");
+						ui.add(
+							egui::DragValue::new(&mut bd.width)
+								.clamp_range(1..=1024)
+								.speed(0.125)
+						);
+						ui.label("×");
+						ui.add(
+							egui::DragValue::new(&mut bd.height)
+								.clamp_range(1..=1024)
+								.speed(0.125)
+						);
+						
+						ui.separator();
+						
+						ui.label("Size:");
+						ui.add(
+							egui::DragValue::new(&mut self.size.x)
+								.max_decimals(0)
+								.clamp_range(1..=64)
+								.speed(0.125)
+								.suffix("px")
+						);
+						// self.size.y = self.size.x;
+						ui.label("×");
+						ui.add(
+							egui::DragValue::new(&mut self.size.y)
+								.max_decimals(0)
+								.clamp_range(1..=64)
+								.speed(0.125)
+								.suffix("px")
+						);
+					});
+					
+					ui.separator();
+					
+					ui.horizontal(|ui| {
+						ui.label("Format:");
+						for format in PixelFormat::ALL {
+							ui.selectable_value(&mut bd.format, format, format.friendly_name());
+						}
+					});
+					
+					ui.horizontal(|ui| {
+						ui.label("Frame:");
+						ui.add(
+							egui::DragValue::new(&mut bd.frame_length)
+								.clamp_range(1..=1_000_000)
+								.suffix(" ins")
+						).on_hover_text("How many instructions each frame lasts.\nThe display raises vblank when one's done.");
+					});
 					
 					ui.separator();
 					
+					ui.horizontal(|ui| {
+						for &(name, base) in &segments {
+							ui.selectable_value(&mut bd.framebuffer, base, name);
+						}
+						
+						let row = (bd.width * bd.format.bytes_per_pixel()) as u32;
+						if ui.add_enabled(
+							bd.framebuffer > 0x00_0000, egui::Button::new("←").small()
+						).clicked() {
+							bd.framebuffer = bd.framebuffer.saturating_sub(row);
+						}
+						if ui.add_enabled(
+							bd.framebuffer.checked_add(row).is_some(), egui::Button::new("→").small()
+						).clicked() {
+							bd.framebuffer += row;
+						}
+					});
+				});
+				
+				ui.monospace(format!("{:#010X}", bd.framebuffer));
+				ui.weak(format!("frame {}", bd.frame()));
+			});
+			
+			ui.separator();
+			
+			let bd = mem.device::<BitmapDisplay>().unwrap();
+			let pixels = bd.render(mem);
+			let size = (bd.width, bd.height);
+			ui.vertical_centered_justified(|ui| {
+				mmio_display(ui, &mut self.texture, &pixels, size, self.size);
+			});
+		});
+	}
+	
+	fn show_tiles(&mut self, mem: &mut Memory, ui: &mut egui::Ui) {
+		let Some(tg) = mem.device_mut::<TileGraphics>() else {
+			ui.weak("This core's memory doesn't have a graphics chip hooked up.");
+			return;
+		};
+		
+		ui.horizontal(|ui| {
+			ui.menu_button("View", |ui| {
+				ui.horizontal(|ui| {
 					ui.label("Size:");
 					ui.add(
 						egui::DragValue::new(&mut self.size.x)
 							.max_decimals(0)
-							.clamp_range(4..=64)
+							.clamp_range(1..=64)
 							.speed(0.125)
 							.suffix("px")
 					);
-					// self.size.y = self.size.x;
 					ui.label("×");
 					ui.add(
 						egui::DragValue::new(&mut self.size.y)
 							.max_decimals(0)
-							.clamp_range(4..=64)
+							.clamp_range(1..=64)
 							.speed(0.125)
 							.suffix("px")
 					);
 				});
 				
-				ui.separator();
-				
 				ui.horizontal(|ui| {
-					let vl = &mut self.look;
-					
-					// ui.selectable_value(vl, MemoryPosition::ProgramCounter, "PC");
-					ui.selectable_value(vl, MemoryPosition::Position(0x00_0000), ".text");
-					ui.selectable_value(vl, MemoryPosition::Position(0x00_2000), ".data");
-					ui.selectable_value(vl, MemoryPosition::Position(0x01_0000), "MMIO");
-					
-					if let MemoryPosition::Position(look) = &mut self.look {
-						if ui.add_enabled(
-							*look > 0x00_0000, egui::Button::new("←").small()
-						).clicked() {
-							*look = look.saturating_sub((self.cells.0 as u32) << 2);
-						}
-						if ui.add_enabled(
-							*look < 0x01_0000, egui::Button::new("→").small()
-						).clicked() {
-							*look = look.saturating_add((self.cells.0 as u32) << 2).min(0x01_0000);
-						}
-					}
+					ui.label("Frame:");
+					ui.add(
+						egui::DragValue::new(&mut tg.frame_length)
+							.clamp_range(1..=1_000_000)
+							.suffix(" ins")
+					).on_hover_text("How many instructions each frame lasts.\nThe picture's put together when one's done.");
 				});
+				
+				ui.separator();
+				
+				ui.checkbox(&mut tg.show_background, "Background");
+				ui.checkbox(&mut tg.show_sprites, "Sprites");
 			});
 			
-			ui.separator();
-			
-			let look = match self.look {
-				MemoryPosition::Position(n) => n,
-				_ => unimplemented!(),
-			} as usize;
-			let mem_take = self.cells.0 * self.cells.1 * 4;
-			ui.vertical_centered_justified(|ui| {
-				if let Some(mem_slice) = mem.get_slice(look as u32, mem_take) {
-					mmio_display(ui, mem_slice, self.cells, self.size);
-				} else {
-					ui.label(format!(
-						"oops! {look:#010X} isn't in a valid page,\n\
-						or is across a page boundary.\n\
-						you didn't do anything wrong, it's my bad;\n\
-						i have a half-baked \"sparse memory\" system..."
-					));
-				}
-			});
+			ui.monospace(format!("{}×{}", tg.width, tg.height));
+			ui.weak(format!("scrolled to {}, {}", tg.scroll_x as i32, tg.scroll_y as i32));
+			ui.weak(format!("frame {}", tg.frame()));
+		});
+		
+		ui.separator();
+		
+		let size = (tg.width, tg.height);
+		ui.vertical_centered_justified(|ui| {
+			mmio_display(ui, &mut self.texture, tg.screen(), size, self.size);
 		});
 	}
 }
@@ -0,0 +1,83 @@
+use super::*;
+
+use mips_emulator::mmio::KeyboardDisplay;
+
+/// Like MARS' "Keyboard and Display MMIO Simulator" tool. Whatever's typed in
+/// here goes to the focused core's keyboard, and whatever it prints shows up
+/// up top.
+#[derive(Default)]
+pub(super) struct KeyboardWindowState {
+	/// Typed, but not sent to the keyboard yet. Only ever holds a frame's
+	/// worth of typing.
+	input: String,
+}
+
+impl KeyboardWindowState {
+	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
+		egui::Window::new("Keyboard and Display").show(ctx, |ui| {
+			let Some(kd) = mem.device_mut::<KeyboardDisplay>() else {
+				ui.weak("This core's memory doesn't have a keyboard hooked up.");
+				return;
+			};
+			
+			ui.horizontal(|ui| {
+				ui.monospace(format!("{:#010X}", kd.base()));
+				
+				ui.separator();
+				
+				ui.label("Delay:");
+				ui.add(
+					egui::DragValue::new(&mut kd.delay)
+						.clamp_range(0..=1000)
+						.suffix(" ins")
+				).on_hover_text("How many instructions it takes to print a character.");
+				
+				ui.separator();
+				
+				if ui.small_button("Clear").clicked() {
+					kd.output.clear();
+				}
+			});
+			
+			ui.separator();
+			
+			egui::ScrollArea::vertical()
+				.auto_shrink([false, true])
+				.max_height(160.0)
+				.stick_to_bottom(true)
+				.show(ui, |ui| {
+				ui.add(
+					egui::Label::new(egui::RichText::new(&kd.output).monospace())
+						.wrap(true)
+				);
+			});
+			
+			ui.separator();
+			
+			ui.horizontal(|ui| {
+				let response = ui.add(
+					egui::TextEdit::singleline(&mut self.input)
+						.font(egui::TextStyle::Monospace)
+						.hint_text("Type here...")
+						.desired_width(160.0)
+				);
+				
+				// single line edits lose focus on enter, so that's when it's sent
+				if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+					self.input.push('\n');
+					response.request_focus();
+				}
+				
+				if !self.input.is_empty() {
+					kd.push_str(&self.input);
+					self.input.clear();
+				}
+				
+				let waiting = kd.keys_waiting();
+				if waiting > 0 {
+					ui.weak(format!("{waiting} keys waiting"));
+				}
+			});
+		});
+	}
+}
@@ -26,10 +26,14 @@ pub(super) enum MemoryInterpretation {
 }
 impl MemoryInterpretation {
 	// would like a "str_to_bytes(&self, &str) -> Vec<u8>" kinda thing
-	fn str_to_bytes(&self, a: &str) -> Result<Vec<u8>, &'static str> {
+	fn str_to_bytes(&self, a: &str, addr: u32) -> Result<Vec<u8>, Vec<mips_assembler::Error>> {
 		use MemoryInterpretation::*;
 		match self {
-			Instruction => Ok(Cpu::from_assembly(a)?.to_le_bytes().to_vec()),
+			Instruction => {
+				let bases = mips_assembler::Bases { text: addr, ..Default::default() };
+				let program = mips_assembler::assemble(a, bases)?;
+				Ok(program.image(map::TEXT).map(|(_, bytes)| bytes).unwrap_or_default())
+			},
 			Text => Ok(a.as_bytes().to_vec()),
 			// _ => Err("unimplemented"),
 		}
@@ -48,9 +52,11 @@ fn replace_control_char(c: char) -> char {
 }
 
 impl MemoryWindowState {
-	pub(super) fn show(&mut self, core: &mut Core, mem: &mut Memory, ctx: &egui::Context) {
+	pub(super) fn show(&mut self, core: &mut Core, ctx: &egui::Context) {
 		use MemoryPosition::*;
 		use MemoryInterpretation::*;
+		
+		let mem = &mut core.mem;
 		// https://github.com/emilk/egui/blob/master/egui_demo_lib/src/demo/scrolling.rs
 		// https://github.com/emilk/egui/blob/master/egui_demo_lib/src/demo/mod.rs
 		
@@ -61,7 +67,7 @@ impl MemoryWindowState {
 				match self.look {
 					ProgramCounter => (core.inner.pc >> 2).saturating_sub(3) << 2,
 					LastException => (core.inner.cp0[Cp0Register::ExPC] >> 2).saturating_sub(3) << 2,
-					Position(p) => p,
+					Position(_) | Segment(_) => self.look.resolve(mem).unwrap_or(0),
 				}
 			};
 			
@@ -76,12 +82,25 @@ impl MemoryWindowState {
 					ui.weak("Jump to...");
 					ui.horizontal_wrapped(|ui| {
 						ui.selectable_value(&mut self.look, ProgramCounter, "PC");
-						ui.selectable_value(&mut self.look, Position(0x00_0000), ".text");
-						ui.selectable_value(&mut self.look, Position(0x00_2000), ".data");
-						ui.selectable_value(&mut self.look, Position(0x01_0000), "MMIO");
+						for seg in &mem.map.segments {
+							ui.selectable_value(&mut self.look, Segment(seg.name), seg.name)
+								.on_hover_text(format!(
+									"{:#010X}..={:#010X} ({})",
+									seg.range.start(), seg.range.end(), seg.perms
+								));
+						}
 						ui.selectable_value(&mut self.look, LastException, "Exception");
 					});
 					
+					ui.weak("Device registers...");
+					ui.horizontal_wrapped(|ui| {
+						for device in mem.devices() {
+							let base = *device.range().start();
+							ui.selectable_value(&mut self.look, Position(base), device.name())
+								.on_hover_text(format!("{base:#010X}"));
+						}
+					});
+					
 					ui.separator();
 					
 					ui.label("Use the radio buttons at the top-left of the window to select which CPU core to focus on.");
@@ -99,7 +118,7 @@ impl MemoryWindowState {
 				// _ => Cpu::INSTRUCTION_BYTES,
 			};
 			
-			let row_num = mips_emulator::mem::MEMORY_SIZE / row_eat;
+			let row_num = (mips_emulator::mem::MEMORY_SIZE / row_eat as u64) as usize;
 			
 			fn v_divider(ui: &mut egui::Ui) -> egui::Response {
 				ui.add_sized([4.0, ui.available_height()], egui::Separator::default().spacing(0.0).vertical())
@@ -114,7 +133,7 @@ impl MemoryWindowState {
 				Text => "MemoryText",
 				// _ => "MemoryBytes",
 			})
-				.num_columns(7)
+				.num_columns(8)
 				.min_col_width(1.0)
 				.show(ui,
 			|ui| {
@@ -160,29 +179,31 @@ impl MemoryWindowState {
 					}
 					
 					ui.monospace(format!("{addr:#010X}"));
+					// where it is, like `main+0x8`, if anything says
+					ui.label(egui::RichText::new(mem.symbols.describe(addr).unwrap_or_default()).monospace().weak());
 					
 					v_divider(ui);
 					
-					if let Some(bytes) = mem.get_slice(addr, row_eat) {
-						ui.horizontal(|ui| {
-							for b in bytes {
-								ui.monospace(format!("{b:02X}"));
-							}
-							// ui.monospace(bytes.iter().fold(
-							// 	String::with_capacity(bytes.len() * 3),
-							// 	|d, b| d + &format!("{b:02X} ")
-							// ).trim_end());
-						});
-					} else {
-						ui.label("Page Fault :)");
-					}
+					// unallocated pages are dimmed, so you can tell them
+					// apart from zeroes that were actually written.
+					let allocated = mem.is_allocated(addr);
+					ui.horizontal(|ui| {
+						for b in mem.bytes(addr, row_eat) {
+							let b = egui::RichText::new(format!("{b:02X}")).monospace();
+							ui.label(if allocated { b } else { b.weak() });
+						}
+						// ui.monospace(bytes.iter().fold(
+						// 	String::with_capacity(bytes.len() * 3),
+						// 	|d, b| d + &format!("{b:02X} ")
+						// ).trim_end());
+					});
 					
 					v_divider(ui);
 					
 					if let Some((e_addr, e_text)) = &mut self.edit {
 						if addr == *e_addr {
 							if ui.text_edit_singleline(e_text).lost_focus() {
-								if let Ok(b) = self.interp.str_to_bytes(e_text) {
+								if let Ok(b) = self.interp.str_to_bytes(e_text, *e_addr) {
 									mem.set_slice(*e_addr, &b);
 								}
 								self.edit = None;
@@ -204,10 +225,9 @@ impl MemoryWindowState {
 							}
 						},
 						Text => {
-							let bytes = mem.get_slice(addr, TEXT_WIDTH)
-								.unwrap_or(&[0u8; TEXT_WIDTH]);
+							let bytes = mem.copy_to_vec(addr, TEXT_WIDTH);
 							
-							let text = String::from_utf8_lossy(bytes)
+							let text = String::from_utf8_lossy(&bytes)
 								.into_owned();
 							
 							let text = text.chars()
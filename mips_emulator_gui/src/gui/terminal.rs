@@ -0,0 +1,91 @@
+use super::*;
+
+use mips_emulator::mmio::Uart;
+
+/// A serial terminal, on the other end of the focused core's UART.
+#[derive(Default)]
+pub(super) struct TerminalWindowState {
+	input: String,
+	
+	/// Send `\r\n` for enter instead of just `\n`, like most terminals do.
+	crlf: bool,
+}
+
+/// What the program sent, cleaned up to show. Carriage returns go, and
+/// backspaces take the last character with them.
+fn screen(output: &[u8]) -> String {
+	let mut s = String::new();
+	for c in String::from_utf8_lossy(output).chars() {
+		match c {
+			'\r' => (),
+			'\x08' => { s.pop(); },
+			c => s.push(c),
+		}
+	}
+	s
+}
+
+impl TerminalWindowState {
+	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
+		egui::Window::new("Serial Terminal").show(ctx, |ui| {
+			let Some(uart) = mem.device_mut::<Uart>() else {
+				ui.weak("This core's memory doesn't have a UART hooked up.");
+				return;
+			};
+			
+			ui.horizontal(|ui| {
+				ui.monospace(format!("{:#010X}", uart.base()));
+				
+				ui.separator();
+				
+				ui.checkbox(&mut self.crlf, "Send CR LF");
+				
+				ui.separator();
+				
+				if ui.small_button("Clear").clicked() {
+					uart.output.clear();
+				}
+			});
+			
+			ui.separator();
+			
+			egui::ScrollArea::vertical()
+				.auto_shrink([false, true])
+				.max_height(240.0)
+				.stick_to_bottom(true)
+				.show(ui, |ui| {
+				ui.add(
+					egui::Label::new(egui::RichText::new(screen(&uart.output)).monospace())
+						.wrap(true)
+				);
+			});
+			
+			ui.separator();
+			
+			ui.horizontal(|ui| {
+				let response = ui.add(
+					egui::TextEdit::singleline(&mut self.input)
+						.font(egui::TextStyle::Monospace)
+						.hint_text("Type here...")
+						.desired_width(240.0)
+				);
+				
+				// same trick as the keyboard window
+				if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+					self.input.push_str(if self.crlf { "\r\n" } else { "\n" });
+					response.request_focus();
+				}
+				
+				if !self.input.is_empty() {
+					uart.send(self.input.as_bytes());
+					self.input.clear();
+				}
+				
+				let waiting = uart.bytes_waiting();
+				if waiting > 0 {
+					ui.weak(format!("{waiting} bytes waiting"));
+				}
+			});
+		});
+	}
+}
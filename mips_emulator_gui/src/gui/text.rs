@@ -0,0 +1,114 @@
+use super::*;
+
+use mips_emulator::mmio::TextDisplay;
+
+/// Shows the focused core's text display, in Iosevka.
+pub(super) struct TextScreen {
+	font_size: f32,
+}
+impl Default for TextScreen {
+	fn default() -> Self {
+		TextScreen { font_size: 12.0 }
+	}
+}
+
+fn color(i: u8) -> egui::Color32 {
+	let [b, g, r, _] = TextDisplay::PALETTE[i as usize & 0xF].to_le_bytes();
+	egui::Color32::from_rgb(r, g, b)
+}
+
+impl TextScreen {
+	pub(super) fn show(&mut self, mem: &mut Memory, ctx: &egui::Context) {
+		egui::Window::new("Text Display").show(ctx, |ui| {
+			let segments: Vec<_> = mem.map.segments.iter()
+				.map(|s| (s.name, s.base()))
+				.collect();
+			
+			let Some(td) = mem.device_mut::<TextDisplay>() else {
+				ui.weak("This core's memory doesn't have a text display hooked up.");
+				return;
+			};
+			
+			ui.horizontal(|ui| {
+				ui.menu_button("View", |ui| {
+					ui.horizontal(|ui| {
+						ui.label("Cells:");
+						ui.add(
+							egui::DragValue::new(&mut td.columns)
+								.clamp_range(1..=256)
+								.speed(0.125)
+						);
+						ui.label("×");
+						ui.add(
+							egui::DragValue::new(&mut td.rows)
+								.clamp_range(1..=128)
+								.speed(0.125)
+						);
+						
+						ui.separator();
+						
+						ui.label("Font:");
+						ui.add(
+							egui::DragValue::new(&mut self.font_size)
+								.max_decimals(0)
+								.clamp_range(6..=48)
+								.speed(0.125)
+								.suffix("pt")
+						);
+					});
+					
+					ui.separator();
+					
+					ui.horizontal(|ui| {
+						for &(name, base) in &segments {
+							ui.selectable_value(&mut td.buffer, base, name);
+						}
+					});
+				});
+				
+				ui.monospace(format!("{:#010X}", td.buffer));
+				ui.weak(format!("cursor at {}, {}", td.cursor.0, td.cursor.1));
+			});
+			
+			ui.separator();
+			
+			let td = mem.device::<TextDisplay>().unwrap();
+			let cells = td.cells(mem);
+			let (columns, rows) = (td.columns, td.rows);
+			
+			let font = egui::FontId::monospace(self.font_size);
+			let cell_size = ui.fonts(|f| egui::vec2(f.glyph_width(&font, 'M'), f.row_height(&font)));
+			
+			let (rect, _) = ui.allocate_exact_size(
+				cell_size * egui::vec2(columns as f32, rows as f32),
+				egui::Sense::hover()
+			);
+			if !ui.is_rect_visible(rect) { return; }
+			
+			let painter = ui.painter_at(rect);
+			let origin = rect.left_top().round();
+			
+			for (i, cell) in cells.iter().enumerate() {
+				let pos = origin + cell_size * egui::vec2((i % columns) as f32, (i / columns) as f32);
+				painter.rect_filled(egui::Rect::from_min_size(pos, cell_size), 0.0, color(cell.bg));
+				
+				if !matches!(cell.ch, '\0' | ' ') {
+					painter.text(pos, egui::Align2::LEFT_TOP, cell.ch, font.clone(), color(cell.fg));
+				}
+			}
+			
+			// a blinking underline, like the real thing
+			let (col, row) = (td.cursor.0 as usize, td.cursor.1 as usize);
+			let blink = ui.input(|i| i.time).fract() < 0.5;
+			if td.show_cursor && col < columns && row < rows {
+				if blink {
+					let cell = cells[row * columns + col];
+					let pos = origin + cell_size * egui::vec2(col as f32, row as f32 + 1.0);
+					let underline = egui::Rect::from_min_max(pos - egui::vec2(0.0, 2.0), pos + egui::vec2(cell_size.x, 0.0));
+					painter.rect_filled(underline, 0.0, color(cell.fg));
+				}
+				ctx.request_repaint_after(std::time::Duration::from_millis(250));
+			}
+		});
+	}
+}
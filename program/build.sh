@@ -6,12 +6,17 @@ JAVA_EXEC_PATH="java"
 MARS_JAR_PATH="$HOME/Downloads/mars/Mars4_5.jar"
 # Adjust these variables accordingly.
 
+# Which of MARS' memory configurations to assemble for. Pick the same one in
+# the emulator's "Memory Layout" menu.
+# (Default, CompactDataAtZero, or CompactTextAtZero)
+MEMORY_CONFIG="${2:-CompactTextAtZero}"
+
 "$JAVA_EXEC_PATH" -jar "$MARS_JAR_PATH" \
 	nc \
 	a ae1 \
-	mc CompactTextAtZero \
+	mc "$MEMORY_CONFIG" \
 	dump .text Binary out.text.bin \
 	dump .data Binary out.data.bin \
 	"$1"
 
-# ( cd program; ./build.sh bitmap_example_simple.asm ) && cargo run --release
+# ( cd program; ./build.sh bitmap_example_simple.asm [memory config] ) && cargo run --release
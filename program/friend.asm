@@ -1,4 +1,4 @@
-# cargo -q run --example mips_assembler -- program/friend.asm program/friend.text.bin
+# cargo -q run --example mips_assembler -- program/friend.asm program/friend
 .text # <-- doesn't do anything yet.
 # todo: strip directives (like .text) or better yet, parse them
 	addi $t0, $t0, 1